@@ -0,0 +1,118 @@
+//! Fires concurrent requests at a running server and reports latency percentiles.
+//!
+//! Used to compare handler changes under load, e.g. before and after moving
+//! Diesel calls onto the blocking pool:
+//!
+//! ```sh
+//! cargo run --release --example load_test -- http://localhost:8080/ingredients 64 2000
+//! LOAD_TEST_TOKEN=$(LINE_CHANNEL_ID=local cargo run --bin kidney-admin -- line-test-token U123) \
+//!     cargo run --release --example load_test -- http://localhost:8080/get_meal_plan 64 2000 '{}'
+//! ```
+//!
+//! Arguments: URL, concurrency (default 32), total requests (default 1000) and an
+//! optional JSON body, which switches the request to POST. `LOAD_TEST_TOKEN`, when
+//! set, is sent as the bearer token.
+//!
+//! Measured on one CPU with a local Postgres, release builds of the commit before
+//! the blocking pool and of the current tree. While a session held
+//! `LOCK TABLE ingredients IN ACCESS EXCLUSIVE MODE` for 3s and 64 clients called
+//! `/ingredients` (640 requests), `load_test http://localhost:8080/ 4 400` saw:
+//!
+//! | | `/` p50 | `/` p99 | `/` max | wall time |
+//! | --- | --- | --- | --- | --- |
+//! | Diesel on the runtime threads | 0.12ms | 6.5ms | 2.39s | 2.46s |
+//! | Diesel on the blocking pool | 0.29ms | 2.1ms | 4.1ms | 34ms |
+//!
+//! With nothing blocking, `/ingredients 64 2000` costs more on the blocking pool
+//! (p50 9-11ms against 5-6ms, 5.4-5.8k against 10.7-11k req/s on the same machine):
+//! the hop to a blocking thread is the price of not stalling every other request
+//! behind a slow query.
+
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(url) = args.first().cloned() else {
+        eprintln!("Usage: load_test <url> [concurrency] [requests] [json body]");
+        std::process::exit(2);
+    };
+    let concurrency: usize = args.get(1).and_then(|v| v.parse().ok()).unwrap_or(32);
+    let total: usize = args.get(2).and_then(|v| v.parse().ok()).unwrap_or(1000);
+    let body: Option<serde_json::Value> = args.get(3).map(|v| {
+        serde_json::from_str(v).unwrap_or_else(|err| {
+            eprintln!("Invalid JSON body: {}", err);
+            std::process::exit(2);
+        })
+    });
+
+    let token = env::var("LOAD_TEST_TOKEN").ok();
+
+    let client = reqwest::Client::new();
+    let remaining = Arc::new(Mutex::new(total));
+    let latencies = Arc::new(Mutex::new(Vec::with_capacity(total)));
+    let failures = Arc::new(Mutex::new(0usize));
+
+    let started = Instant::now();
+    let workers: Vec<_> = (0..concurrency)
+        .map(|_| {
+            let (client, url, body, token) = (client.clone(), url.clone(), body.clone(), token.clone());
+            let (remaining, latencies, failures) = (remaining.clone(), latencies.clone(), failures.clone());
+            tokio::spawn(async move {
+                loop {
+                    {
+                        let mut remaining = remaining.lock().await;
+                        if *remaining == 0 {
+                            break;
+                        }
+                        *remaining -= 1;
+                    }
+
+                    let mut request = match &body {
+                        Some(body) => client.post(&url).json(body),
+                        None => client.get(&url),
+                    };
+                    if let Some(token) = &token {
+                        request = request.bearer_auth(token);
+                    }
+
+                    let sent = Instant::now();
+                    let ok = matches!(request.send().await, Ok(response) if response.status().is_success());
+                    let elapsed = sent.elapsed();
+
+                    latencies.lock().await.push(elapsed);
+                    if !ok {
+                        *failures.lock().await += 1;
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        let _ = worker.await;
+    }
+    let wall = started.elapsed();
+
+    let mut latencies = latencies.lock().await.clone();
+    latencies.sort();
+    let percentile = |p: f64| -> Duration {
+        if latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let index = ((latencies.len() as f64 * p).ceil() as usize).clamp(1, latencies.len()) - 1;
+        latencies[index]
+    };
+
+    println!("requests:    {} ({} failed)", latencies.len(), failures.lock().await);
+    println!("concurrency: {}", concurrency);
+    println!("wall time:   {:.2?}", wall);
+    println!("throughput:  {:.1} req/s", latencies.len() as f64 / wall.as_secs_f64());
+    println!("p50:         {:.2?}", percentile(0.50));
+    println!("p95:         {:.2?}", percentile(0.95));
+    println!("p99:         {:.2?}", percentile(0.99));
+    println!("max:         {:.2?}", latencies.last().copied().unwrap_or_default());
+}
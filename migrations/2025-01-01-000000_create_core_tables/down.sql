@@ -0,0 +1,19 @@
+DROP TABLE users_nutrients_limit_per_day;
+DROP TABLE users_ingredient_allergies;
+DROP TABLE users_food_condition_types;
+DROP TABLE users_diseases;
+DROP TABLE user_take_medicines;
+DROP TABLE user_nutrient_tracking;
+DROP TABLE user_medicines;
+DROP TABLE user_calorie_tracking;
+DROP TABLE recipes_nutrients;
+DROP TABLE recipes_ingredients;
+DROP TABLE recipes_ingredient_allergies;
+DROP TABLE recipes;
+DROP TABLE nutrients;
+DROP TABLE ingredients;
+DROP TABLE ingredient_allergies;
+DROP TABLE food_condition_types;
+DROP TABLE disease;
+DROP TABLE users;
+DROP TABLE admins;
@@ -0,0 +1,139 @@
+-- Users, their health profile, the recipe catalog and nutrient tracking.
+-- Foreign keys follow the `joinable!` declarations in src/schema.rs.
+-- These tables predate the migrations, so existing ones are left as they are.
+
+CREATE TABLE IF NOT EXISTS admins (
+    admin_email TEXT PRIMARY KEY,
+    admin_password UUID NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    user_id SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    birthdate TIMESTAMP NOT NULL,
+    weight DOUBLE PRECISION NOT NULL,
+    height DOUBLE PRECISION NOT NULL,
+    profile_img_link VARCHAR(255),
+    user_line_id TEXT,
+    gender VARCHAR(50),
+    kidney_level INTEGER,
+    kidney_dialysis BOOLEAN
+);
+
+CREATE TABLE IF NOT EXISTS disease (
+    disease_id SERIAL PRIMARY KEY,
+    disease_name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS food_condition_types (
+    food_condition_type_id SERIAL PRIMARY KEY,
+    food_condition_type_name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ingredient_allergies (
+    ingredient_allergy_id SERIAL PRIMARY KEY,
+    ingredient_allergy_name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ingredients (
+    ingredient_id SERIAL PRIMARY KEY,
+    ingredient_name VARCHAR(150) NOT NULL,
+    ingredient_name_eng TEXT
+);
+
+CREATE TABLE IF NOT EXISTS nutrients (
+    nutrient_id SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    unit VARCHAR(50) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS recipes (
+    recipe_id SERIAL PRIMARY KEY,
+    recipe_name VARCHAR(150) NOT NULL,
+    recipe_method TEXT[],
+    calories DOUBLE PRECISION NOT NULL,
+    calories_unit VARCHAR(50) NOT NULL,
+    recipe_img_link TEXT[],
+    food_category TEXT[] NOT NULL,
+    dish_type TEXT[]
+);
+
+CREATE TABLE IF NOT EXISTS recipes_ingredient_allergies (
+    recipe_id INTEGER NOT NULL REFERENCES recipes (recipe_id),
+    ingredient_allergy_id INTEGER NOT NULL REFERENCES ingredient_allergies (ingredient_allergy_id),
+    PRIMARY KEY (recipe_id, ingredient_allergy_id)
+);
+
+CREATE TABLE IF NOT EXISTS recipes_ingredients (
+    recipes_ingredients_id SERIAL PRIMARY KEY,
+    recipe_id INTEGER NOT NULL,
+    ingredient_id INTEGER NOT NULL REFERENCES ingredients (ingredient_id),
+    amount INTEGER NOT NULL,
+    ingredient_unit VARCHAR(50) NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS recipes_nutrients (
+    recipe_nutrient_id SERIAL PRIMARY KEY,
+    recipe_id INTEGER NOT NULL,
+    nutrient_id INTEGER NOT NULL REFERENCES nutrients (nutrient_id),
+    quantity DOUBLE PRECISION NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS user_calorie_tracking (
+    tracking_id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    date DATE NOT NULL,
+    calories DOUBLE PRECISION NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS user_medicines (
+    user_medicine_id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    medicine_per_times DOUBLE PRECISION NOT NULL,
+    user_medicine_img_link TEXT[],
+    medicine_unit VARCHAR(50),
+    medicine_name TEXT,
+    medicine_note TEXT,
+    medicine_schedule TIMESTAMP[],
+    medicine_amount INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS user_nutrient_tracking (
+    tracking_id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    nutrient_id INTEGER NOT NULL REFERENCES nutrients (nutrient_id),
+    date DATE NOT NULL,
+    quantity DOUBLE PRECISION NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS user_take_medicines (
+    user_take_medicines_id SERIAL PRIMARY KEY,
+    user_id INTEGER REFERENCES users (user_id),
+    user_medicine_id INTEGER REFERENCES user_medicines (user_medicine_id),
+    user_take_medicine_time DATE
+);
+
+CREATE TABLE IF NOT EXISTS users_diseases (
+    users_diseases_id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    disease_id INTEGER NOT NULL REFERENCES disease (disease_id)
+);
+
+CREATE TABLE IF NOT EXISTS users_food_condition_types (
+    users_food_condition_types_id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    food_condition_type_id INTEGER NOT NULL REFERENCES food_condition_types (food_condition_type_id)
+);
+
+CREATE TABLE IF NOT EXISTS users_ingredient_allergies (
+    users_ingredient_allergies_id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    ingredient_allergy_id INTEGER NOT NULL REFERENCES ingredient_allergies (ingredient_allergy_id)
+);
+
+CREATE TABLE IF NOT EXISTS users_nutrients_limit_per_day (
+    users_nutrients_limit_per_day_id SERIAL PRIMARY KEY,
+    user_id INTEGER REFERENCES users (user_id),
+    nutrient_id INTEGER REFERENCES nutrients (nutrient_id),
+    nutrient_limit DOUBLE PRECISION
+);
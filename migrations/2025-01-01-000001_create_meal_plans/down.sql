@@ -0,0 +1,2 @@
+DROP TABLE meal_plan_recipes;
+DROP TABLE meal_plans;
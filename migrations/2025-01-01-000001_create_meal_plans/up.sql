@@ -0,0 +1,16 @@
+-- Predates the migrations like the core tables; existing tables are left as they are.
+
+CREATE TABLE IF NOT EXISTS meal_plans (
+    meal_plan_id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    name VARCHAR(100) NOT NULL,
+    date DATE NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS meal_plan_recipes (
+    meal_plan_recipe_id SERIAL PRIMARY KEY,
+    meal_plan_id INTEGER NOT NULL REFERENCES meal_plans (meal_plan_id),
+    recipe_id INTEGER NOT NULL,
+    ischecked BOOLEAN,
+    meal_time INTEGER
+);
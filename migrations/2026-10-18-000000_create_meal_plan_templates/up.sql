@@ -0,0 +1,17 @@
+CREATE TABLE meal_plan_templates (
+    template_id SERIAL PRIMARY KEY,
+    name VARCHAR(100) NOT NULL,
+    created_by TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- day_index is the offset from the day the template is applied on
+CREATE TABLE meal_plan_template_recipes (
+    template_recipe_id SERIAL PRIMARY KEY,
+    template_id INTEGER NOT NULL REFERENCES meal_plan_templates (template_id),
+    day_index INTEGER NOT NULL,
+    recipe_id INTEGER NOT NULL REFERENCES recipes (recipe_id),
+    meal_time INTEGER
+);
+
+CREATE INDEX meal_plan_template_recipes_template_id_idx ON meal_plan_template_recipes (template_id);
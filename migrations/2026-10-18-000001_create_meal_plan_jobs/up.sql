@@ -0,0 +1,15 @@
+CREATE TABLE meal_plan_jobs (
+    job_id UUID PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (user_id),
+    kind VARCHAR(20) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    request JSONB NOT NULL,
+    result JSONB,
+    error TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Workers pick up unfinished jobs in submission order on startup
+CREATE INDEX meal_plan_jobs_status_created_at_idx ON meal_plan_jobs (status, created_at);
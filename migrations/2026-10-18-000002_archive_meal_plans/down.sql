@@ -0,0 +1,2 @@
+DROP INDEX meal_plans_user_id_date_active_idx;
+ALTER TABLE meal_plans DROP COLUMN archived_at;
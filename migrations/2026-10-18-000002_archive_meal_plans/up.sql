@@ -0,0 +1,4 @@
+ALTER TABLE meal_plans ADD COLUMN archived_at TIMESTAMP;
+
+-- A user has at most one active meal plan per day; archived days are kept as history
+CREATE UNIQUE INDEX meal_plans_user_id_date_active_idx ON meal_plans (user_id, date) WHERE archived_at IS NULL;
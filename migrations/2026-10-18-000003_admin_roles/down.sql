@@ -0,0 +1,4 @@
+ALTER TABLE admins DROP COLUMN role;
+ALTER TABLE admins DROP COLUMN password_hash;
+ALTER TABLE admins ADD COLUMN admin_password UUID NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE admins ALTER COLUMN admin_password DROP DEFAULT;
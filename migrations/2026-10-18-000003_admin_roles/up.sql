@@ -0,0 +1,7 @@
+-- Admin passwords were stored as UUIDs that cannot be verified safely. They are
+-- replaced by argon2 hashes; existing admins have no hash until one is set with
+-- `kidney-admin create-admin`, and cannot log in before that.
+ALTER TABLE admins DROP COLUMN admin_password;
+ALTER TABLE admins ADD COLUMN password_hash TEXT;
+ALTER TABLE admins ADD COLUMN role VARCHAR(20) NOT NULL DEFAULT 'dietitian'
+    CHECK (role IN ('dietitian', 'superadmin'));
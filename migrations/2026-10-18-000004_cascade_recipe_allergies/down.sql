@@ -0,0 +1,4 @@
+ALTER TABLE recipes_ingredient_allergies DROP CONSTRAINT recipes_ingredient_allergies_recipe_id_fkey;
+ALTER TABLE recipes_ingredient_allergies
+    ADD CONSTRAINT recipes_ingredient_allergies_recipe_id_fkey
+    FOREIGN KEY (recipe_id) REFERENCES recipes (recipe_id);
@@ -0,0 +1,5 @@
+-- A recipe's allergens are derived from its ingredients, so they go with the recipe.
+ALTER TABLE recipes_ingredient_allergies DROP CONSTRAINT IF EXISTS recipes_ingredient_allergies_recipe_id_fkey;
+ALTER TABLE recipes_ingredient_allergies
+    ADD CONSTRAINT recipes_ingredient_allergies_recipe_id_fkey
+    FOREIGN KEY (recipe_id) REFERENCES recipes (recipe_id) ON DELETE CASCADE;
@@ -0,0 +1,264 @@
+//! Data maintenance used by the `kidney-admin` binary.
+//!
+//! These replace the ad hoc SQL we used to run by hand: seeding the reference
+//! tables a fresh database needs and rebuilding tables that are derived from
+//! others. Every function is idempotent and runs in a single transaction.
+
+use crate::schema::{
+    disease, food_condition_types, ingredient_allergies, ingredients, meal_plan_recipes, meal_plans, nutrients,
+    recipes, recipes_ingredient_allergies, recipes_ingredients, recipes_nutrients, user_calorie_tracking,
+    user_nutrient_tracking,
+};
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Nutrients in the order of the ids the recommender and planner rely on (1 = calories ... 7 = sodium).
+pub const NUTRIENTS: [(i32, &str, &str); 7] = [
+    (1, "calories", "kcal"),
+    (2, "carbs", "g"),
+    (3, "fat", "g"),
+    (4, "phosphorus", "mg"),
+    (5, "potassium", "mg"),
+    (6, "protein", "g"),
+    (7, "sodium", "mg"),
+];
+
+pub const INGREDIENT_ALLERGIES: [&str; 9] = [
+    "milk", "egg", "fish", "shellfish", "peanut", "tree nut", "wheat", "soy", "sesame",
+];
+
+pub const DISEASES: [&str; 5] = ["diabetes", "hypertension", "heart disease", "gout", "dyslipidemia"];
+
+pub const FOOD_CONDITION_TYPES: [&str; 5] = [
+    "low sodium",
+    "low potassium",
+    "low phosphorus",
+    "low protein",
+    "fluid restriction",
+];
+
+/// Rows added per table by [`seed_reference_data`]; rows that already existed are not counted.
+#[derive(Serialize, Debug, Default)]
+pub struct SeedReport {
+    pub nutrients: usize,
+    pub ingredient_allergies: usize,
+    pub diseases: usize,
+    pub food_condition_types: usize,
+}
+
+/// Inserts the reference rows that are missing. Nutrients keep their fixed ids; the
+/// other tables are matched by name, so renamed rows are left alone.
+pub fn seed_reference_data(conn: &mut PgConnection) -> QueryResult<SeedReport> {
+    conn.transaction(|conn| {
+        let mut report = SeedReport::default();
+
+        for (id, name, unit) in NUTRIENTS {
+            report.nutrients += diesel::insert_into(nutrients::table)
+                .values((nutrients::nutrient_id.eq(id), nutrients::name.eq(name), nutrients::unit.eq(unit)))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+        // Explicit ids do not advance the serial, so move it past them
+        diesel::sql_query(
+            "SELECT setval(pg_get_serial_sequence('nutrients', 'nutrient_id'), (SELECT MAX(nutrient_id) FROM nutrients))",
+        )
+        .execute(conn)?;
+
+        let existing: Vec<String> = ingredient_allergies::table
+            .select(ingredient_allergies::ingredient_allergy_name)
+            .load(conn)?;
+        for name in INGREDIENT_ALLERGIES.iter().filter(|name| !existing.iter().any(|e| e == *name)) {
+            report.ingredient_allergies += diesel::insert_into(ingredient_allergies::table)
+                .values(ingredient_allergies::ingredient_allergy_name.eq(name))
+                .execute(conn)?;
+        }
+
+        let existing: Vec<String> = disease::table.select(disease::disease_name).load(conn)?;
+        for name in DISEASES.iter().filter(|name| !existing.iter().any(|e| e == *name)) {
+            report.diseases += diesel::insert_into(disease::table)
+                .values(disease::disease_name.eq(name))
+                .execute(conn)?;
+        }
+
+        let existing: Vec<String> = food_condition_types::table
+            .select(food_condition_types::food_condition_type_name)
+            .load(conn)?;
+        for name in FOOD_CONDITION_TYPES.iter().filter(|name| !existing.iter().any(|e| e == *name)) {
+            report.food_condition_types += diesel::insert_into(food_condition_types::table)
+                .values(food_condition_types::food_condition_type_name.eq(name))
+                .execute(conn)?;
+        }
+
+        Ok(report)
+    })
+}
+
+/// Rebuilds `recipes_ingredient_allergies` from the recipes' ingredients.
+///
+/// A recipe contains an allergen when one of its ingredients has the allergen's
+/// name in its Thai or English name, case-insensitively ("peanut" matches
+/// "Peanut butter"). Returns how many links were written.
+pub fn recompute_recipe_allergens(conn: &mut PgConnection) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        let allergies: Vec<(i32, String)> = ingredient_allergies::table
+            .select((ingredient_allergies::ingredient_allergy_id, ingredient_allergies::ingredient_allergy_name))
+            .load(conn)?;
+
+        let recipe_ingredients: Vec<(i32, String, Option<String>)> = recipes_ingredients::table
+            .inner_join(ingredients::table)
+            .select((
+                recipes_ingredients::recipe_id,
+                ingredients::ingredient_name,
+                ingredients::ingredient_name_eng,
+            ))
+            .load(conn)?;
+
+        let links = allergen_links(&allergies, &recipe_ingredients);
+
+        diesel::delete(recipes_ingredient_allergies::table).execute(conn)?;
+        let rows: Vec<_> = links
+            .iter()
+            .map(|(recipe_id, allergy_id)| {
+                (
+                    recipes_ingredient_allergies::recipe_id.eq(recipe_id),
+                    recipes_ingredient_allergies::ingredient_allergy_id.eq(allergy_id),
+                )
+            })
+            .collect();
+        diesel::insert_into(recipes_ingredient_allergies::table)
+            .values(&rows)
+            .execute(conn)
+    })
+}
+
+/// `(recipe_id, allergy_id)` for every recipe with an ingredient naming the allergen, sorted and
+/// without duplicates. `recipe_ingredients` holds each ingredient's Thai and English name.
+fn allergen_links(allergies: &[(i32, String)], recipe_ingredients: &[(i32, String, Option<String>)]) -> Vec<(i32, i32)> {
+    let mut links: Vec<(i32, i32)> = Vec::new();
+    for (recipe_id, name, name_eng) in recipe_ingredients {
+        let names = [Some(name.to_lowercase()), name_eng.as_ref().map(|n| n.to_lowercase())];
+        for (allergy_id, allergy_name) in allergies {
+            let allergy_name = allergy_name.to_lowercase();
+            if names.iter().flatten().any(|n| n.contains(&allergy_name)) {
+                links.push((*recipe_id, *allergy_id));
+            }
+        }
+    }
+    links.sort_unstable();
+    links.dedup();
+    links
+}
+
+/// Rows written by [`recompute_nutrient_tracking`].
+#[derive(Serialize, Debug, Default)]
+pub struct TrackingReport {
+    pub nutrient_rows: usize,
+    pub calorie_rows: usize,
+}
+
+/// Rebuilds `user_nutrient_tracking` and `user_calorie_tracking` in `[from, to]` from the
+/// recipes users checked off on their active meal plans. Missing bounds default to the
+/// first and last meal plan dates.
+pub fn recompute_nutrient_tracking(
+    conn: &mut PgConnection,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> QueryResult<TrackingReport> {
+    conn.transaction(|conn| {
+        let (first, last): (Option<NaiveDate>, Option<NaiveDate>) = meal_plans::table
+            .select((diesel::dsl::min(meal_plans::date), diesel::dsl::max(meal_plans::date)))
+            .first(conn)?;
+        let (Some(from), Some(to)) = (from.or(first), to.or(last)) else {
+            return Ok(TrackingReport::default()); // No meal plans, nothing to derive from
+        };
+
+        let eaten = meal_plans::table
+            .inner_join(meal_plan_recipes::table)
+            .filter(meal_plans::archived_at.is_null())
+            .filter(meal_plans::date.between(from, to))
+            .filter(meal_plan_recipes::ischecked.eq(true));
+
+        // Summed in Rust: the group by would span columns of three tables
+        let mut nutrient_totals: BTreeMap<(i32, i32, NaiveDate), f64> = BTreeMap::new();
+        for (user_id, nutrient_id, date, quantity) in eaten
+            .inner_join(recipes_nutrients::table.on(meal_plan_recipes::recipe_id.eq(recipes_nutrients::recipe_id)))
+            .select((meal_plans::user_id, recipes_nutrients::nutrient_id, meal_plans::date, recipes_nutrients::quantity))
+            .load::<(i32, i32, NaiveDate, f64)>(conn)?
+        {
+            *nutrient_totals.entry((user_id, nutrient_id, date)).or_default() += quantity;
+        }
+
+        let mut calorie_totals: BTreeMap<(i32, NaiveDate), f64> = BTreeMap::new();
+        for (user_id, date, calories) in eaten
+            .inner_join(recipes::table.on(meal_plan_recipes::recipe_id.eq(recipes::recipe_id)))
+            .select((meal_plans::user_id, meal_plans::date, recipes::calories))
+            .load::<(i32, NaiveDate, f64)>(conn)?
+        {
+            *calorie_totals.entry((user_id, date)).or_default() += calories;
+        }
+
+        diesel::delete(user_nutrient_tracking::table.filter(user_nutrient_tracking::date.between(from, to)))
+            .execute(conn)?;
+        diesel::delete(user_calorie_tracking::table.filter(user_calorie_tracking::date.between(from, to)))
+            .execute(conn)?;
+
+        let nutrient_rows: Vec<_> = nutrient_totals
+            .into_iter()
+            .map(|((user_id, nutrient_id, date), quantity)| {
+                (
+                    user_nutrient_tracking::user_id.eq(user_id),
+                    user_nutrient_tracking::nutrient_id.eq(nutrient_id),
+                    user_nutrient_tracking::date.eq(date),
+                    user_nutrient_tracking::quantity.eq(quantity),
+                )
+            })
+            .collect();
+        let calorie_rows: Vec<_> = calorie_totals
+            .into_iter()
+            .map(|((user_id, date), calories)| {
+                (
+                    user_calorie_tracking::user_id.eq(user_id),
+                    user_calorie_tracking::date.eq(date),
+                    user_calorie_tracking::calories.eq(calories),
+                )
+            })
+            .collect();
+
+        Ok(TrackingReport {
+            nutrient_rows: diesel::insert_into(user_nutrient_tracking::table)
+                .values(&nutrient_rows)
+                .execute(conn)?,
+            calorie_rows: diesel::insert_into(user_calorie_tracking::table)
+                .values(&calorie_rows)
+                .execute(conn)?,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nutrients_keep_the_ids_the_planner_expects() {
+        let ids: Vec<i32> = NUTRIENTS.iter().map(|(id, _, _)| *id).collect();
+        assert_eq!(ids, (1..=7).collect::<Vec<_>>());
+        assert_eq!(NUTRIENTS[0].1, "calories");
+        assert_eq!(NUTRIENTS[6].1, "sodium");
+    }
+
+    #[test]
+    fn allergens_match_either_ingredient_name_case_insensitively() {
+        let allergies = vec![(1, "peanut".to_string()), (2, "Milk".to_string()), (3, "sesame".to_string())];
+        let recipe_ingredients = vec![
+            (10, "เนยถั่ว".to_string(), Some("Peanut butter".to_string())),
+            (10, "ถั่วลิสง".to_string(), Some("roasted peanuts".to_string())),
+            (11, "coconut milk".to_string(), None),
+            (12, "rice".to_string(), Some("Rice".to_string())),
+        ];
+
+        assert_eq!(allergen_links(&allergies, &recipe_ingredients), vec![(10, 1), (11, 2)]);
+    }
+}
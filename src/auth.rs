@@ -0,0 +1,376 @@
+//! Admin authentication and roles.
+//!
+//! Admins log in with their email and password (argon2 hashes in `admins`) and
+//! get a signed JWT, sent back as `Authorization: Bearer <token>`. Routes that
+//! change the catalog sit behind [`require_role`], which checks the token and
+//! the role and hands the caller to handlers as an [`AdminUser`].
+//!
+//! Dietitians manage recipes, ingredients and templates; superadmins can also
+//! manage admins. Tokens are stateless, so a removed admin keeps access until
+//! their token expires.
+
+use crate::config::AdminAuthConfig;
+use crate::error::AppError;
+use crate::models::Admin;
+use crate::repo::AdminRepo;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::extract::{FromRequestParts, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::Response;
+use diesel::QueryResult;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+use utoipa::ToSchema;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Dietitian,
+    Superadmin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Dietitian => "dietitian",
+            Role::Superadmin => "superadmin",
+        }
+    }
+
+    /// Whether this role may do what `required` may; superadmins may do everything.
+    pub fn includes(self, required: Role) -> bool {
+        self == Role::Superadmin || self == required
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "dietitian" => Ok(Role::Dietitian),
+            "superadmin" => Ok(Role::Superadmin),
+            _ => Err(format!("unknown role '{}', expected dietitian or superadmin", value)),
+        }
+    }
+}
+
+/// What a token asserts about its holder.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Claims {
+    pub sub: String, // Admin email
+    pub role: Role,
+    pub iat: u64,
+    pub exp: u64,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct IssuedToken {
+    pub token: String,
+    pub token_type: &'static str,
+    pub expires_at: u64, // Unix seconds
+    pub role: Role,
+}
+
+/// Signing keys and token lifetime, shared by the login route and [`AdminUser`].
+#[derive(Clone)]
+pub struct AuthConfig {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    pub token_ttl: Duration,
+}
+
+impl AuthConfig {
+    /// Tokens are HS256-signed with `secret`.
+    pub fn new(secret: &[u8], token_ttl: Duration) -> Self {
+        AuthConfig {
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            token_ttl,
+        }
+    }
+
+    /// Without a configured secret (allowed only with `ADMIN_JWT_RANDOM_SECRET`) a
+    /// random one is used, so tokens stop working when the server restarts.
+    pub fn from_config(config: &AdminAuthConfig) -> Self {
+        let secret = match &config.jwt_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                warn!("Signing admin tokens with a random secret; they will not survive a restart");
+                SaltString::generate(&mut OsRng).as_str().as_bytes().to_vec()
+            }
+        };
+
+        AuthConfig::new(&secret, config.token_ttl)
+    }
+
+    pub fn issue(&self, email: &str, role: Role) -> Result<IssuedToken, jsonwebtoken::errors::Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let claims = Claims {
+            sub: email.to_string(),
+            role,
+            iat: now,
+            exp: now + self.token_ttl.as_secs(),
+        };
+        let token = encode(&Header::new(Algorithm::HS256), &claims, &self.encoding_key)?;
+
+        Ok(IssuedToken {
+            token,
+            token_type: "Bearer",
+            expires_at: claims.exp,
+            role,
+        })
+    }
+
+    /// Checks the signature and expiry and returns the claims.
+    pub fn verify(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        let validation = Validation::new(Algorithm::HS256);
+        decode::<Claims>(token, &self.decoding_key, &validation).map(|data| data.claims)
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default().hash_password(password.as_bytes(), &salt)?.to_string())
+}
+
+/// False for a wrong password and for a hash that cannot be parsed.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        .unwrap_or(false)
+}
+
+/// Checked against when there is no real hash, so every login costs one verification.
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("no admin has this password").expect("hashing a fixed password"));
+
+/// The admin with this email if the password matches; `None` for an unknown email, a
+/// wrong password or an admin without a password hash, so callers cannot tell them apart,
+/// not even by how long the answer takes.
+pub fn authenticate<R: AdminRepo>(repo: &mut R, email: &str, password: &str) -> QueryResult<Option<Admin>> {
+    authenticate_with(repo, email, password, verify_password)
+}
+
+fn authenticate_with<R, V>(repo: &mut R, email: &str, password: &str, verify: V) -> QueryResult<Option<Admin>>
+where
+    R: AdminRepo,
+    V: FnOnce(&str, &str) -> bool,
+{
+    let admin = repo.find_admin(email)?;
+    let hash = admin.as_ref().and_then(|admin| admin.password_hash.as_deref());
+    let valid = verify(password, hash.unwrap_or(&DUMMY_HASH)) && hash.is_some();
+    Ok(admin.filter(|_| valid))
+}
+
+/// An admin authenticated by a bearer token.
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub email: String,
+    pub role: Role,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for AdminUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Already checked by require_role
+        if let Some(admin) = parts.extensions.get::<AdminUser>() {
+            return Ok(admin.clone());
+        }
+
+        let config = parts
+            .extensions
+            .get::<Arc<AuthConfig>>()
+            .ok_or_else(|| AppError::Internal("Admin authentication is not configured".to_string()))?;
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+
+        let claims = config
+            .verify(token.trim())
+            .map_err(|_| AppError::Unauthorized("Invalid or expired token".to_string()))?;
+
+        Ok(AdminUser {
+            email: claims.sub,
+            role: claims.role,
+        })
+    }
+}
+
+/// Middleware for `axum::middleware::from_fn_with_state(role, require_role)`: lets the
+/// request through only for admins whose role includes `required`.
+pub async fn require_role(
+    State(required): State<Role>,
+    admin: AdminUser,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if !admin.role.includes(required) {
+        return Err(AppError::Forbidden(format!("Requires the {} role", required)));
+    }
+
+    request.extensions_mut().insert(admin);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::memory::MemoryRepo;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::{middleware, Extension, Router};
+    use tower::ServiceExt;
+
+    const SECRET: &[u8] = b"test-secret-that-is-long-enough-for-hs256";
+
+    fn config() -> AuthConfig {
+        AuthConfig::new(SECRET, Duration::from_secs(60 * 60))
+    }
+
+    fn token_with_exp(config: &AuthConfig, role: Role, exp: u64) -> String {
+        let claims = Claims { sub: "admin@x.io".to_string(), role, iat: 0, exp };
+        encode(&Header::new(Algorithm::HS256), &claims, &config.encoding_key).unwrap()
+    }
+
+    #[test]
+    fn login_needs_the_right_password() {
+        let mut repo = MemoryRepo::new();
+        repo.upsert_admin("admin@x.io", &hash_password("correct horse").unwrap(), Role::Dietitian).unwrap();
+        repo.admins.push(Admin {
+            admin_email: "legacy@x.io".to_string(),
+            password_hash: None,
+            role: "dietitian".to_string(),
+        });
+
+        let admin = authenticate(&mut repo, "admin@x.io", "correct horse").unwrap();
+        assert_eq!(admin.map(|admin| admin.role), Some("dietitian".to_string()));
+
+        assert!(authenticate(&mut repo, "admin@x.io", "wrong horse").unwrap().is_none());
+        assert!(authenticate(&mut repo, "nobody@x.io", "correct horse").unwrap().is_none());
+        assert!(authenticate(&mut repo, "legacy@x.io", "").unwrap().is_none());
+    }
+
+    #[test]
+    fn every_login_verifies_a_hash() {
+        let mut repo = MemoryRepo::new();
+        repo.upsert_admin("admin@x.io", &hash_password("correct horse").unwrap(), Role::Dietitian).unwrap();
+        repo.admins.push(Admin {
+            admin_email: "legacy@x.io".to_string(),
+            password_hash: None,
+            role: "dietitian".to_string(),
+        });
+
+        for email in ["admin@x.io", "nobody@x.io", "legacy@x.io"] {
+            let mut verified = Vec::new();
+            authenticate_with(&mut repo, email, "guess", |password, hash| {
+                verified.push(hash.to_string());
+                verify_password(password, hash)
+            })
+            .unwrap();
+            assert_eq!(verified.len(), 1, "{}", email);
+            assert!(PasswordHash::new(&verified[0]).is_ok(), "{}", email);
+        }
+
+        // Even the dummy hash's own password does not log anyone in
+        assert!(authenticate(&mut repo, "nobody@x.io", "no admin has this password").unwrap().is_none());
+        assert!(authenticate(&mut repo, "legacy@x.io", "no admin has this password").unwrap().is_none());
+    }
+
+    #[test]
+    fn issued_tokens_verify() {
+        let config = config();
+        let issued = config.issue("admin@x.io", Role::Superadmin).unwrap();
+
+        let claims = config.verify(&issued.token).unwrap();
+        assert_eq!(claims.sub, "admin@x.io");
+        assert_eq!(claims.role, Role::Superadmin);
+        assert_eq!(claims.exp, issued.expires_at);
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let config = config();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        // Past the 60s leeway jsonwebtoken allows for clock skew
+        let token = token_with_exp(&config, Role::Dietitian, now - 120);
+
+        assert!(config.verify(&token).is_err());
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let config = config();
+        let token = config.issue("admin@x.io", Role::Dietitian).unwrap().token;
+
+        // Same signature over claims promoted to superadmin
+        let mut parts: Vec<String> = token.split('.').map(str::to_string).collect();
+        let forged = Claims { sub: "admin@x.io".to_string(), role: Role::Superadmin, iat: 0, exp: u64::MAX / 2 };
+        let forged_token = encode(&Header::new(Algorithm::HS256), &forged, &EncodingKey::from_secret(b"other")).unwrap();
+        parts[1] = forged_token.split('.').nth(1).unwrap().to_string();
+        assert!(config.verify(&parts.join(".")).is_err());
+
+        // Validly signed, but with another secret
+        assert!(config.verify(&forged_token).is_err());
+    }
+
+    #[test]
+    fn superadmins_include_every_role() {
+        assert!(Role::Superadmin.includes(Role::Dietitian));
+        assert!(Role::Superadmin.includes(Role::Superadmin));
+        assert!(Role::Dietitian.includes(Role::Dietitian));
+        assert!(!Role::Dietitian.includes(Role::Superadmin));
+    }
+
+    async fn status_for(required: Role, authorization: Option<String>) -> StatusCode {
+        let config = Arc::new(config());
+        let app = Router::new()
+            .route("/", get(|admin: AdminUser| async move { admin.email }))
+            .layer(middleware::from_fn_with_state(required, require_role))
+            .layer(Extension(config));
+
+        let mut request = Request::builder().uri("/");
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn routes_check_the_role_in_the_token() {
+        let config = config();
+        let dietitian = format!("Bearer {}", config.issue("d@x.io", Role::Dietitian).unwrap().token);
+        let superadmin = format!("Bearer {}", config.issue("s@x.io", Role::Superadmin).unwrap().token);
+
+        assert_eq!(status_for(Role::Dietitian, Some(dietitian.clone())).await, StatusCode::OK);
+        assert_eq!(status_for(Role::Superadmin, Some(dietitian)).await, StatusCode::FORBIDDEN);
+        assert_eq!(status_for(Role::Superadmin, Some(superadmin)).await, StatusCode::OK);
+        assert_eq!(status_for(Role::Dietitian, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status_for(Role::Dietitian, Some("Bearer not-a-token".to_string())).await, StatusCode::UNAUTHORIZED);
+
+        let expired = token_with_exp(&config, Role::Superadmin, 1);
+        assert_eq!(status_for(Role::Dietitian, Some(format!("Bearer {}", expired))).await, StatusCode::UNAUTHORIZED);
+    }
+}
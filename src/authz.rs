@@ -0,0 +1,124 @@
+//! Authorization of changes to a user's own meal plans.
+//!
+//! Handlers that change a meal plan entry by its `meal_plan_recipe_id` first turn
+//! the id into an [`OwnedMealPlanRecipe`] with [`authorize_meal_plan_recipe`],
+//! which checks that the entry belongs to one of the user's meal plans. The
+//! repository methods that modify a single entry only accept that type, so a
+//! handler cannot skip the check. Changes addressed by date are already scoped
+//! to the user by their queries.
+
+use crate::error::{AppError, AppResult};
+use crate::identity::UserId;
+use crate::repo::MealPlanRepo;
+use tracing::warn;
+
+/// A `meal_plan_recipes` row known to belong to `user_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnedMealPlanRecipe {
+    meal_plan_recipe_id: i32,
+    user_id: UserId,
+}
+
+impl OwnedMealPlanRecipe {
+    pub fn id(self) -> i32 {
+        self.meal_plan_recipe_id
+    }
+
+    pub fn user_id(self) -> UserId {
+        self.user_id
+    }
+}
+
+/// 404 when the entry does not exist, 403 when it is on another user's meal plan.
+pub fn authorize_meal_plan_recipe<R: MealPlanRepo>(
+    repo: &mut R,
+    user_id: UserId,
+    meal_plan_recipe_id: i32,
+) -> AppResult<OwnedMealPlanRecipe> {
+    let owner = repo
+        .meal_plan_recipe_owner(meal_plan_recipe_id)
+        .map_err(AppError::internal("Error fetching meal plan recipe"))?;
+
+    match owner {
+        None => Err(AppError::not_found("Meal plan recipe not found")),
+        Some(owner) if owner != user_id => {
+            warn!(%user_id, meal_plan_recipe_id, %owner, "Refused change to another user's meal plan recipe");
+            Err(AppError::Forbidden("Meal plan recipe belongs to another user".to_string()))
+        }
+        Some(_) => Ok(OwnedMealPlanRecipe { meal_plan_recipe_id, user_id }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::LineUserId;
+    use crate::repo::memory::{MemoryMealPlan, MemoryMealPlanRecipe, MemoryRepo, MemoryUser};
+    use crate::routes::mealplan::Nutrition;
+    use crate::service::require_user;
+    use chrono::NaiveDate;
+
+    const OWNER: UserId = UserId(1);
+    const OTHER: UserId = UserId(2);
+    const OWN_ENTRY: i32 = 10;
+    const OTHER_ENTRY: i32 = 20;
+
+    /// Two users with one meal plan entry each.
+    fn repo() -> MemoryRepo {
+        let mut repo = MemoryRepo::new();
+        for (user_id, line_id, meal_plan_id, meal_plan_recipe_id) in [(OWNER, "U1", 1, OWN_ENTRY), (OTHER, "U2", 2, OTHER_ENTRY)] {
+            repo.users.push(MemoryUser {
+                user_id,
+                line_id: LineUserId(line_id.to_string()),
+                allergy_ids: Default::default(),
+                limits: Nutrition::default(),
+            });
+            repo.meal_plans.push(MemoryMealPlan {
+                meal_plan_id,
+                user_id,
+                name: "Plan".to_string(),
+                date: NaiveDate::from_ymd_opt(2030, 1, 1).unwrap(),
+                archived_at: None,
+            });
+            repo.meal_plan_recipes.push(MemoryMealPlanRecipe {
+                meal_plan_recipe_id,
+                meal_plan_id,
+                recipe_id: 100,
+                meal_time: 1,
+                ischecked: false,
+            });
+        }
+        repo
+    }
+
+    /// The check `user_already_eat` makes: find the caller, then the entry.
+    fn authorize(repo: &mut MemoryRepo, line_id: &str, meal_plan_recipe_id: i32) -> AppResult<OwnedMealPlanRecipe> {
+        let user_id = require_user(repo, &LineUserId(line_id.to_string()))?;
+        authorize_meal_plan_recipe(repo, user_id, meal_plan_recipe_id)
+    }
+
+    #[test]
+    fn own_entries_are_allowed() {
+        let entry = authorize(&mut repo(), "U1", OWN_ENTRY).unwrap();
+
+        assert_eq!(entry.id(), OWN_ENTRY);
+        assert_eq!(entry.user_id(), OWNER);
+    }
+
+    #[test]
+    fn another_users_entry_is_forbidden() {
+        assert!(matches!(authorize(&mut repo(), "U1", OTHER_ENTRY), Err(AppError::Forbidden(_))));
+    }
+
+    #[test]
+    fn missing_entries_are_not_found() {
+        assert!(matches!(authorize(&mut repo(), "U1", 999), Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn unregistered_callers_are_not_found() {
+        for entry in [OWN_ENTRY, OTHER_ENTRY, 999] {
+            assert!(matches!(authorize(&mut repo(), "U3", entry), Err(AppError::NotFound(_))), "entry {}", entry);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Data management for the kidney meal planner database.
+//!
+//! ```sh
+//! kidney-admin migrate
+//! kidney-admin seed
+//! kidney-admin export-recipes [FILE] [--format json|csv]
+//! kidney-admin import-recipes FILE [--format json|csv] [--dry-run]
+//! kidney-admin recompute-allergens
+//! kidney-admin recompute-tracking [--from YYYY-MM-DD] [--to YYYY-MM-DD]
+//! kidney-admin create-admin EMAIL [--role dietitian|superadmin]
+//! kidney-admin line-test-token LINE_USER_ID [--key FILE] [--minutes N]
+//! ```
+//!
+//! Connects with `DATABASE_URL`, like the server. Recipe files are JSON arrays of
+//! `RecipeRecord`s or CSV (see `recipe_io::read_csv`); the format follows the file
+//! extension unless `--format` is given. `export-recipes` writes to stdout when no
+//! file is given.
+//! A running server caches the recipe catalog, so restart it after importing.
+//! `create-admin` reads the password from `ADMIN_PASSWORD`, or from stdin when that
+//! is not set; use it to create the first superadmin. `line-test-token` prints a LINE
+//! ID token signed with the development key, for a server using `dev/line-test-jwks.json`.
+
+use chrono::NaiveDate;
+use kidney_diesel::admin::{recompute_nutrient_tracking, recompute_recipe_allergens, seed_reference_data};
+use kidney_diesel::auth::{hash_password, Role};
+use kidney_diesel::config::Settings;
+use kidney_diesel::db::run_migrations;
+use kidney_diesel::establish_connection;
+use kidney_diesel::identity::LineUserId;
+use kidney_diesel::line_auth::{sign_test_token, LineAuthConfig};
+use kidney_diesel::repo::AdminRepo;
+use kidney_diesel::recipe_io::{export_recipes, import_recipes, read_records, write_records, RecipeFormat};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: kidney-admin <command>
+
+Commands:
+  migrate                        Apply pending database migrations
+  seed                           Insert missing nutrients, allergies, diseases and condition types
+  export-recipes [FILE] [--format json|csv]
+                                 Write every recipe (stdout by default)
+  import-recipes FILE [--format json|csv] [--dry-run]
+                                 Create or replace recipes by name; --dry-run only validates
+  recompute-allergens            Rebuild recipe allergens from their ingredients
+  recompute-tracking [--from YYYY-MM-DD] [--to YYYY-MM-DD]
+                                 Rebuild users' nutrient and calorie tracking from checked meals
+  create-admin EMAIL [--role dietitian|superadmin]
+                                 Create an admin (dietitian by default) or reset their password and role
+  line-test-token LINE_USER_ID [--key FILE] [--minutes N]
+                                 Sign a LINE ID token with the development key (dev/line-test-key.pem)";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(command) = args.first() else {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+
+    if let Err(err) = run(command, &args[1..]) {
+        eprintln!("{} failed: {}", command, err);
+        std::process::exit(1);
+    }
+}
+
+fn run(command: &str, args: &[String]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match command {
+        "migrate" => {
+            let versions = run_migrations(&mut establish_connection())?;
+            if versions.is_empty() {
+                println!("Database schema is up to date");
+            } else {
+                println!("Applied migrations: {}", versions.join(", "));
+            }
+        }
+        "seed" => {
+            let report = seed_reference_data(&mut establish_connection())?;
+            println!(
+                "Added nutrients: {}, allergies: {}, diseases: {}, condition types: {}",
+                report.nutrients, report.ingredient_allergies, report.diseases, report.food_condition_types
+            );
+        }
+        "export-recipes" => {
+            let path = args.first().filter(|arg| !arg.starts_with("--"));
+            let format = format_flag(args, path)?;
+            let records = export_recipes(&mut establish_connection())?;
+            let output = write_records(format, &records)?;
+            match path {
+                Some(path) => {
+                    fs::write(path, output)?;
+                    eprintln!("Exported {} recipe(s) to {}", records.len(), path);
+                }
+                None => print!("{}", output),
+            }
+        }
+        "import-recipes" => {
+            let path = args.first().filter(|arg| !arg.starts_with("--")).ok_or("missing FILE")?;
+            let format = format_flag(args, Some(path))?;
+            let dry_run = args.iter().any(|arg| arg == "--dry-run");
+            let records = read_records(format, &fs::read(path)?)?;
+            let report = import_recipes(&mut establish_connection(), &records, dry_run)?;
+            if report.dry_run {
+                println!("Valid: would create {} and update {} recipe(s)", report.created, report.updated);
+            } else {
+                println!("Created {} and updated {} recipe(s)", report.created, report.updated);
+            }
+        }
+        "recompute-allergens" => {
+            let links = recompute_recipe_allergens(&mut establish_connection())?;
+            println!("Wrote {} recipe allergen link(s)", links);
+        }
+        "recompute-tracking" => {
+            let from = date_flag(args, "--from")?;
+            let to = date_flag(args, "--to")?;
+            let report = recompute_nutrient_tracking(&mut establish_connection(), from, to)?;
+            println!(
+                "Wrote {} nutrient and {} calorie tracking row(s)",
+                report.nutrient_rows, report.calorie_rows
+            );
+        }
+        "create-admin" => {
+            let email = args.first().filter(|arg| !arg.starts_with("--")).ok_or("missing EMAIL")?;
+            let role = match args.iter().position(|arg| arg == "--role") {
+                Some(index) => args.get(index + 1).ok_or("--role needs dietitian or superadmin")?.parse()?,
+                None => Role::Dietitian,
+            };
+            let password = match env::var("ADMIN_PASSWORD") {
+                Ok(password) => password,
+                Err(_) => {
+                    eprintln!("Password for {}:", email);
+                    let mut line = String::new();
+                    io::stdin().read_line(&mut line)?;
+                    line.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            if password.is_empty() {
+                return Err("empty password".into());
+            }
+            let hash = hash_password(&password).map_err(|err| err.to_string())?;
+            establish_connection().upsert_admin(email, &hash, role)?;
+            println!("Saved admin {} as {}", email, role);
+        }
+        "line-test-token" => {
+            let line_user_id = args.first().filter(|arg| !arg.starts_with("--")).ok_or("missing LINE_USER_ID")?;
+            let key_path = flag_value(args, "--key").unwrap_or("dev/line-test-key.pem");
+            let minutes: u64 = match flag_value(args, "--minutes") {
+                Some(value) => value.parse().map_err(|_| format!("invalid --minutes: {}", value))?,
+                None => 60,
+            };
+            let mut settings = Settings::load()?;
+            let config = LineAuthConfig::from_settings(&mut settings);
+            settings.finish()?;
+            let token = sign_test_token(
+                &fs::read(key_path)?,
+                &config,
+                &LineUserId(line_user_id.clone()),
+                Duration::from_secs(minutes * 60),
+            )?;
+            println!("{}", token);
+        }
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        _ => return Err(format!("unknown command\n\n{}", USAGE).into()),
+    }
+    Ok(())
+}
+
+/// Value of `--flag YYYY-MM-DD`, if given.
+fn date_flag(args: &[String], flag: &str) -> Result<Option<NaiveDate>, Box<dyn Error + Send + Sync>> {
+    let Some(index) = args.iter().position(|arg| arg == flag) else {
+        return Ok(None);
+    };
+    let value = args.get(index + 1).ok_or_else(|| format!("{} needs a date", flag))?;
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("invalid {} date: {}", flag, value))?;
+    Ok(Some(date))
+}
+
+/// Value following `flag`, if given.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    args.get(index + 1).map(String::as_str)
+}
+
+/// `--format` if given, otherwise CSV for `.csv` files and JSON for anything else.
+fn format_flag(args: &[String], path: Option<&String>) -> Result<RecipeFormat, Box<dyn Error + Send + Sync>> {
+    if let Some(index) = args.iter().position(|arg| arg == "--format") {
+        let value = args.get(index + 1).ok_or("--format needs json or csv")?;
+        return Ok(value.parse()?);
+    }
+    let is_csv = path.is_some_and(|path| path.to_lowercase().ends_with(".csv"));
+    Ok(if is_csv { RecipeFormat::Csv } else { RecipeFormat::Json })
+}
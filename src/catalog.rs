@@ -0,0 +1,150 @@
+//! In-memory cache of the recipe pool sent to the recommender.
+//!
+//! Building the pool needs a grouped join over `recipes_nutrients`, which is the
+//! same for every user; only the allergy filter differs. The catalog loads every
+//! recipe with its nutrients and allergens once, and each request filters it in
+//! Rust. Anything in this process that changes recipes, their nutrients or
+//! allergens must call [`RecipeCatalog::invalidate`]. Changes made elsewhere
+//! (`kidney-admin`, another instance, SQL by hand) show up once the cached copy
+//! is older than `CATALOG_TTL_SECS`.
+
+use crate::repo::RecipeRepo;
+use crate::routes::mealplan::FoodMenu;
+use diesel::QueryResult;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct CatalogRecipe {
+    pub menu: FoodMenu,
+    pub allergy_ids: Vec<i32>,
+}
+
+#[derive(Default)]
+struct CatalogState {
+    // Bumped on every invalidation so a load that raced with it is not cached.
+    generation: u64,
+    recipes: Option<(Arc<Vec<CatalogRecipe>>, Instant)>, // With when they were loaded
+}
+
+pub struct RecipeCatalog {
+    state: RwLock<CatalogState>,
+    ttl: Duration,
+}
+
+impl RecipeCatalog {
+    /// A catalog that reloads recipes cached for longer than `ttl`; zero reloads them on every request.
+    pub fn new(ttl: Duration) -> Self {
+        RecipeCatalog { state: RwLock::default(), ttl }
+    }
+
+    /// Returns the cached recipes, loading them from the repository on first use and once they expire.
+    pub fn recipes<R: RecipeRepo>(&self, repo: &mut R) -> QueryResult<Arc<Vec<CatalogRecipe>>> {
+        let generation = {
+            let state = self.state.read().unwrap();
+            match &state.recipes {
+                Some((recipes, loaded_at)) if loaded_at.elapsed() < self.ttl => return Ok(recipes.clone()),
+                _ => state.generation,
+            }
+        };
+
+        let loaded_at = Instant::now();
+        let recipes = Arc::new(repo.load_catalog()?);
+
+        let mut state = self.state.write().unwrap();
+        if state.generation == generation {
+            state.recipes = Some((recipes.clone(), loaded_at));
+        }
+        Ok(recipes)
+    }
+
+    /// Drops the cached recipes; the next request reloads them.
+    pub fn invalidate(&self) {
+        let mut state = self.state.write().unwrap();
+        state.generation += 1;
+        state.recipes = None;
+    }
+}
+
+/// The recipes that contain none of the given allergies, in the recommender's `FoodMenu` format.
+pub fn food_menus_for(recipes: &[CatalogRecipe], allergy_ids: &HashSet<i32>) -> Vec<FoodMenu> {
+    recipes
+        .iter()
+        .filter(|recipe| !recipe.allergy_ids.iter().any(|id| allergy_ids.contains(id)))
+        .map(|recipe| recipe.menu.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::memory::MemoryRepo;
+    use crate::routes::mealplan::Nutrition;
+
+    fn repo_with(recipe_ids: &[i32]) -> MemoryRepo {
+        let mut repo = MemoryRepo::new();
+        repo.recipes = recipe_ids
+            .iter()
+            .map(|&recipe_id| CatalogRecipe {
+                menu: FoodMenu {
+                    name: format!("Recipe {}", recipe_id),
+                    nutrition: Nutrition::default(),
+                    recipe_id,
+                    recipe_img_link: Vec::new(),
+                },
+                allergy_ids: Vec::new(),
+            })
+            .collect();
+        repo
+    }
+
+    fn ids(recipes: &[CatalogRecipe]) -> Vec<i32> {
+        recipes.iter().map(|recipe| recipe.menu.recipe_id).collect()
+    }
+
+    #[test]
+    fn recipes_are_cached_until_invalidated() {
+        let catalog = RecipeCatalog::new(Duration::from_secs(60));
+        let mut repo = repo_with(&[1, 2]);
+        assert_eq!(ids(&catalog.recipes(&mut repo).unwrap()), vec![1, 2]);
+
+        repo.recipes.pop();
+        assert_eq!(ids(&catalog.recipes(&mut repo).unwrap()), vec![1, 2]);
+
+        catalog.invalidate();
+        assert_eq!(ids(&catalog.recipes(&mut repo).unwrap()), vec![1]);
+    }
+
+    #[test]
+    fn recipes_are_reloaded_once_they_expire() {
+        let catalog = RecipeCatalog::new(Duration::from_millis(20));
+        let mut repo = repo_with(&[1, 2]);
+        assert_eq!(ids(&catalog.recipes(&mut repo).unwrap()), vec![1, 2]);
+
+        repo.recipes.pop();
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(ids(&catalog.recipes(&mut repo).unwrap()), vec![1]);
+    }
+
+    #[test]
+    fn a_zero_ttl_reloads_every_time() {
+        let catalog = RecipeCatalog::new(Duration::ZERO);
+        let mut repo = repo_with(&[1, 2]);
+        assert_eq!(ids(&catalog.recipes(&mut repo).unwrap()), vec![1, 2]);
+
+        repo.recipes.pop();
+        assert_eq!(ids(&catalog.recipes(&mut repo).unwrap()), vec![1]);
+    }
+
+    #[test]
+    fn food_menus_leave_out_recipes_with_the_users_allergies() {
+        let mut recipes = repo_with(&[1, 2, 3]).recipes;
+        recipes[1].allergy_ids = vec![7];
+        recipes[2].allergy_ids = vec![8, 9];
+
+        let menus = food_menus_for(&recipes, &HashSet::from([7, 9]));
+
+        assert_eq!(menus.iter().map(|menu| menu.recipe_id).collect::<Vec<_>>(), vec![1]);
+    }
+}
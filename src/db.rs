@@ -0,0 +1,138 @@
+//! Database access from async code.
+//!
+//! Diesel and r2d2 are blocking: checking out a connection can wait for the
+//! pool and every query waits for Postgres. Running that directly in a handler
+//! parks a Tokio worker thread, and with a few slow queries the whole server
+//! stops answering. [`Db::run`] moves the work, including the pool checkout,
+//! onto the blocking thread pool so handlers only `.await` it.
+//!
+//! The schema lives in `migrations/` and is embedded into the binary, so a new
+//! database can be brought up with [`run_migrations`] and nothing else.
+
+use crate::error::{AppError, AppResult};
+use crate::repo::{JobRepo, MealPlanRepo, NutrientRepo, RecipeRepo, UserRepo};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use metrics::histogram;
+use std::error::Error;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::Span;
+
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Applies the embedded migrations that have not run yet and returns their versions.
+pub fn run_migrations(conn: &mut PgConnection) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+    let applied = conn.run_pending_migrations(MIGRATIONS)?;
+    Ok(applied.iter().map(|version| version.to_string()).collect())
+}
+
+/// Versions of the embedded migrations the database has not applied yet.
+pub fn pending_migrations(conn: &mut PgConnection) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+    let pending = conn.pending_migrations(MIGRATIONS)?;
+    Ok(pending.iter().map(|migration| migration.name().version().to_string()).collect())
+}
+
+/// Somewhere to run repository work from async code: [`Db`], or
+/// [`crate::repo::memory::MemoryDb`] where the code under test should not need Postgres.
+pub trait Database: Clone + Send + Sync + 'static {
+    type Repo: UserRepo + RecipeRepo + NutrientRepo + MealPlanRepo + JobRepo;
+
+    fn run<T, F>(&self, f: F) -> impl Future<Output = AppResult<T>> + Send
+    where
+        F: FnOnce(&mut Self::Repo) -> AppResult<T> + Send + 'static,
+        T: Send + 'static;
+}
+
+#[derive(Clone)]
+pub struct Db {
+    pool: Arc<DbPool>,
+}
+
+impl Db {
+    pub fn new(pool: DbPool) -> Self {
+        Db { pool: Arc::new(pool) }
+    }
+
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    /// Runs `f` with a pooled connection on the blocking thread pool, inside the caller's span.
+    /// Time waiting for the connection and time holding it are recorded separately.
+    pub async fn run<T, F>(&self, f: F) -> AppResult<T>
+    where
+        F: FnOnce(&mut PgConnection) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let span = Span::current();
+        tokio::task::spawn_blocking(move || {
+            let _entered = span.enter();
+            let checkout = Instant::now();
+            let mut conn = pool.get()?;
+            histogram!("db_pool_wait_seconds").record(checkout.elapsed().as_secs_f64());
+
+            let started = Instant::now();
+            let result = f(&mut conn);
+            histogram!("db_query_duration_seconds").record(started.elapsed().as_secs_f64());
+            result
+        })
+        .await
+        .map_err(AppError::internal("Database task failed"))?
+    }
+}
+
+impl Database for Db {
+    type Repo = PgConnection;
+
+    fn run<T, F>(&self, f: F) -> impl Future<Output = AppResult<T>> + Send
+    where
+        F: FnOnce(&mut PgConnection) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        Db::run(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn unreachable_db() -> Db {
+        let manager = ConnectionManager::<PgConnection>::new("postgres://nobody@127.0.0.1:1/none");
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(200))
+            .build_unchecked(manager);
+        Db::new(pool)
+    }
+
+    #[test]
+    fn migrations_start_from_the_baseline_schema() {
+        use diesel::migration::MigrationSource;
+        use diesel::pg::Pg;
+
+        let versions: Vec<String> = MigrationSource::<Pg>::migrations(&MIGRATIONS)
+            .unwrap()
+            .iter()
+            .map(|migration| migration.name().version().to_string())
+            .collect();
+
+        assert_eq!(versions[..3], ["00000000000000", "20250101000000", "20250101000001"]);
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", versions);
+    }
+
+    #[tokio::test]
+    async fn a_failed_checkout_is_an_internal_error() {
+        let db = unreachable_db();
+
+        let err = db.run(|_conn| Ok(())).await.unwrap_err();
+
+        assert_eq!(err.message(), "Failed to connect to the database");
+    }
+}
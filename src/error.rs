@@ -0,0 +1,257 @@
+//! Error type returned by every route.
+//!
+//! Handlers return `Result<_, AppError>`. Each variant maps to one HTTP status
+//! and one stable `code`, and every error is answered with the same JSON body:
+//!
+//! ```json
+//! { "code": "not_found", "error": "User not found", "details": { ... } }
+//! ```
+//!
+//! `error` is a human readable message and may change; clients should branch on
+//! `code`. `details` is only present when there is structured data to report
+//! (overlapping dates, validation issues). Internal causes are logged, never
+//! returned.
+
+use crate::recipe_io::ImportError;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::NaiveDate;
+use diesel::r2d2::PoolError;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use tracing::error;
+use utoipa::ToSchema;
+
+#[derive(Debug)]
+pub enum AppError {
+    /// Malformed request or invalid values (400, `bad_request`).
+    BadRequest(String),
+    /// Missing, invalid or expired credentials (401, `unauthorized`).
+    Unauthorized(String),
+    /// Authenticated, but not allowed to do this (403, `forbidden`).
+    Forbidden(String),
+    /// The user, recipe, meal plan, ... does not exist (404, `not_found`).
+    NotFound(String),
+    /// The request clashes with existing data (409, `conflict`).
+    Conflict(String, Option<Value>),
+    /// The request was understood but its content was rejected (422, `validation_failed`).
+    Unprocessable(String, Option<Value>),
+    /// Too much queued work; the client should retry later (429, `too_many_requests`).
+    TooManyRequests(String),
+    /// The AI recommender failed or sent something unusable (502, `upstream_error`).
+    Upstream(String),
+    /// Database or other server-side failure (500, `internal_error`).
+    Internal(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl AppError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        AppError::BadRequest(message.into())
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound(message.into())
+    }
+
+    /// For `map_err`: logs the underlying error and reports only `message` to the client.
+    pub fn internal<E: fmt::Display>(message: &'static str) -> impl FnOnce(E) -> AppError {
+        move |err| {
+            error!(error = %err, "{}", message);
+            AppError::Internal(message.to_string())
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(..) => StatusCode::CONFLICT,
+            AppError::Unprocessable(..) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(..) => "conflict",
+            AppError::Unprocessable(..) => "validation_failed",
+            AppError::TooManyRequests(_) => "too_many_requests",
+            AppError::Upstream(_) => "upstream_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message, _)
+            | AppError::Unprocessable(message, _)
+            | AppError::TooManyRequests(message)
+            | AppError::Upstream(message)
+            | AppError::Internal(message) => message,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let details = match self {
+            AppError::Conflict(_, details) | AppError::Unprocessable(_, details) => details.clone(),
+            _ => None,
+        };
+
+        ErrorBody {
+            code: self.code(),
+            error: self.message().to_string(),
+            details,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(self.body())).into_response()
+    }
+}
+
+impl From<PoolError> for AppError {
+    fn from(err: PoolError) -> Self {
+        AppError::internal("Failed to connect to the database")(err)
+    }
+}
+
+impl From<ImportError> for AppError {
+    fn from(err: ImportError) -> Self {
+        match err {
+            ImportError::Parse(message) => AppError::BadRequest(format!("Invalid recipe file: {}", message)),
+            ImportError::Invalid(issues) => AppError::Unprocessable(
+                "Recipe import failed validation".to_string(),
+                Some(serde_json::json!({ "issues": issues })),
+            ),
+            ImportError::Database(err) => AppError::internal("Failed to import recipes")(err),
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+/// `axum::Json` that rejects bad bodies with an [`AppError`] instead of plain text.
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(AppError))]
+pub struct AppJson<T>(pub T);
+
+/// `axum::extract::Path` that rejects bad parameters with an [`AppError`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(AppError))]
+pub struct AppPath<T>(pub T);
+
+/// `axum::extract::Query` that rejects bad query strings with an [`AppError`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(AppError))]
+pub struct AppQuery<T>(pub T);
+
+/// Parses a `YYYY-MM-DD` date from a request.
+pub fn parse_date(date_str: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .map_err(|_| AppError::bad_request("Invalid date format. Use YYYY-MM-DD"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn every_error_has_its_own_status_and_code() {
+        let errors = [
+            (AppError::bad_request("x"), StatusCode::BAD_REQUEST, "bad_request"),
+            (AppError::not_found("x"), StatusCode::NOT_FOUND, "not_found"),
+            (AppError::Conflict("x".to_string(), None), StatusCode::CONFLICT, "conflict"),
+            (AppError::Unprocessable("x".to_string(), None), StatusCode::UNPROCESSABLE_ENTITY, "validation_failed"),
+            (AppError::TooManyRequests("x".to_string()), StatusCode::TOO_MANY_REQUESTS, "too_many_requests"),
+            (AppError::Upstream("x".to_string()), StatusCode::BAD_GATEWAY, "upstream_error"),
+            (AppError::Internal("x".to_string()), StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+        ];
+
+        for (err, status, code) in errors {
+            assert_eq!(err.status(), status);
+            assert_eq!(err.code(), code);
+        }
+    }
+
+    #[test]
+    fn bodies_carry_details_only_when_there_are_some() {
+        let conflict = AppError::Conflict("Taken".to_string(), Some(json!({ "dates": ["2026-01-01"] })));
+        assert_eq!(
+            serde_json::to_value(conflict.body()).unwrap(),
+            json!({ "code": "conflict", "error": "Taken", "details": { "dates": ["2026-01-01"] } })
+        );
+
+        let not_found = AppError::not_found("User not found");
+        assert_eq!(
+            serde_json::to_value(not_found.body()).unwrap(),
+            json!({ "code": "not_found", "error": "User not found" })
+        );
+    }
+
+    #[test]
+    fn internal_errors_hide_their_cause() {
+        let err = AppError::internal("Failed to create meal plan")("connection reset");
+        assert_eq!(err.message(), "Failed to create meal plan");
+    }
+
+    #[test]
+    fn dates_must_be_year_month_day() {
+        assert_eq!(parse_date("2026-02-01").unwrap(), NaiveDate::from_ymd_opt(2026, 2, 1).unwrap());
+        for date_str in ["01/02/2026", "2026-02-30", ""] {
+            assert!(matches!(parse_date(date_str), Err(AppError::BadRequest(_))), "{}", date_str);
+        }
+    }
+}
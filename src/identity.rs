@@ -0,0 +1,152 @@
+//! User identifiers.
+//!
+//! Users have two ids that used to be passed around as bare `i32`/`String` and
+//! were easy to mix up: the internal `users.user_id` and the LINE account id in
+//! `users.user_line_id`. Clients only ever know the LINE id; the internal id is
+//! for joins. Both types map straight onto their columns so they can be used in
+//! Diesel queries.
+
+use crate::schema::users;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::{Integer, Text};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+/// Internal id from `users.user_id`. Never sent to clients as a LINE id.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow, ToSchema)]
+#[serde(transparent)]
+#[diesel(sql_type = Integer)]
+pub struct UserId(pub i32);
+
+/// LINE account id from `users.user_line_id`, the id clients identify themselves with.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash, AsExpression, FromSqlRow, ToSchema)]
+#[serde(transparent)]
+#[diesel(sql_type = Text)]
+pub struct LineUserId(pub String);
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for LineUserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ToSql<Integer, Pg> for UserId {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <i32 as ToSql<Integer, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<Integer, Pg> for UserId {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        <i32 as FromSql<Integer, Pg>>::from_sql(bytes).map(UserId)
+    }
+}
+
+impl ToSql<Text, Pg> for LineUserId {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<Text, Pg> for LineUserId {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        <String as FromSql<Text, Pg>>::from_sql(bytes).map(LineUserId)
+    }
+}
+
+/// Looks up the internal id of the user with the given LINE id.
+pub fn resolve_user(conn: &mut PgConnection, line_id: &LineUserId) -> QueryResult<UserId> {
+    users::table
+        .filter(users::user_line_id.eq(line_id))
+        .select(users::user_id)
+        .first(conn)
+}
+
+/// How a user is identified in a payload sent to the AI recommender.
+///
+/// Contract v1 is what the recommender was built against and is kept for
+/// compatibility: `/ai` receives the internal id under the `user_line_id` label,
+/// while `/ai_update` receives the LINE id, under `user_line_id` at the top level
+/// and under `user_id` inside `mealplan`. Contract v2 always sends both ids
+/// under their own names, plus the contract version.
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum PayloadIdentity {
+    V1InternalIdAsLineId { user_line_id: String },
+    V1LineId { user_line_id: LineUserId },
+    V1LineIdAsUserId { user_id: LineUserId },
+    V2 {
+        contract_version: u8,
+        user_id: UserId,
+        user_line_id: LineUserId,
+    },
+}
+
+impl PayloadIdentity {
+    pub fn v2(user_id: UserId, line_id: &LineUserId) -> Self {
+        PayloadIdentity::V2 {
+            contract_version: 2,
+            user_id,
+            user_line_id: line_id.clone(),
+        }
+    }
+}
+
+/// Makes a recommender response identify the user the way clients expect: by
+/// LINE id under `user_line_id`, whatever id the recommender echoed back.
+pub fn set_response_identity(object: &mut serde_json::Map<String, serde_json::Value>, line_id: &LineUserId) {
+    object.remove("user_id");
+    object.insert("user_line_id".to_string(), serde_json::Value::String(line_id.0.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn ids_serialize_as_bare_values() {
+        assert_eq!(serde_json::to_value(UserId(7)).unwrap(), json!(7));
+        assert_eq!(serde_json::to_value(LineUserId("U1".to_string())).unwrap(), json!("U1"));
+    }
+
+    #[test]
+    fn payload_identities_use_the_contract_labels() {
+        let line_id = LineUserId("U1".to_string());
+
+        let v1_ai = PayloadIdentity::V1InternalIdAsLineId { user_line_id: "7".to_string() };
+        assert_eq!(serde_json::to_value(v1_ai).unwrap(), json!({"user_line_id": "7"}));
+
+        let v1_update = PayloadIdentity::V1LineId { user_line_id: line_id.clone() };
+        assert_eq!(serde_json::to_value(v1_update).unwrap(), json!({"user_line_id": "U1"}));
+
+        let v1_mealplan = PayloadIdentity::V1LineIdAsUserId { user_id: line_id.clone() };
+        assert_eq!(serde_json::to_value(v1_mealplan).unwrap(), json!({"user_id": "U1"}));
+
+        let v2 = PayloadIdentity::v2(UserId(7), &line_id);
+        assert_eq!(
+            serde_json::to_value(v2).unwrap(),
+            json!({"contract_version": 2, "user_id": 7, "user_line_id": "U1"})
+        );
+    }
+
+    #[test]
+    fn responses_identify_the_user_by_line_id() {
+        let mut object = json!({"user_id": 7, "user_line_id": "7", "mealplans": []});
+
+        set_response_identity(object.as_object_mut().unwrap(), &LineUserId("U1".to_string()));
+
+        assert_eq!(object, json!({"user_line_id": "U1", "mealplans": []}));
+    }
+}
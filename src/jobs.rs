@@ -0,0 +1,610 @@
+//! Background AI meal plan generation.
+//!
+//! Jobs are stored in `meal_plan_jobs` so they survive restarts. Submitting a job
+//! inserts a `pending` row and hands its id to the queue; a bounded number of
+//! workers claim jobs (`pending` -> `running`), run the same code as the
+//! synchronous AI handlers and record the outcome (`succeeded` / `failed`).
+//! On startup `recover` puts interrupted `running` jobs back to `pending` and
+//! re-enqueues everything that has not finished, which also covers jobs still
+//! queued when [`JobQueue::shutdown`] stopped the dispatcher. A job that has
+//! already been started `JOB_MAX_ATTEMPTS` times is marked failed instead, so
+//! one that keeps taking the process down is not retried forever. Finished jobs
+//! are deleted `JOB_RETENTION_HOURS` after they finished.
+//!
+//! The queue holds at most `JOB_QUEUE_CAPACITY` jobs and a user may have
+//! `JOB_MAX_UNFINISHED_PER_USER` jobs pending or running; submissions past
+//! either are refused with a 429. A result, and the plan a `persist` job saves,
+//! is only written while the job is still on the run that produced it, in one
+//! transaction, so a job picked up twice saves its plan once.
+
+use crate::catalog::RecipeCatalog;
+use crate::config::JobConfig;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::identity::UserId;
+use crate::recommender::Recommender;
+use crate::repo::{ClaimedJob, JobRepo, MealPlanRepo};
+use crate::routes::mealplan::{
+    generate_ai_meal_plan, regenerate_ai_meal_plan, MealPlanRequest, PlanToSave, UpdateMealPlanRequest,
+};
+use diesel::result::Error as DieselError;
+use diesel::QueryResult;
+use metrics::counter;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc, watch, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{error, info, info_span, warn, Instrument};
+use uuid::Uuid;
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_RUNNING: &str = "running";
+pub const STATUS_SUCCEEDED: &str = "succeeded";
+pub const STATUS_FAILED: &str = "failed";
+
+/// `ai_meal_plan` request.
+pub const KIND_GENERATE: &str = "generate";
+/// `update_meal_plan` request.
+pub const KIND_UPDATE: &str = "update";
+
+#[derive(Clone)]
+pub struct JobQueue {
+    sender: mpsc::Sender<Uuid>,
+    finished: broadcast::Sender<Uuid>,
+    permits: Arc<Semaphore>, // One per worker; all free means no job is running
+    workers: u32,
+    max_attempts: i32,
+    max_unfinished_per_user: usize,
+    stopping: watch::Sender<bool>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>, // The dispatcher and the sweep, awaited by `shutdown`
+}
+
+/// How often finished jobs past their retention are looked for.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+impl JobQueue {
+    /// The queue and the receiving end of its channel, with nothing running yet.
+    fn new(config: &JobConfig) -> (Self, mpsc::Receiver<Uuid>) {
+        let (sender, receiver) = mpsc::channel(config.queue_capacity.max(1));
+        let (finished, _) = broadcast::channel(256);
+        let workers = config.workers.max(1);
+        let (stopping, _) = watch::channel(false);
+
+        let queue = JobQueue {
+            sender,
+            finished,
+            permits: Arc::new(Semaphore::new(workers)),
+            workers: workers as u32,
+            max_attempts: config.max_attempts.max(1),
+            max_unfinished_per_user: config.max_unfinished_per_user.max(1),
+            stopping,
+            tasks: Arc::new(Mutex::new(Vec::new())),
+        };
+        (queue, receiver)
+    }
+
+    /// Starts the dispatcher on the current Tokio runtime with at most `config.workers`
+    /// jobs running at once, and the sweep that deletes old finished jobs.
+    pub fn start<D: Database>(
+        db: D,
+        recommender: Arc<dyn Recommender>,
+        catalog: Arc<RecipeCatalog>,
+        config: JobConfig,
+    ) -> Self {
+        let (queue, mut receiver) = JobQueue::new(&config);
+        let permits = queue.permits.clone();
+        let finished = queue.finished.clone();
+        let max_attempts = queue.max_attempts;
+        let mut stop = queue.stopping.subscribe();
+
+        let sweep = tokio::spawn(sweep_finished_jobs(db.clone(), config.retention, queue.stopping.subscribe()));
+
+        let dispatcher = tokio::spawn(async move {
+            loop {
+                // Once stopping, queued jobs are left pending for the next process
+                let job_id = tokio::select! {
+                    biased;
+                    _ = stop.wait_for(|stopping| *stopping) => break,
+                    job_id = receiver.recv() => match job_id {
+                        Some(job_id) => job_id,
+                        None => break,
+                    },
+                };
+                let permit = tokio::select! {
+                    biased;
+                    _ = stop.wait_for(|stopping| *stopping) => break,
+                    permit = permits.clone().acquire_owned() => permit.expect("job semaphore closed"),
+                };
+                let db = db.clone();
+                let recommender = recommender.clone();
+                let catalog = catalog.clone();
+                let finished = finished.clone();
+
+                tokio::spawn(async move {
+                    run_job(&db, recommender.as_ref(), &catalog, job_id, max_attempts)
+                        .instrument(info_span!("job", %job_id))
+                        .await;
+                    // Nobody may be waiting for this job; a send error just means no subscribers.
+                    let _ = finished.send(job_id);
+                    // Release the pool handle before the permit, so a drained queue holds none
+                    drop(db);
+                    drop(permit);
+                });
+            }
+        });
+
+        queue.tasks.lock().unwrap().extend([sweep, dispatcher]);
+        queue
+    }
+
+    /// Requeues jobs left unfinished by a previous process and returns how many
+    /// were requeued. Jobs out of attempts are marked failed.
+    pub async fn recover<D: Database>(&self, db: &D) -> AppResult<usize> {
+        let max_attempts = self.max_attempts;
+        let pending = db
+            .run(move |repo| reset_unfinished_jobs(repo, max_attempts).map_err(AppError::internal("Failed to recover meal plan jobs")))
+            .await?;
+
+        // More may be pending than the queue holds; they go in as workers make room
+        let count = pending.len();
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            for job_id in pending {
+                if sender.send(job_id).await.is_err() {
+                    break; // Stopped; the rest stay pending until the next start
+                }
+            }
+        });
+        Ok(count)
+    }
+
+    /// Stores a new pending job and queues it for a worker. Refused with a 429 when the
+    /// queue is full or the user already has as many unfinished jobs as allowed.
+    pub fn submit<R: JobRepo>(&self, repo: &mut R, user_id: UserId, kind: &str, request: Value) -> AppResult<Uuid> {
+        // Take the queue slot first, so a refused job leaves no row behind
+        let slot = match self.sender.try_reserve() {
+            Ok(slot) => Some(slot),
+            Err(TrySendError::Full(())) => {
+                counter!("meal_plan_jobs_rejected_total", "reason" => "queue_full").increment(1);
+                return Err(AppError::TooManyRequests("Too many jobs are queued; try again later".to_string()));
+            }
+            Err(TrySendError::Closed(())) => None,
+        };
+
+        // Not atomic with the insert: concurrent submissions may go one or two over
+        let unfinished = repo
+            .unfinished_job_count(user_id)
+            .map_err(AppError::internal("Failed to create job"))?;
+        if unfinished >= self.max_unfinished_per_user {
+            counter!("meal_plan_jobs_rejected_total", "reason" => "user_limit").increment(1);
+            return Err(AppError::TooManyRequests(format!(
+                "At most {} jobs may be unfinished at once; wait for one to finish",
+                self.max_unfinished_per_user
+            )));
+        }
+
+        let job_id = Uuid::new_v4();
+        repo.insert_job(job_id, user_id, kind, request)
+            .map_err(AppError::internal("Failed to create job"))?;
+
+        match slot {
+            Some(slot) => slot.send(job_id),
+            None => warn!(%job_id, "Job dispatcher stopped; job stays pending until restart"),
+        }
+        Ok(job_id)
+    }
+
+    /// Stops starting jobs and waits until the dispatcher, the sweep and the running
+    /// jobs have finished; after that the queue holds no database handle. Jobs
+    /// submitted or queued after this stay pending until the next start.
+    pub async fn shutdown(&self) {
+        let _ = self.stopping.send(true);
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        for task in tasks {
+            let _ = task.await;
+        }
+        let _ = self.permits.acquire_many(self.workers).await;
+    }
+
+    /// Jobs being run right now.
+    pub fn running(&self) -> usize {
+        self.workers as usize - self.permits.available_permits()
+    }
+
+    /// Subscribes to job completions, for callers that want to wait on a result.
+    pub fn subscribe(&self) -> broadcast::Receiver<Uuid> {
+        self.finished.subscribe()
+    }
+}
+
+pub fn is_finished(status: &str) -> bool {
+    status == STATUS_SUCCEEDED || status == STATUS_FAILED
+}
+
+/// Fails the jobs out of attempts, puts running ones back to pending and returns the pending ids.
+fn reset_unfinished_jobs<R: JobRepo>(repo: &mut R, max_attempts: i32) -> QueryResult<Vec<Uuid>> {
+    let exhausted = repo.fail_exhausted_jobs(max_attempts, &attempts_exhausted(max_attempts))?;
+    if exhausted > 0 {
+        warn!(count = exhausted, "Gave up on meal plan jobs out of attempts");
+        counter!("meal_plan_jobs_exhausted_total").increment(exhausted as u64);
+    }
+    repo.requeue_running_jobs()?;
+    repo.pending_job_ids()
+}
+
+fn attempts_exhausted(max_attempts: i32) -> String {
+    format!("Gave up after {} attempts; the job was interrupted each time", max_attempts)
+}
+
+/// Deletes finished jobs last updated more than `retention` ago, hourly until the queue stops.
+async fn sweep_finished_jobs<D: Database>(db: D, retention: Duration, mut stop: watch::Receiver<bool>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL.min(retention));
+
+    loop {
+        tokio::select! {
+            biased;
+            _ = stop.wait_for(|stopping| *stopping) => break,
+            _ = interval.tick() => {}
+        }
+
+        let deleted = db
+            .run(move |repo| {
+                repo.delete_finished_jobs(retention)
+                    .map_err(|err| AppError::Internal(err.to_string()))
+            })
+            .await;
+
+        match deleted {
+            Ok(0) => {}
+            Ok(count) => info!(count, "Deleted finished meal plan jobs past retention"),
+            Err(err) => error!(error = %err, "Failed to delete old meal plan jobs"),
+        }
+    }
+}
+
+async fn run_job<D: Database>(
+    db: &D,
+    recommender: &dyn Recommender,
+    catalog: &Arc<RecipeCatalog>,
+    job_id: Uuid,
+    max_attempts: i32,
+) {
+    let claimed = db
+        .run(move |repo| {
+            repo.claim_job(job_id, max_attempts)
+                .map_err(|err| AppError::Internal(err.to_string()))
+        })
+        .await;
+
+    let ClaimedJob { kind, request, attempt } = match claimed {
+        Ok(Some(claimed)) => claimed,
+        Ok(None) => return,
+        Err(err) => {
+            error!(error = %err, "Failed to claim job");
+            return;
+        }
+    };
+
+    info!(kind, attempt, "Running job");
+
+    let outcome = match kind.as_str() {
+        KIND_GENERATE => match serde_json::from_value::<MealPlanRequest>(request) {
+            Ok(payload) => generate_ai_meal_plan(db, recommender, catalog, payload)
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(format!("Invalid job request: {}", err)),
+        },
+        KIND_UPDATE => match serde_json::from_value::<UpdateMealPlanRequest>(request) {
+            Ok(payload) => regenerate_ai_meal_plan(db, recommender, catalog, payload)
+                .await
+                .map(|result| (result, None))
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(format!("Invalid job request: {}", err)),
+        },
+        other => Err(format!("Unknown job kind: {}", other)),
+    };
+
+    let recorded = db.run(move |repo| record_outcome(repo, job_id, attempt, outcome)).await;
+
+    match recorded {
+        Ok(Some(status)) => {
+            counter!("meal_plan_jobs_total", "kind" => kind, "status" => status).increment(1);
+            info!(status, "Job finished");
+        }
+        Ok(None) => warn!("Job was picked up again while it ran; dropped this run's result"),
+        Err(err) => error!(error = %err, "Failed to save job result"),
+    }
+}
+
+/// Records the outcome of run `attempt` of the job and returns the status written, or `None`
+/// if the job has moved on to another run. A plan to save is saved in the same transaction,
+/// so it is only written by the run whose result is recorded; if saving fails the job fails.
+fn record_outcome<R: JobRepo + MealPlanRepo>(
+    repo: &mut R,
+    job_id: Uuid,
+    attempt: i32,
+    outcome: Result<(Value, Option<PlanToSave>), String>,
+) -> AppResult<Option<&'static str>> {
+    let (mut result, to_save) = match outcome {
+        Ok(outcome) => outcome,
+        Err(message) => return finish(repo, job_id, attempt, STATUS_FAILED, None, Some(message)),
+    };
+    let Some(to_save) = to_save else {
+        return finish(repo, job_id, attempt, STATUS_SUCCEEDED, Some(result), None);
+    };
+
+    let mut save_error = None;
+    let saved = repo.atomically(|repo| {
+        if let Err(err) = to_save.save(repo, &mut result, "ai_job") {
+            save_error = Some(err);
+            return Err(DieselError::RollbackTransaction);
+        }
+        match repo.finish_job(job_id, attempt, STATUS_SUCCEEDED, Some(result.clone()), None)? {
+            0 => Err(DieselError::RollbackTransaction),
+            _ => Ok(()),
+        }
+    });
+
+    match (saved, save_error) {
+        (Ok(()), _) => Ok(Some(STATUS_SUCCEEDED)),
+        (Err(_), Some(err)) => finish(repo, job_id, attempt, STATUS_FAILED, None, Some(err.to_string())),
+        (Err(DieselError::RollbackTransaction), None) => Ok(None),
+        (Err(err), None) => Err(AppError::Internal(err.to_string())),
+    }
+}
+
+fn finish<R: JobRepo>(
+    repo: &mut R,
+    job_id: Uuid,
+    attempt: i32,
+    status: &'static str,
+    result: Option<Value>,
+    error: Option<String>,
+) -> AppResult<Option<&'static str>> {
+    let updated = repo
+        .finish_job(job_id, attempt, status, result, error)
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    Ok((updated > 0).then_some(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::CatalogRecipe;
+    use crate::identity::LineUserId;
+    use crate::recommender::MockRecommender;
+    use crate::repo::memory::{MemoryDb, MemoryRepo, MemoryUser};
+    use crate::routes::mealplan::{FoodMenu, Nutrition, OverwritePolicy, Recipe};
+    use serde_json::json;
+
+    const USER: UserId = UserId(1);
+
+    fn config() -> JobConfig {
+        JobConfig {
+            workers: 2,
+            max_attempts: 3,
+            retention: Duration::from_secs(24 * 60 * 60),
+            queue_capacity: 10,
+            max_unfinished_per_user: 3,
+        }
+    }
+
+    fn db() -> MemoryDb {
+        let mut repo = MemoryRepo::new();
+        repo.users.push(MemoryUser {
+            user_id: USER,
+            line_id: LineUserId("U1".to_string()),
+            allergy_ids: Default::default(),
+            limits: Nutrition::default(),
+        });
+        repo.recipes.push(CatalogRecipe {
+            menu: FoodMenu {
+                name: "Recipe 1".to_string(),
+                nutrition: Nutrition::default(),
+                recipe_id: 1,
+                recipe_img_link: Vec::new(),
+            },
+            allergy_ids: Vec::new(),
+        });
+        MemoryDb::new(repo)
+    }
+
+    fn start(db: &MemoryDb) -> JobQueue {
+        let recommender: Arc<dyn Recommender> = Arc::new(MockRecommender::new(json!({ "mealplans": [[{ "recipe_id": 1 }]] })));
+        let catalog = Arc::new(RecipeCatalog::new(Duration::ZERO));
+        JobQueue::start(db.clone(), recommender, catalog, config())
+    }
+
+    fn generate_request(persist: bool) -> Value {
+        json!({ "data": { "u_id": "U1", "days": 1, "generator": "ai", "persist": persist, "start_date": "2030-01-01" } })
+    }
+
+    fn job(db: &MemoryDb, job_id: Uuid) -> crate::models::MealPlanJob {
+        db.0.lock().unwrap().find_job(job_id).unwrap().unwrap()
+    }
+
+    async fn run_to_completion(db: &MemoryDb, queue: &JobQueue, kind: &str, request: Value) -> Uuid {
+        let mut finished = queue.subscribe();
+        let job_id = queue.submit(&mut *db.0.lock().unwrap(), USER, kind, request).unwrap();
+        assert_eq!(job(db, job_id).status, STATUS_PENDING);
+        while finished.recv().await.unwrap() != job_id {}
+        job_id
+    }
+
+    #[test]
+    fn only_succeeded_and_failed_jobs_are_finished() {
+        assert!(is_finished(STATUS_SUCCEEDED));
+        assert!(is_finished(STATUS_FAILED));
+        assert!(!is_finished(STATUS_PENDING));
+        assert!(!is_finished(STATUS_RUNNING));
+    }
+
+    #[tokio::test]
+    async fn submitted_jobs_run_and_save_their_plan() {
+        let db = db();
+        let queue = start(&db);
+
+        let job_id = run_to_completion(&db, &queue, KIND_GENERATE, generate_request(true)).await;
+
+        let succeeded = job(&db, job_id);
+        assert_eq!(succeeded.status, STATUS_SUCCEEDED);
+        assert_eq!(succeeded.attempts, 1);
+        assert_eq!(succeeded.error, None);
+        let result = succeeded.result.unwrap();
+        assert_eq!(result["start_date"], json!("2030-01-01"));
+        assert_eq!(result["persisted"], json!(true));
+        assert_eq!(db.0.lock().unwrap().meal_plans.len(), 1);
+        queue.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn failing_jobs_are_marked_failed() {
+        let db = db();
+        let queue = start(&db);
+
+        let job_id = run_to_completion(&db, &queue, "unknown", json!({})).await;
+
+        let failed = job(&db, job_id);
+        assert_eq!(failed.status, STATUS_FAILED);
+        assert_eq!(failed.result, None);
+        assert_eq!(failed.error.as_deref(), Some("Unknown job kind: unknown"));
+
+        // A plan that cannot be saved fails the job and leaves no days behind
+        let job_id = run_to_completion(&db, &queue, KIND_GENERATE, json!({
+            "data": { "u_id": "U1", "days": 1, "generator": "ai", "persist": true, "start_date": "not a date" }
+        }))
+        .await;
+        assert_eq!(job(&db, job_id).status, STATUS_FAILED);
+        assert!(db.0.lock().unwrap().meal_plans.is_empty());
+        queue.shutdown().await;
+    }
+
+    #[test]
+    fn a_run_that_lost_the_job_saves_nothing() {
+        let mut repo = MemoryRepo::new();
+        let job_id = Uuid::new_v4();
+        repo.insert_job(job_id, USER, KIND_GENERATE, generate_request(true)).unwrap();
+        let first = repo.claim_job(job_id, 3).unwrap().unwrap();
+        // A restart put the job back and another worker picked it up
+        repo.requeue_running_jobs().unwrap();
+        let second = repo.claim_job(job_id, 3).unwrap().unwrap();
+        assert_eq!((first.attempt, second.attempt), (1, 2));
+
+        let plan = || PlanToSave {
+            user_id: USER,
+            requested_start: Some("2030-01-01".to_string()),
+            mealplans: vec![vec![Recipe { recipe_id: Some(1), meal_time: Some(1) }]],
+            overwrite: OverwritePolicy::default(),
+        };
+        let stale = record_outcome(&mut repo, job_id, first.attempt, Ok((json!({}), Some(plan())))).unwrap();
+        assert_eq!(stale, None);
+        assert!(repo.meal_plans.is_empty());
+
+        let current = record_outcome(&mut repo, job_id, second.attempt, Ok((json!({}), Some(plan())))).unwrap();
+        assert_eq!(current, Some(STATUS_SUCCEEDED));
+        assert_eq!(repo.meal_plans.len(), 1);
+
+        // Delivered again after it finished
+        let again = record_outcome(&mut repo, job_id, second.attempt, Ok((json!({}), Some(plan())))).unwrap();
+        assert_eq!(again, None);
+        assert_eq!(repo.meal_plans.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn users_are_limited_in_unfinished_jobs() {
+        let (queue, _receiver) = JobQueue::new(&JobConfig { max_unfinished_per_user: 2, ..config() });
+        let mut repo = MemoryRepo::new();
+
+        queue.submit(&mut repo, USER, KIND_GENERATE, json!({})).unwrap();
+        let job_id = queue.submit(&mut repo, USER, KIND_GENERATE, json!({})).unwrap();
+        let err = queue.submit(&mut repo, USER, KIND_GENERATE, json!({})).unwrap_err();
+        assert!(matches!(err, AppError::TooManyRequests(_)), "{:?}", err);
+        assert!(queue.submit(&mut repo, UserId(2), KIND_GENERATE, json!({})).is_ok());
+
+        // Finished jobs do not count
+        repo.claim_job(job_id, 3).unwrap();
+        repo.finish_job(job_id, 1, STATUS_SUCCEEDED, None, None).unwrap();
+        assert!(queue.submit(&mut repo, USER, KIND_GENERATE, json!({})).is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_refuses_jobs() {
+        // Nothing takes jobs off the queue
+        let (queue, _receiver) = JobQueue::new(&JobConfig { queue_capacity: 1, ..config() });
+        let mut repo = MemoryRepo::new();
+
+        queue.submit(&mut repo, USER, KIND_GENERATE, json!({})).unwrap();
+        let err = queue.submit(&mut repo, UserId(2), KIND_GENERATE, json!({})).unwrap_err();
+        assert!(matches!(err, AppError::TooManyRequests(_)), "{:?}", err);
+        assert_eq!(repo.jobs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn recover_requeues_unfinished_jobs_and_fails_exhausted_ones() {
+        let (queue, mut receiver) = JobQueue::new(&config());
+        let db = MemoryDb::default();
+        let (pending, interrupted, exhausted, finished) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        {
+            let repo = &mut *db.0.lock().unwrap();
+            for (job_id, status, attempts) in [
+                (pending, STATUS_PENDING, 0),
+                (interrupted, STATUS_RUNNING, 1),
+                (exhausted, STATUS_RUNNING, 3),
+                (finished, STATUS_SUCCEEDED, 1),
+            ] {
+                repo.insert_job(job_id, USER, KIND_GENERATE, json!({})).unwrap();
+                let job = repo.jobs.last_mut().unwrap();
+                job.status = status.to_string();
+                job.attempts = attempts;
+            }
+        }
+
+        assert_eq!(queue.recover(&db).await.unwrap(), 2);
+
+        assert_eq!(receiver.recv().await, Some(pending));
+        assert_eq!(receiver.recv().await, Some(interrupted));
+        assert_eq!(job(&db, interrupted).status, STATUS_PENDING);
+        assert_eq!(job(&db, exhausted).status, STATUS_FAILED);
+        assert_eq!(job(&db, exhausted).error, Some(attempts_exhausted(3)));
+        assert_eq!(job(&db, finished).status, STATUS_SUCCEEDED);
+    }
+
+    #[tokio::test]
+    async fn sweep_deletes_finished_jobs_past_retention() {
+        let db = MemoryDb::default();
+        let (old_finished, new_finished, old_pending) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        {
+            let repo = &mut *db.0.lock().unwrap();
+            let two_days_ago = chrono::Utc::now().naive_utc() - chrono::Duration::days(2);
+            for (job_id, status, updated_at) in [
+                (old_finished, STATUS_FAILED, Some(two_days_ago)),
+                (new_finished, STATUS_SUCCEEDED, None),
+                (old_pending, STATUS_PENDING, Some(two_days_ago)),
+            ] {
+                repo.insert_job(job_id, USER, KIND_GENERATE, json!({})).unwrap();
+                let job = repo.jobs.last_mut().unwrap();
+                job.status = status.to_string();
+                if let Some(updated_at) = updated_at {
+                    job.updated_at = updated_at;
+                }
+            }
+        }
+
+        // The first sweep runs right away
+        let (stopping, stop) = watch::channel(false);
+        let sweep = tokio::spawn(sweep_finished_jobs(db.clone(), config().retention, stop));
+        let swept = async {
+            while db.0.lock().unwrap().jobs.len() == 3 {
+                tokio::task::yield_now().await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), swept).await.unwrap();
+        stopping.send(true).unwrap();
+        sweep.await.unwrap();
+
+        let remaining: Vec<Uuid> = db.0.lock().unwrap().jobs.iter().map(|job| job.job_id).collect();
+        assert_eq!(remaining, vec![new_finished, old_pending]);
+    }
+}
@@ -1,15 +1,31 @@
 use diesel::prelude::*;
-use dotenvy::dotenv;
-use std::env;
 
+pub mod admin;
+pub mod auth;
+pub mod authz;
+pub mod catalog;
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod identity;
+pub mod jobs;
+pub mod line_auth;
+pub mod logging;
 pub mod models;
+pub mod monitoring;
+pub mod openapi;
+pub mod planner;
+pub mod recipe_io;
+pub mod recommender;
+pub mod repo;
 pub mod schema;
 pub mod routes;
+pub mod service;
 
 pub fn establish_connection() -> PgConnection {
-    dotenv().ok();
+    let settings = config::Settings::load().unwrap_or_else(|err| panic!("{}", err));
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database_url = settings.get("DATABASE_URL").expect("DATABASE_URL must be set");
     PgConnection::establish(&database_url)
         .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
 }
\ No newline at end of file
@@ -0,0 +1,439 @@
+//! LINE Login for the user-facing routes.
+//!
+//! The LIFF app sends the user's LINE ID token as `Authorization: Bearer <token>`.
+//! [`LineUser`] checks the signature against LINE's published keys (JWKS), the
+//! audience (our channel id), the issuer and the expiry, and yields the LINE id
+//! from `sub`. Handlers use that id instead of trusting one in the request body.
+//!
+//! Keys come from `LINE_JWKS_URL` (LINE's endpoint by default), cached and
+//! refetched when a token names a key we have not seen, or from `LINE_JWKS_FILE`.
+//! Only one request fetches at a time. If a refetch fails, the cached keys stay
+//! in use and the next attempt waits, longer after each failure.
+//! `dev/line-test-jwks.json` is a key set for local development whose private
+//! key is checked in next to it (`kidney-admin line-test-token` signs with it),
+//! so it must never be configured in production.
+
+use crate::config::Settings;
+use crate::error::AppError;
+use crate::identity::LineUserId;
+use axum::extract::FromRequestParts;
+use axum::http::header::AUTHORIZATION;
+use axum::http::request::Parts;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{info, warn};
+
+pub const DEFAULT_LINE_JWKS_URL: &str = "https://api.line.me/oauth2/v2.1/certs";
+pub const DEFAULT_LINE_ISSUER: &str = "https://access.line.me";
+/// Key id of the checked-in development key set.
+pub const TEST_KEY_ID: &str = "kidney-local-test";
+
+/// How long fetched keys are trusted before they are fetched again.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// Shortest time between two fetches, so tokens with made-up key ids cannot hammer LINE.
+/// Also the wait after a failed refetch, doubling with each further failure up to `JWKS_MAX_AGE`.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum LineAuthError {
+    /// The token is malformed, signed with an unknown key or fails validation.
+    InvalidToken(String),
+    /// The key set could not be loaded.
+    Jwks(String),
+}
+
+impl fmt::Display for LineAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineAuthError::InvalidToken(reason) => write!(f, "invalid ID token: {}", reason),
+            LineAuthError::Jwks(reason) => write!(f, "failed to load LINE keys: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LineAuthError {}
+
+#[derive(Debug, Clone)]
+pub enum JwksSource {
+    Url(String),
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct LineAuthConfig {
+    pub channel_id: String, // Expected `aud`
+    pub issuer: String,
+    pub jwks: JwksSource,
+}
+
+impl LineAuthConfig {
+    /// Reads `LINE_CHANNEL_ID` (required), `LINE_ISSUER` and `LINE_JWKS_FILE` or `LINE_JWKS_URL`.
+    pub fn from_settings(settings: &mut Settings) -> Self {
+        let channel_id = settings.required("LINE_CHANNEL_ID");
+        let issuer = settings.string("LINE_ISSUER", DEFAULT_LINE_ISSUER);
+        let jwks = match settings.get("LINE_JWKS_FILE") {
+            Some(path) => JwksSource::File(PathBuf::from(path)),
+            None => JwksSource::Url(settings.string("LINE_JWKS_URL", DEFAULT_LINE_JWKS_URL)),
+        };
+
+        LineAuthConfig { channel_id, issuer, jwks }
+    }
+}
+
+/// The claims of a LINE ID token we use.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LineClaims {
+    pub iss: String,
+    pub sub: String, // LINE user id
+    pub aud: String,
+    pub exp: u64,
+    pub iat: u64,
+    pub name: Option<String>,
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+    failures: u32, // Refetches that failed since `fetched_at`
+    retry_at: Option<Instant>, // After a failure, the keys are used as they are until then
+}
+
+impl CachedKeys {
+    fn new(keys: JwkSet) -> Self {
+        CachedKeys { keys, fetched_at: Instant::now(), failures: 0, retry_at: None }
+    }
+
+    fn backing_off(&self) -> bool {
+        self.retry_at.is_some_and(|retry_at| Instant::now() < retry_at)
+    }
+
+    fn refetch_failed(&mut self) {
+        self.failures += 1;
+        let backoff = JWKS_MIN_REFRESH.saturating_mul(1 << (self.failures - 1).min(16)).min(JWKS_MAX_AGE);
+        self.retry_at = Some(Instant::now() + backoff);
+    }
+}
+
+/// Verifies LINE ID tokens; shared by all requests through an `Extension<Arc<LineAuth>>`.
+pub struct LineAuth {
+    config: LineAuthConfig,
+    client: Client,
+    keys: RwLock<Option<CachedKeys>>,
+    refresh: Mutex<()>, // Held while fetching, so concurrent requests wait for one fetch
+}
+
+impl LineAuth {
+    pub fn new(config: LineAuthConfig) -> Result<Self, reqwest::Error> {
+        let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+        Ok(LineAuth {
+            config,
+            client,
+            keys: RwLock::new(None),
+            refresh: Mutex::new(()),
+        })
+    }
+
+    pub fn config(&self) -> &LineAuthConfig {
+        &self.config
+    }
+
+    /// Loads the key set now, so a broken `LINE_JWKS_FILE` or URL shows up at startup.
+    pub async fn preload(&self) -> Result<usize, LineAuthError> {
+        let keys = self.load().await?;
+        if keys.find(TEST_KEY_ID).is_some() {
+            warn!("LINE key set contains the development test key; anyone can sign ID tokens for it");
+        }
+        let count = keys.keys.len();
+        *self.keys.write().await = Some(CachedKeys::new(keys));
+        Ok(count)
+    }
+
+    /// Checks the token and returns its claims.
+    pub async fn verify(&self, token: &str) -> Result<LineClaims, LineAuthError> {
+        let header = decode_header(token).map_err(|err| LineAuthError::InvalidToken(err.to_string()))?;
+        // Only asymmetric algorithms: the key set is public
+        if !matches!(header.alg, Algorithm::ES256 | Algorithm::RS256) {
+            return Err(LineAuthError::InvalidToken(format!("unsupported algorithm {:?}", header.alg)));
+        }
+        let kid = header.kid.ok_or_else(|| LineAuthError::InvalidToken("missing key id".to_string()))?;
+
+        let key = self.decoding_key(&kid).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.channel_id]);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_required_spec_claims(&["exp", "aud", "iss", "sub"]);
+
+        decode::<LineClaims>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|err| LineAuthError::InvalidToken(err.to_string()))
+    }
+
+    /// Key `kid` from the cache, refetching the key set when it is stale or lacks the key.
+    async fn decoding_key(&self, kid: &str) -> Result<DecodingKey, LineAuthError> {
+        if let Some(key) = self.cached_key(kid).await {
+            return key;
+        }
+
+        // Requests that waited here find the key set the first one fetched
+        let _refreshing = self.refresh.lock().await;
+        if let Some(key) = self.cached_key(kid).await {
+            return key;
+        }
+
+        match self.load().await {
+            Ok(keys) => {
+                let key = keys.find(kid).map(to_decoding_key).unwrap_or_else(|| Err(unknown_key(kid)));
+                *self.keys.write().await = Some(CachedKeys::new(keys));
+                key
+            }
+            Err(err) => match self.keys.write().await.as_mut() {
+                Some(cached) => {
+                    cached.refetch_failed();
+                    warn!(error = %err, failures = cached.failures, "Failed to refetch LINE keys; using the cached ones");
+                    cached.keys.find(kid).map(to_decoding_key).unwrap_or_else(|| Err(unknown_key(kid)))
+                }
+                None => Err(err),
+            },
+        }
+    }
+
+    /// The answer the cache gives for `kid`, or `None` if the key set should be fetched first.
+    async fn cached_key(&self, kid: &str) -> Option<Result<DecodingKey, LineAuthError>> {
+        let cached = self.keys.read().await;
+        let cached = cached.as_ref()?;
+        let backing_off = cached.backing_off();
+        let fresh = cached.fetched_at.elapsed() < JWKS_MAX_AGE || backing_off;
+        let recent = cached.fetched_at.elapsed() < JWKS_MIN_REFRESH || backing_off;
+        match cached.keys.find(kid) {
+            Some(jwk) if fresh => Some(to_decoding_key(jwk)),
+            None if recent => Some(Err(unknown_key(kid))),
+            _ => None,
+        }
+    }
+
+    async fn load(&self) -> Result<JwkSet, LineAuthError> {
+        match &self.config.jwks {
+            JwksSource::File(path) => {
+                let contents = tokio::fs::read(path)
+                    .await
+                    .map_err(|err| LineAuthError::Jwks(format!("{}: {}", path.display(), err)))?;
+                serde_json::from_slice(&contents).map_err(|err| LineAuthError::Jwks(format!("{}: {}", path.display(), err)))
+            }
+            JwksSource::Url(url) => {
+                let response = self
+                    .client
+                    .get(url)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|err| LineAuthError::Jwks(err.to_string()))?;
+                response.json().await.map_err(|err| LineAuthError::Jwks(err.to_string()))
+            }
+        }
+    }
+}
+
+/// Signs an ID token for `line_user_id` with the development key (`dev/line-test-key.pem`),
+/// accepted by a server whose `LINE_JWKS_FILE` is `dev/line-test-jwks.json`.
+pub fn sign_test_token(
+    key_pem: &[u8],
+    config: &LineAuthConfig,
+    line_user_id: &LineUserId,
+    ttl: Duration,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let claims = LineClaims {
+        iss: config.issuer.clone(),
+        sub: line_user_id.0.clone(),
+        aud: config.channel_id.clone(),
+        exp: now + ttl.as_secs(),
+        iat: now,
+        name: None,
+    };
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(TEST_KEY_ID.to_string());
+    encode(&header, &claims, &EncodingKey::from_ec_pem(key_pem)?)
+}
+
+fn unknown_key(kid: &str) -> LineAuthError {
+    LineAuthError::InvalidToken(format!("unknown key id {}", kid))
+}
+
+fn to_decoding_key(jwk: &jsonwebtoken::jwk::Jwk) -> Result<DecodingKey, LineAuthError> {
+    DecodingKey::from_jwk(jwk).map_err(|err| LineAuthError::Jwks(err.to_string()))
+}
+
+/// The LINE user who signed the request, from a verified ID token.
+#[derive(Debug, Clone)]
+pub struct LineUser(pub LineUserId);
+
+impl<S: Send + Sync> FromRequestParts<S> for LineUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let auth = parts
+            .extensions
+            .get::<Arc<LineAuth>>()
+            .cloned()
+            .ok_or_else(|| AppError::Internal("LINE authentication is not configured".to_string()))?;
+
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| AppError::Unauthorized("Missing LINE ID token".to_string()))?;
+
+        match auth.verify(token.trim()).await {
+            Ok(claims) => Ok(LineUser(LineUserId(claims.sub))),
+            Err(LineAuthError::InvalidToken(reason)) => {
+                info!(%reason, "Rejected LINE ID token");
+                Err(AppError::Unauthorized("Invalid or expired LINE ID token".to_string()))
+            }
+            Err(err @ LineAuthError::Jwks(_)) => Err(AppError::Upstream(err.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_KEY: &[u8] = include_bytes!("../dev/line-test-key.pem");
+    const TEST_JWKS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/dev/line-test-jwks.json");
+
+    fn config(channel_id: &str, jwks: JwksSource) -> LineAuthConfig {
+        LineAuthConfig {
+            channel_id: channel_id.to_string(),
+            issuer: DEFAULT_LINE_ISSUER.to_string(),
+            jwks,
+        }
+    }
+
+    fn test_auth(channel_id: &str) -> LineAuth {
+        LineAuth::new(config(channel_id, JwksSource::File(PathBuf::from(TEST_JWKS)))).unwrap()
+    }
+
+    fn token_for(channel_id: &str, ttl: Duration) -> String {
+        let config = config(channel_id, JwksSource::File(PathBuf::from(TEST_JWKS)));
+        sign_test_token(TEST_KEY, &config, &LineUserId("U1234".to_string()), ttl).unwrap()
+    }
+
+    #[tokio::test]
+    async fn valid_tokens_yield_the_line_id() {
+        let auth = test_auth("local");
+
+        let claims = auth.verify(&token_for("local", Duration::from_secs(600))).await.unwrap();
+        assert_eq!(claims.sub, "U1234");
+        assert_eq!(claims.aud, "local");
+    }
+
+    #[tokio::test]
+    async fn tokens_for_another_channel_are_rejected() {
+        let auth = test_auth("local");
+
+        let err = auth.verify(&token_for("other-channel", Duration::from_secs(600))).await.unwrap_err();
+        assert!(matches!(err, LineAuthError::InvalidToken(_)), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_rejected() {
+        let auth = test_auth("local");
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        // Past the 60s leeway jsonwebtoken allows for clock skew
+        let claims = LineClaims {
+            iss: DEFAULT_LINE_ISSUER.to_string(),
+            sub: "U1234".to_string(),
+            aud: "local".to_string(),
+            exp: now - 120,
+            iat: now - 720,
+            name: None,
+        };
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(TEST_KEY_ID.to_string());
+        let token = encode(&header, &claims, &EncodingKey::from_ec_pem(TEST_KEY).unwrap()).unwrap();
+
+        let err = auth.verify(&token).await.unwrap_err();
+        assert!(matches!(err, LineAuthError::InvalidToken(_)), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn unknown_key_ids_refetch_the_key_set() {
+        // Starts without the test key, as if LINE had rotated keys since the last fetch
+        let path = std::env::temp_dir().join(format!("line-jwks-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"keys": []}"#).unwrap();
+        let auth = LineAuth::new(config("local", JwksSource::File(path.clone()))).unwrap();
+        assert_eq!(auth.preload().await.unwrap(), 0);
+
+        std::fs::copy(TEST_JWKS, &path).unwrap();
+        let token = token_for("local", Duration::from_secs(600));
+
+        // Just fetched, so the unknown key id is refused without another fetch
+        let err = auth.verify(&token).await.unwrap_err();
+        assert!(err.to_string().contains("unknown key id"), "{}", err);
+
+        // Once the cache is older than the refresh interval, the key set is fetched again
+        if let Some(cached) = auth.keys.write().await.as_mut() {
+            cached.fetched_at -= JWKS_MIN_REFRESH + Duration::from_secs(1);
+        }
+        let claims = auth.verify(&token).await.unwrap();
+        assert_eq!(claims.sub, "U1234");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale_keys_are_used_while_the_source_fails() {
+        let path = std::env::temp_dir().join(format!("line-jwks-stale-{}.json", std::process::id()));
+        std::fs::copy(TEST_JWKS, &path).unwrap();
+        let auth = LineAuth::new(config("local", JwksSource::File(path.clone()))).unwrap();
+        assert_eq!(auth.preload().await.unwrap(), 1);
+        let token = token_for("local", Duration::from_secs(600));
+
+        // Past the cache's age with the source gone, the cached key still verifies
+        std::fs::remove_file(&path).unwrap();
+        if let Some(cached) = auth.keys.write().await.as_mut() {
+            cached.fetched_at -= JWKS_MAX_AGE + Duration::from_secs(1);
+        }
+        assert_eq!(auth.verify(&token).await.unwrap().sub, "U1234");
+        let retry_at = auth.keys.read().await.as_ref().and_then(|cached| cached.retry_at).unwrap();
+        assert!(retry_at > Instant::now() + JWKS_MIN_REFRESH - Duration::from_secs(1));
+
+        // Backing off, the source is not read again even though it now lacks the key
+        std::fs::write(&path, r#"{"keys": []}"#).unwrap();
+        assert_eq!(auth.verify(&token).await.unwrap().sub, "U1234");
+
+        // Once the wait is over it is, and the rotated-out key is gone
+        if let Some(cached) = auth.keys.write().await.as_mut() {
+            cached.retry_at = Some(Instant::now());
+        }
+        let err = auth.verify(&token).await.unwrap_err();
+        assert!(err.to_string().contains("unknown key id"), "{}", err);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_refetches_back_off_longer_each_time() {
+        let mut cached = CachedKeys::new(JwkSet { keys: Vec::new() });
+        let waits: Vec<Duration> = (0..10)
+            .map(|_| {
+                cached.refetch_failed();
+                cached.retry_at.unwrap() - Instant::now()
+            })
+            .collect();
+
+        assert!(waits[0] <= JWKS_MIN_REFRESH && waits[0] > JWKS_MIN_REFRESH - Duration::from_secs(1));
+        assert!(waits[1] > JWKS_MIN_REFRESH * 2 - Duration::from_secs(1));
+        assert!(waits[9] <= JWKS_MAX_AGE && waits[9] > JWKS_MAX_AGE - Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,179 @@
+//! Logging and request tracing.
+//!
+//! Logs go through `tracing`, as JSON lines by default (`LOG_FORMAT=text` for
+//! local development), filtered by `LOG_LEVEL` directives such as
+//! `info,kidney_diesel::jobs=debug`. Every request runs in a `request` span
+//! carrying its `x-request-id` (generated when the client sends none and echoed
+//! in the response), so all lines logged while handling it can be found together.
+//!
+//! Users' health data must not end up in logs: log ids and counts, not
+//! payloads. When a payload is worth logging at debug level, pass it through
+//! [`redact`] first.
+
+use axum::extract::MatchedPath;
+use axum::http::Request;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+/// Header carrying the request id, set by `SetRequestIdLayer` before tracing starts.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// JSON keys whose values are personal health data, replaced by [`redact`].
+pub const REDACTED_KEYS: [&str; 14] = [
+    "nutrition",
+    "nutrition_limit_per_day",
+    "nutrients",
+    "calories",
+    "weight",
+    "height",
+    "birthdate",
+    "gender",
+    "kidney_level",
+    "kidney_dialysis",
+    "diseases",
+    "allergies",
+    "food_conditions",
+    "medicines",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Json,
+    Text,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "json" => Ok(LogFormat::Json),
+            "text" => Ok(LogFormat::Text),
+            _ => Err(format!("unknown format '{}', expected json or text", value)),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogFormat::Json => "json",
+            LogFormat::Text => "text",
+        })
+    }
+}
+
+/// Installs the global subscriber; `filter` has been checked by [`crate::config::check_log_level`].
+pub fn init(filter: &str, format: LogFormat) {
+    let filter = EnvFilter::try_new(filter).unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_target(true);
+    match format {
+        LogFormat::Json => builder.json().with_current_span(true).with_span_list(false).init(),
+        LogFormat::Text => builder.init(),
+    }
+}
+
+/// Span for one request, for `TraceLayer::make_span_with`. Uses the route pattern
+/// rather than the path, which can hold ids and emails.
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-");
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        .unwrap_or("unmatched");
+
+    tracing::info_span!("request", request_id, method = %request.method(), route)
+}
+
+/// A copy of `value` with the values of [`REDACTED_KEYS`] replaced, at any depth.
+pub fn redact(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| {
+                    let value = if REDACTED_KEYS.contains(&key.as_str()) {
+                        Value::String("[redacted]".to_string())
+                    } else {
+                        redact(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn health_data_is_redacted_at_any_depth() {
+        let request = json!({
+            "data": {
+                "user_info": {
+                    "weight": 62.5,
+                    "birthdate": "1960-04-01",
+                    "gender": "female",
+                    "kidney_level": 4,
+                    "kidney_dialysis": false,
+                    "diseases": ["diabetes"],
+                },
+                "days": 3,
+            },
+        });
+
+        assert_eq!(
+            redact(&request),
+            json!({
+                "data": {
+                    "user_info": {
+                        "weight": "[redacted]",
+                        "birthdate": "[redacted]",
+                        "gender": "[redacted]",
+                        "kidney_level": "[redacted]",
+                        "kidney_dialysis": "[redacted]",
+                        "diseases": "[redacted]",
+                    },
+                    "days": 3,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn objects_in_arrays_are_redacted() {
+        let plan = json!([
+            [{ "recipe_id": 1, "nutrition": { "protein": 12.0 } }],
+            [{ "recipe_id": 2, "calories": 450 }, "lunch", 3],
+        ]);
+
+        assert_eq!(
+            redact(&plan),
+            json!([
+                [{ "recipe_id": 1, "nutrition": "[redacted]" }],
+                [{ "recipe_id": 2, "calories": "[redacted]" }, "lunch", 3],
+            ])
+        );
+    }
+
+    #[test]
+    fn other_keys_pass_through_unchanged() {
+        let body = json!({ "status": "success", "mealplans": [[{ "recipe_id": 7, "meal_time": 2 }]], "note": null, "user_line_id": "U1" });
+
+        assert_eq!(redact(&body), body);
+    }
+}
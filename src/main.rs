@@ -1,64 +1,144 @@
 use axum::{
-    routing::{delete, get, patch, post}, Extension, Router
+    middleware, routing::{delete, get, patch, post}, Extension, Router
 };
-use tower_http::cors::{Any, CorsLayer};
-use dotenvy::dotenv;
-use serde::Serialize;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{error, info, warn, Level};
+use utoipa_swagger_ui::SwaggerUi;
 use std::sync::Arc;
 use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::time::Instant;
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 
-mod schema;
-mod models;
-mod routes; // Declare the routes module
-
-use routes::ingredient::{get_ingredients, create_ingredient}; // Import create_ingredient
-use routes::recipe::{update_recipe, delete_recipe};
-use routes::mealplan::{create_meal_plan, get_meal_plan, user_already_eat, edit_meal_plan, ai_meal_plan, update_meal_plan}; // Import edit_meal_plan
+use kidney_diesel::auth::{require_role, AuthConfig, Role};
+use kidney_diesel::catalog::RecipeCatalog;
+use kidney_diesel::config::Config;
+use kidney_diesel::db::{run_migrations, Db};
+use kidney_diesel::error::AppError;
+use kidney_diesel::jobs::JobQueue;
+use kidney_diesel::line_auth::LineAuth;
+use kidney_diesel::logging;
+use kidney_diesel::monitoring::{self, track_requests};
+use kidney_diesel::openapi::get_openapi;
+use kidney_diesel::recommender::{HttpRecommender, MockRecommender, Recommender};
+use kidney_diesel::routes::ingredient::{get_ingredients, create_ingredient}; // Import create_ingredient
+use kidney_diesel::routes::recipe::{update_recipe, delete_recipe};
+use kidney_diesel::routes::admin::{
+    admin_login, create_admin, current_admin, delete_admin, export_recipe_file, get_admins, import_recipe_file,
+};
+use kidney_diesel::routes::mealplan::{create_meal_plan, get_meal_plan, user_already_eat, edit_meal_plan, delete_meal_plan, ai_meal_plan, update_meal_plan}; // Import edit_meal_plan
+use kidney_diesel::routes::job::{submit_ai_meal_plan_job, submit_update_meal_plan_job, get_meal_plan_job};
+use kidney_diesel::routes::monitoring::{get_health, get_metrics, get_readiness};
+use kidney_diesel::routes::v2;
+use kidney_diesel::routes::template::{
+    create_meal_plan_template, save_meal_plan_as_template, get_meal_plan_templates, apply_meal_plan_template,
+    clone_meal_plan, delete_meal_plan_template, apply_meal_plan_template_for_user, clone_meal_plan_for_user,
+};
 
 use std::env;
 
-// Define a struct to represent the ingredient table rows
-#[derive(Serialize, Queryable)]
-struct Ingredient {
-    id: i32,
-    name: String,
-    quantity: i32,
-}
-
 #[tokio::main]
 async fn main() {
-    dotenv().expect("Failed to load .env file");
-
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let server_address = format!("0.0.0.0:{}", port);
+    // Environment, .env and CONFIG_FILE; every problem is reported before exiting.
+    // Logging is configured there too, so these errors go straight to stderr.
+    let config = Config::load().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    logging::init(&config.log_level, config.log_format);
+    info!("{}", config);
 
-    let database_url = std::env::var("DATABASE_URL").unwrap_or_else(|_| {
-        eprintln!("DATABASE_URL not set. Exiting.");
+    // Before anything records a metric; scraped at /metrics
+    let metrics = monitoring::install().unwrap_or_else(|err| {
+        error!(error = %err, "Failed to install metrics recorder");
         std::process::exit(1);
     });
 
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
+    let manager = ConnectionManager::<PgConnection>::new(config.database.url.clone());
     let db_pool = r2d2::Pool::builder()
-        .max_size(16)
+        .max_size(config.database.pool_max_size)
+        .min_idle(config.database.pool_min_idle)
+        .connection_timeout(config.database.connect_timeout)
+        .idle_timeout(Some(config.database.idle_timeout))
         .build(manager)
         .unwrap_or_else(|err| {
-            eprintln!("Failed to create database pool: {}", err);
+            error!(error = %err, "Failed to create database pool");
             std::process::exit(1);
         });
 
-    let listener = TcpListener::bind(&server_address).await.unwrap_or_else(|err| {
-        eprintln!("Failed to bind to {}: {}", server_address, err);
+    // `--migrate` applies pending migrations and exits; RUN_MIGRATIONS=true applies them before serving
+    let migrate_only = env::args().skip(1).any(|arg| arg == "--migrate");
+    if migrate_only || config.run_migrations {
+        let applied = db_pool
+            .get()
+            .map_err(|err| err.to_string())
+            .and_then(|mut conn| run_migrations(&mut conn).map_err(|err| err.to_string()));
+        match applied {
+            Ok(versions) if versions.is_empty() => info!("Database schema is up to date"),
+            Ok(versions) => info!(versions = %versions.join(", "), "Applied migrations"),
+            Err(err) => {
+                error!(error = %err, "Failed to run migrations");
+                std::process::exit(1);
+            }
+        }
+        if migrate_only {
+            return;
+        }
+    }
+
+    let server_address = config.server.socket_addr();
+    let listener = TcpListener::bind(server_address).await.unwrap_or_else(|err| {
+        error!(address = %server_address, error = %err, "Failed to bind");
         std::process::exit(1);
     });
 
-    println!("Listening on {}", listener.local_addr().unwrap());
+    info!(address = %listener.local_addr().unwrap(), "Listening");
 
-    let db_pool = Arc::new(db_pool);
+    let db = Db::new(db_pool);
+
+    // AI_RECOMMENDER_URL=mock answers every AI call with an empty plan, for local development
+    let recommender: Arc<dyn Recommender> = if config.recommender.base_url == "mock" {
+        Arc::new(MockRecommender::new(serde_json::json!({ "mealplans": [] })))
+    } else {
+        Arc::new(HttpRecommender::new(config.recommender.clone()).unwrap_or_else(|err| {
+            error!(error = %err, "Failed to create recommender client");
+            std::process::exit(1);
+        }))
+    };
     
+    let catalog = Arc::new(RecipeCatalog::new(config.catalog_ttl));
+
+    // Background AI generation: bounded worker pool, resuming jobs left over from the last run
+    let job_queue = JobQueue::start(db.clone(), recommender.clone(), catalog.clone(), config.jobs.clone());
+    match job_queue.recover(&db).await {
+        Ok(0) => {}
+        Ok(count) => info!(count, "Requeued unfinished meal plan jobs"),
+        Err(err) => error!(error = %err, "Failed to recover meal plan jobs"),
+    }
+
+    let auth = Arc::new(AuthConfig::from_config(&config.admin_auth));
+
+    // User routes take the LINE user from a verified ID token, never from the request body
+    let line_auth = Arc::new(LineAuth::new(config.line_auth.clone()).unwrap_or_else(|err| {
+        error!(error = %err, "Failed to create LINE key client");
+        std::process::exit(1);
+    }));
+    match line_auth.preload().await {
+        Ok(count) => info!(count, "Loaded LINE signing keys"),
+        Err(err) => warn!(error = %err, "LINE keys not loaded, retrying on the first request"),
+    }
+
+    let allowed_origins = if config.server.allows_any_origin() {
+        AllowOrigin::any()
+    } else {
+        // Checked by Config::load
+        AllowOrigin::list(config.server.cors_allowed_origins.iter().map(|origin| origin.parse().unwrap()))
+    };
     let cors = CorsLayer::new()
-        .allow_origin(Any)
+        .allow_origin(allowed_origins)
         .allow_methods([
             http::Method::GET,
             http::Method::POST,
@@ -68,29 +148,149 @@ async fn main() {
         ])
         .allow_headers(Any);
 
-    let app = Router::new()
-        .route("/", get(|| async { "Hello, World!" }))
-        .route("/ingredients", get(get_ingredients))
+    // Catalog changes need a dietitian (or superadmin) token, managing admins a superadmin one
+    let catalog_routes = Router::new()
         .route("/create_ingredient", post(create_ingredient)) // Add route for create_ingredient
         .route("/update_recipe/{r_id}", patch(update_recipe))
         .route("/delete_recipe/{r_id}", delete(delete_recipe))
+        .route("/create_meal_plan_template", post(create_meal_plan_template))
+        .route("/save_meal_plan_as_template", post(save_meal_plan_as_template))
+        .route("/delete_meal_plan_template/{t_id}", delete(delete_meal_plan_template))
+        .route("/admin/users/{user_line_id}/apply_meal_plan_template", post(apply_meal_plan_template_for_user))
+        .route("/admin/users/{user_line_id}/clone_meal_plan", post(clone_meal_plan_for_user))
+        .route("/admin/recipes/export", get(export_recipe_file))
+        .route("/admin/recipes/import", post(import_recipe_file))
+        .route("/admin/me", get(current_admin))
+        .route_layer(middleware::from_fn_with_state(Role::Dietitian, require_role));
+
+    let superadmin_routes = Router::new()
+        .route("/admin/admins", get(get_admins).post(create_admin))
+        .route("/admin/admins/{email}", delete(delete_admin))
+        .route_layer(middleware::from_fn_with_state(Role::Superadmin, require_role));
+
+    // Swagger UI reads the document served at /openapi.json
+    let docs = if config.server.swagger_ui {
+        Router::from(SwaggerUi::new("/docs").config(utoipa_swagger_ui::Config::from("/openapi.json")))
+    } else {
+        Router::new()
+    };
+
+    let app = Router::new()
+        .route("/", get(|| async { "Hello, World!" }))
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_readiness))
+        .route("/metrics", get(get_metrics))
+        .route("/openapi.json", get(get_openapi))
+        .route("/ingredients", get(get_ingredients))
+        .route("/admin/login", post(admin_login))
         .route("/create_meal_plan", post(create_meal_plan))
         .route("/get_meal_plan", post(get_meal_plan))
         .route("/user_already_eat", patch(user_already_eat))
         .route("/edit_meal_plan", patch(edit_meal_plan))
+        .route("/delete_meal_plan", delete(delete_meal_plan))
         .route("/ai_meal_plan", post(ai_meal_plan))
         .route("/update_meal_plan", post(update_meal_plan))
+        .route("/ai_meal_plan_jobs", post(submit_ai_meal_plan_job))
+        .route("/update_meal_plan_jobs", post(submit_update_meal_plan_job))
+        .route("/meal_plan_jobs/{job_id}", get(get_meal_plan_job))
+        .route("/meal_plan_templates", get(get_meal_plan_templates))
+        .route("/apply_meal_plan_template", post(apply_meal_plan_template))
+        .route("/clone_meal_plan", post(clone_meal_plan))
+        .merge(catalog_routes)
+        .merge(superadmin_routes)
+        .merge(docs)
+        // Resource-oriented API; the routes above stay as v1 aliases for existing clients
+        .nest("/api/v2", v2::router())
         .fallback(fallback_handler) // Add a fallback route
-        .layer(Extension(db_pool))
+        .layer(Extension(db.clone()))
+        .layer(Extension(recommender))
+        .layer(Extension(job_queue.clone()))
+        .layer(Extension(catalog))
+        .layer(Extension(auth))
+        .layer(Extension(line_auth))
+        .layer(Extension(metrics))
+        .layer(middleware::from_fn(track_requests))
+        // One span per request, inside the request id so every log line carries it
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(logging::request_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
         .layer(cors);
 
-    if let Err(err) = axum::serve(listener, app).await {
-        eprintln!("Server error: {}", err);
+    // On SIGTERM/SIGINT stop accepting connections, then give in-flight requests and
+    // running jobs until the deadline between them
+    let shutdown_timeout = config.server.shutdown_timeout;
+    let (deadline_sender, mut deadline) = watch::channel(None::<Instant>);
+    let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        info!(timeout_secs = shutdown_timeout.as_secs(), "Shutting down, draining requests and jobs");
+        let _ = deadline_sender.send(Some(Instant::now() + shutdown_timeout));
+    });
+    let drain_expired = async {
+        let deadline = deadline.wait_for(Option::is_some).await.ok().and_then(|deadline| *deadline);
+        tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)).await;
+    };
+
+    tokio::select! {
+        result = server => match result {
+            Ok(()) => info!("Requests drained"),
+            Err(err) => error!(error = %err, "Server error"),
+        },
+        _ = drain_expired => warn!("Shutdown deadline passed with requests in flight, dropping them"),
+    }
+
+    let deadline = deadline.borrow().unwrap_or_else(|| Instant::now() + shutdown_timeout);
+    match tokio::time::timeout_at(deadline, job_queue.shutdown()).await {
+        Ok(()) => info!("Background jobs drained"),
+        Err(_) => warn!(
+            running = job_queue.running(),
+            "Shutdown deadline passed with jobs running; they are requeued on the next start"
+        ),
+    }
+
+    // With requests and jobs drained this is the last handle on the pool, and dropping it
+    // closes the connections; jobs still running past the deadline keep theirs until exit
+    let state = db.pool().state();
+    info!(connections = state.connections, "Closing database pool");
+    drop(db);
+}
+
+/// Resolves on Ctrl-C or SIGTERM, which Fly.io sends before stopping a machine.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!(error = %err, "Failed to listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                error!(error = %err, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
     }
 }
 
 // Add a fallback handler to log unhandled requests
-async fn fallback_handler(uri: axum::http::Uri) -> impl axum::response::IntoResponse {
-    eprintln!("Unhandled request: {}", uri);
-    (axum::http::StatusCode::NOT_FOUND, "Route not found")
+async fn fallback_handler(uri: axum::http::Uri) -> AppError {
+    warn!(path = %uri.path(), "Unhandled request");
+    AppError::not_found("Route not found")
 }
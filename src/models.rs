@@ -2,12 +2,14 @@ use diesel::prelude::*;
 use serde::{Serialize, Deserialize};
 
 // Admins Table
-#[derive(Queryable, Selectable, Insertable, Debug, Serialize, Deserialize)]
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::admins)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Admin {
     pub admin_email: String,
-    pub admin_password: uuid::Uuid,
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+    pub role: String,
 }
 
 // Disease Table
@@ -47,6 +49,23 @@ pub struct Ingredient {
     pub ingredient_name_eng: Option<String>,
 }
 
+// Meal Plan Jobs Table
+#[derive(Queryable, Selectable, Insertable, Debug, Clone, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::meal_plan_jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MealPlanJob {
+    pub job_id: uuid::Uuid,
+    pub user_id: i32,
+    pub kind: String,
+    pub status: String,
+    pub request: serde_json::Value,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
 // Meal Plan Recipes Table
 #[derive(Queryable, Selectable, Insertable, Debug, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::meal_plan_recipes)]
@@ -58,6 +77,29 @@ pub struct MealPlanRecipe {
     pub ischecked: Option<bool>,
 }
 
+// Meal Plan Templates Table
+#[derive(Queryable, Selectable, Insertable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::meal_plan_templates)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MealPlanTemplate {
+    pub template_id: i32,
+    pub name: String,
+    pub created_by: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+// Meal Plan Template Recipes Table
+#[derive(Queryable, Selectable, Insertable, Debug, Serialize, Deserialize)]
+#[diesel(table_name = crate::schema::meal_plan_template_recipes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MealPlanTemplateRecipe {
+    pub template_recipe_id: i32,
+    pub template_id: i32,
+    pub day_index: i32,
+    pub recipe_id: i32,
+    pub meal_time: Option<i32>,
+}
+
 // Meal Plans Table
 #[derive(Queryable, Selectable, Insertable, Debug, Serialize, Deserialize)]
 #[diesel(table_name = crate::schema::meal_plans)]
@@ -67,6 +109,7 @@ pub struct MealPlan {
     pub user_id: i32,
     pub name: String,
     pub date: chrono::NaiveDate,
+    pub archived_at: Option<chrono::NaiveDateTime>,
 }
 
 // Nutrients Table
@@ -0,0 +1,93 @@
+//! Prometheus metrics, served at `/metrics`.
+//!
+//! Code records through the `metrics` macros (`counter!`, `histogram!`) and the
+//! recorder installed by [`install`] keeps the values until they are scraped.
+//! Durations are histograms in seconds. Labels are kept to small fixed sets:
+//! requests are labelled by route pattern rather than path, which would add a
+//! series per user, recipe and job.
+//!
+//! | Metric | Type | Labels |
+//! |---|---|---|
+//! | `http_requests_total` | counter | method, route, status |
+//! | `http_request_duration_seconds` | histogram | method, route |
+//! | `db_pool_connections`, `db_pool_idle_connections`, `db_pool_max_connections` | gauge | |
+//! | `db_pool_wait_seconds` | histogram | |
+//! | `db_query_duration_seconds` | histogram | |
+//! | `db_pending_migrations` | gauge, set by `/readyz` | |
+//! | `recommender_request_duration_seconds` | histogram | endpoint, outcome |
+//! | `recommender_errors_total` | counter | endpoint, reason |
+//! | `meal_plan_jobs_total` | counter | kind, status |
+//! | `meal_plan_jobs_rejected_total` | counter | reason |
+//! | `meal_plan_jobs_exhausted_total` | counter | |
+//! | `meal_plans_created_total` | counter | source |
+//! | `meal_plan_days_created_total` | counter | source |
+//! | `meals_checked_total` | counter | checked |
+
+use crate::db::DbPool;
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use std::time::Instant;
+
+/// Histogram buckets for durations, from a fast query to a slow AI call.
+const DURATION_BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Installs the global recorder; the handle renders what has been recorded since.
+pub fn install() -> Result<PrometheusHandle, BuildError> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), &DURATION_BUCKETS)?
+        .install_recorder()?;
+
+    describe_counter!("http_requests_total", "HTTP requests answered");
+    describe_histogram!("http_request_duration_seconds", Unit::Seconds, "Time to answer an HTTP request");
+    describe_gauge!("db_pool_connections", "Open database connections");
+    describe_gauge!("db_pool_idle_connections", "Open database connections not in use");
+    describe_gauge!("db_pool_max_connections", "Largest number of database connections the pool opens");
+    describe_histogram!("db_pool_wait_seconds", Unit::Seconds, "Time waiting for a pooled connection");
+    describe_histogram!("db_query_duration_seconds", Unit::Seconds, "Time spent in database work holding a connection");
+    describe_gauge!("db_pending_migrations", "Embedded migrations the database has not applied, as of the last readiness check");
+    describe_histogram!("recommender_request_duration_seconds", Unit::Seconds, "Time of one call to the AI recommender");
+    describe_counter!("recommender_errors_total", "AI recommender calls that failed after retries");
+    describe_counter!("meal_plan_jobs_total", "Background meal plan jobs finished");
+    describe_counter!("meal_plan_jobs_rejected_total", "Meal plan job submissions refused with a 429");
+    describe_counter!("meal_plan_jobs_exhausted_total", "Meal plan jobs failed for running out of attempts");
+    describe_counter!("meal_plans_created_total", "Meal plans saved for users");
+    describe_counter!("meal_plan_days_created_total", "Days of meal plans saved for users");
+    describe_counter!("meals_checked_total", "Meal plan recipes marked as eaten or not eaten");
+
+    Ok(handle)
+}
+
+/// Middleware counting and timing requests per route; add it with `Router::layer`
+/// so the matched route is known.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    histogram!("http_request_duration_seconds", "method" => method.clone(), "route" => route.clone())
+        .record(started.elapsed().as_secs_f64());
+    counter!("http_requests_total", "method" => method, "route" => route, "status" => status).increment(1);
+
+    response
+}
+
+/// Samples the pool gauges and renders all metrics in the Prometheus text format.
+pub fn render(handle: &PrometheusHandle, pool: &DbPool) -> String {
+    let state = pool.state();
+    gauge!("db_pool_connections").set(state.connections as f64);
+    gauge!("db_pool_idle_connections").set(state.idle_connections as f64);
+    gauge!("db_pool_max_connections").set(pool.max_size() as f64);
+
+    handle.run_upkeep();
+    handle.render()
+}
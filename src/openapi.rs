@@ -0,0 +1,380 @@
+//! OpenAPI 3 description of the HTTP API, served at `/openapi.json`.
+//!
+//! Each handler carries its own `#[utoipa::path]` and the request and response
+//! types derive `ToSchema`, so the document follows the code. Handlers return
+//! the response types below rather than `json!` bodies; only generated meal
+//! plans stay loose JSON, since recommender fields are passed through, and
+//! [`GeneratedMealPlan`] describes the fields the server sets. v2 routes served
+//! by a v1 handler are copied from the v1 operation by [`SharedV2Routes`].
+//!
+//! With `SWAGGER_UI=true` the server also serves Swagger UI at `/docs`.
+
+use crate::auth::Role;
+use crate::error::ErrorBody;
+use crate::planner::PlanValidation;
+use crate::recipe_io::RecipeFormat;
+use axum::Json;
+use serde::Serialize;
+use utoipa::openapi::path::{HttpMethod, Operation, PathItem};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::OpenApi as OpenApiDocument;
+use utoipa::{IntoResponses, Modify, OpenApi, ToSchema};
+use uuid::Uuid;
+
+pub const LINE_SECURITY: &str = "line_id_token";
+pub const ADMIN_SECURITY: &str = "admin_token";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Kidney meal planner API",
+        description = "Meal plans for people with kidney disease. User routes take a LINE ID token, \
+                       catalog and admin routes an admin token from `/admin/login`. \
+                       Errors always have the body `{\"code\", \"error\", \"details\"}`; branch on `code`."
+    ),
+    paths(
+        crate::routes::ingredient::get_ingredients,
+        crate::routes::ingredient::create_ingredient,
+        crate::routes::recipe::update_recipe,
+        crate::routes::recipe::delete_recipe,
+        crate::routes::mealplan::create_meal_plan,
+        crate::routes::mealplan::get_meal_plan,
+        crate::routes::mealplan::user_already_eat,
+        crate::routes::mealplan::delete_meal_plan,
+        crate::routes::mealplan::edit_meal_plan,
+        crate::routes::mealplan::ai_meal_plan,
+        crate::routes::mealplan::update_meal_plan,
+        crate::routes::job::submit_ai_meal_plan_job,
+        crate::routes::job::submit_update_meal_plan_job,
+        crate::routes::job::get_meal_plan_job,
+        crate::routes::template::create_meal_plan_template,
+        crate::routes::template::save_meal_plan_as_template,
+        crate::routes::template::get_meal_plan_templates,
+        crate::routes::template::apply_meal_plan_template,
+        crate::routes::template::clone_meal_plan,
+        crate::routes::template::apply_meal_plan_template_for_user,
+        crate::routes::template::clone_meal_plan_for_user,
+        crate::routes::template::delete_meal_plan_template,
+        crate::routes::admin::export_recipe_file,
+        crate::routes::admin::import_recipe_file,
+        crate::routes::admin::admin_login,
+        crate::routes::admin::current_admin,
+        crate::routes::admin::get_admins,
+        crate::routes::admin::create_admin,
+        crate::routes::admin::delete_admin,
+        crate::routes::v2::list_meal_plans,
+        crate::routes::v2::create_meal_plans,
+        crate::routes::v2::delete_meal_plans,
+        crate::routes::v2::replace_meal_plan_recipes,
+        crate::routes::v2::clone_meal_plans,
+        crate::routes::v2::apply_template,
+        crate::routes::v2::generate_meal_plan,
+        crate::routes::v2::regenerate_meal_plan,
+        crate::routes::v2::submit_generate_job,
+        crate::routes::v2::submit_regenerate_job,
+        crate::routes::v2::get_job,
+        crate::routes::v2::update_meal_plan_entry,
+        crate::routes::v2::create_ingredient_v2,
+        crate::routes::v2::create_meal_plan_template_v2,
+        crate::routes::v2::save_meal_plan_as_template_v2,
+        crate::routes::v2::clone_meal_plans_for_user,
+        crate::routes::v2::apply_template_for_user,
+        crate::routes::monitoring::get_health,
+        crate::routes::monitoring::get_readiness,
+        crate::routes::monitoring::get_metrics,
+    ),
+    // Only referenced from query parameters, which utoipa does not collect
+    components(schemas(RecipeFormat)),
+    modifiers(&SecuritySchemes, &SharedV2Routes),
+    tags(
+        (name = "v2", description = "Resource-oriented routes under `/api/v2`; use these for new clients"),
+        (name = "v1", description = "Original routes, kept for existing clients"),
+        (name = "monitoring", description = "Probes and metrics"),
+    )
+)]
+pub struct ApiDoc;
+
+#[axum::debug_handler]
+pub async fn get_openapi() -> Json<OpenApiDocument> {
+    Json(ApiDoc::openapi())
+}
+
+/// Errors of routes signed with a LINE ID token.
+#[derive(IntoResponses)]
+pub enum UserErrors {
+    /// Malformed request or invalid values
+    #[response(status = 400)]
+    BadRequest(ErrorBody),
+    /// Missing, invalid or expired LINE ID token
+    #[response(status = 401)]
+    Unauthorized(ErrorBody),
+    /// The LINE user is not registered, or the resource does not exist
+    #[response(status = 404)]
+    NotFound(ErrorBody),
+    /// Database or other server-side failure
+    #[response(status = 500)]
+    Internal(ErrorBody),
+    /// LINE's signing keys or the AI recommender could not be reached
+    #[response(status = 502)]
+    Upstream(ErrorBody),
+}
+
+/// Errors of routes signed with an admin token.
+#[derive(IntoResponses)]
+pub enum AdminErrors {
+    /// Malformed request or invalid values
+    #[response(status = 400)]
+    BadRequest(ErrorBody),
+    /// Missing, invalid or expired admin token
+    #[response(status = 401)]
+    Unauthorized(ErrorBody),
+    /// The admin's role does not allow this
+    #[response(status = 403)]
+    Forbidden(ErrorBody),
+    /// Database or other server-side failure
+    #[response(status = 500)]
+    Internal(ErrorBody),
+}
+
+/// Errors of routes without authentication.
+#[derive(IntoResponses)]
+pub enum PublicErrors {
+    /// Malformed request or invalid values
+    #[response(status = 400)]
+    BadRequest(ErrorBody),
+    /// Database or other server-side failure
+    #[response(status = 500)]
+    Internal(ErrorBody),
+}
+
+/// `{"status": "success", "message": ...}`
+#[derive(Serialize, ToSchema)]
+pub struct StatusMessage {
+    pub status: &'static str,
+    pub message: &'static str,
+}
+
+impl StatusMessage {
+    pub fn success(message: &'static str) -> Self {
+        StatusMessage { status: "success", message }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MealPlanSaved {
+    pub status: &'static str,
+    pub message: &'static str,
+    pub start_date: String, // YYYY-MM-DD of the first day written
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MealPlansRemoved {
+    pub status: &'static str,
+    pub message: &'static str,
+    pub days: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TemplateSaved {
+    pub status: &'static str,
+    pub message: &'static str,
+    pub template_id: i32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TemplateApplied {
+    pub status: &'static str,
+    pub message: &'static str,
+    pub start_date: String,
+    pub days: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AdminSaved {
+    pub status: &'static str,
+    pub message: &'static str,
+    pub email: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AdminProfile {
+    pub email: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct JobAccepted {
+    pub job_id: Uuid,
+    pub status: &'static str, // Always "pending"
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Health {
+    pub status: &'static str,
+    pub version: &'static str,
+}
+
+/// A generated meal plan. Fields the recommender adds are passed through.
+#[derive(Serialize, ToSchema)]
+pub struct GeneratedMealPlan {
+    pub user_line_id: String,
+    pub days: Option<i32>, // Set by the built-in planner; recommender plans may leave it out
+    pub generator: Option<String>, // "local" when the built-in planner made the plan
+    pub mealplans: Vec<Vec<GeneratedMenu>>,
+    pub persisted: bool,
+    pub start_date: Option<String>, // Set when persisted
+    pub validation: PlanValidation,
+}
+
+/// One menu of a generated day; recommender fields such as `name` and `nutrition` are passed through.
+#[derive(Serialize, ToSchema)]
+pub struct GeneratedMenu {
+    pub recipe_id: i32,
+    pub meal_time: Option<i32>, // Slot from the built-in planner: 1 breakfast to 4 snack
+}
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            LINE_SECURITY,
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .description(Some("LINE ID token from the LIFF app"))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            ADMIN_SECURITY,
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .description(Some("Admin token from POST /admin/login"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// v2 routes whose handler is the v1 one: method, v1 path, v2 path.
+const SHARED_V2_ROUTES: [(HttpMethod, &str, &str); 12] = [
+    (HttpMethod::Get, "/ingredients", "/api/v2/ingredients"),
+    (HttpMethod::Patch, "/update_recipe/{r_id}", "/api/v2/recipes/{id}"),
+    (HttpMethod::Delete, "/delete_recipe/{r_id}", "/api/v2/recipes/{id}"),
+    (HttpMethod::Get, "/meal_plan_templates", "/api/v2/meal-plan-templates"),
+    (HttpMethod::Delete, "/delete_meal_plan_template/{t_id}", "/api/v2/meal-plan-templates/{id}"),
+    (HttpMethod::Get, "/admin/recipes/export", "/api/v2/admin/recipes/export"),
+    (HttpMethod::Post, "/admin/recipes/import", "/api/v2/admin/recipes/import"),
+    (HttpMethod::Post, "/admin/login", "/api/v2/admin/login"),
+    (HttpMethod::Get, "/admin/me", "/api/v2/admin/me"),
+    (HttpMethod::Get, "/admin/admins", "/api/v2/admin/admins"),
+    (HttpMethod::Post, "/admin/admins", "/api/v2/admin/admins"),
+    (HttpMethod::Delete, "/admin/admins/{email}", "/api/v2/admin/admins/{email}"),
+];
+
+/// Documents the [`SHARED_V2_ROUTES`] by copying the v1 operation, renaming the
+/// path parameter where v2 calls it `id`.
+struct SharedV2Routes;
+
+impl Modify for SharedV2Routes {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        for (method, v1_path, v2_path) in SHARED_V2_ROUTES {
+            let Some(operation) = openapi.paths.paths.get(v1_path).and_then(|item| operation(item, &method)) else {
+                continue;
+            };
+
+            let mut operation = operation.clone();
+            operation.tags = Some(vec!["v2".to_string()]);
+            operation.operation_id = operation.operation_id.map(|id| format!("{}_v2", id));
+            for parameter in operation.parameters.iter_mut().flatten() {
+                if !v2_path.contains(&format!("{{{}}}", parameter.name)) {
+                    parameter.name = "id".to_string();
+                }
+            }
+
+            openapi.paths.add_path_operation(v2_path, vec![method], operation);
+        }
+    }
+}
+
+fn operation<'a>(item: &'a PathItem, method: &HttpMethod) -> Option<&'a Operation> {
+    match method {
+        HttpMethod::Get => item.get.as_ref(),
+        HttpMethod::Post => item.post.as_ref(),
+        HttpMethod::Put => item.put.as_ref(),
+        HttpMethod::Patch => item.patch.as_ref(),
+        HttpMethod::Delete => item.delete.as_ref(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operations(doc: &OpenApiDocument) -> Vec<(HttpMethod, &str, &Operation)> {
+        let methods = [HttpMethod::Get, HttpMethod::Post, HttpMethod::Put, HttpMethod::Patch, HttpMethod::Delete];
+        doc.paths
+            .paths
+            .iter()
+            .flat_map(|(path, item)| {
+                methods
+                    .iter()
+                    .filter_map(move |method| operation(item, method).map(|op| (method.clone(), path.as_str(), op)))
+            })
+            .collect()
+    }
+
+    fn summary<'a>(doc: &'a OpenApiDocument, method: HttpMethod, path: &str) -> Option<&'a str> {
+        operations(doc)
+            .into_iter()
+            .find(|(m, p, _)| *m == method && *p == path)
+            .unwrap_or_else(|| panic!("{} is not documented", path))
+            .2
+            .summary
+            .as_deref()
+    }
+
+    #[test]
+    fn every_route_is_documented_once() {
+        let doc = ApiDoc::openapi();
+        let operations = operations(&doc);
+
+        // 32 v1 and monitoring routes, 29 v2 routes; update when routes are added
+        assert_eq!(operations.len(), 61);
+        assert_eq!(operations.iter().filter(|(_, path, _)| path.starts_with("/api/v2/")).count(), 29);
+
+        let mut ids: Vec<_> = operations.iter().filter_map(|(_, _, op)| op.operation_id.as_deref()).collect();
+        assert_eq!(ids.len(), operations.len());
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), operations.len(), "operation ids must be unique");
+    }
+
+    #[test]
+    fn summaries_come_from_their_own_handler() {
+        let doc = ApiDoc::openapi();
+
+        assert!(summary(&doc, HttpMethod::Get, "/healthz").is_some_and(|s| s.starts_with("Liveness")));
+        assert!(summary(&doc, HttpMethod::Get, "/readyz").is_some_and(|s| s.starts_with("Readiness")));
+        assert_eq!(summary(&doc, HttpMethod::Get, "/metrics"), None);
+
+        assert!(summary(&doc, HttpMethod::Post, "/admin/admins").is_some_and(|s| s.starts_with("Creates an admin")));
+        assert_eq!(summary(&doc, HttpMethod::Post, "/admin/login"), None);
+
+        assert!(summary(&doc, HttpMethod::Post, "/admin/recipes/import").is_some_and(|s| s.starts_with("Takes the raw file")));
+        assert_eq!(summary(&doc, HttpMethod::Get, "/admin/recipes/export"), None);
+
+        // Copied v2 routes carry the v1 summary
+        assert_eq!(
+            summary(&doc, HttpMethod::Post, "/api/v2/admin/admins"),
+            summary(&doc, HttpMethod::Post, "/admin/admins")
+        );
+    }
+}
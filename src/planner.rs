@@ -0,0 +1,325 @@
+//! Rule-based meal plan generator used when the remote AI recommender is not
+//! wanted or not reachable, and validation of generated plans.
+//!
+//! Each day is filled slot by slot (breakfast, lunch, dinner, snack). For every
+//! slot the generator greedily picks the recipe whose calories are closest to the
+//! slot's share of the daily calorie limit while using as little as possible of
+//! the remaining nutrient budget. Recipes that would push any limited nutrient
+//! over the day's limit are never chosen, and recipes are not repeated until the
+//! whole pool has been used.
+
+use crate::routes::mealplan::{FoodMenu, Nutrition};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
+
+/// Share of the daily calorie limit targeted by each meal slot, in meal_time order.
+const SLOT_CALORIE_SHARE: [f32; 4] = [0.25, 0.35, 0.30, 0.10];
+
+/// Calorie target used when the user has no calorie limit configured.
+const DEFAULT_DAILY_CALORIES: f32 = 1800.0;
+
+/// Nutrient names in the order used by `nutrients`.
+const NUTRIENT_NAMES: [&str; 7] = ["calories", "carbs", "fat", "phosphorus", "potassium", "protein", "sodium"];
+
+/// Slack allowed on daily totals to absorb rounding in the recommender's output.
+const LIMIT_TOLERANCE: f32 = 0.001;
+
+#[derive(Debug, Clone, Copy)]
+struct Budget {
+    remaining: [f32; 7],
+    limited: [bool; 7],
+}
+
+fn nutrients(n: &Nutrition) -> [f32; 7] {
+    [
+        n.calories,
+        n.carbs,
+        n.fat,
+        n.phosphorus,
+        n.potassium,
+        n.protein,
+        n.sodium,
+    ]
+}
+
+impl Budget {
+    /// A limit of zero means the user has no limit for that nutrient.
+    fn new(limits: &Nutrition) -> Self {
+        let remaining = nutrients(limits);
+        Budget {
+            remaining,
+            limited: remaining.map(|limit| limit > 0.0),
+        }
+    }
+
+    fn fits(&self, menu: &FoodMenu) -> bool {
+        nutrients(&menu.nutrition)
+            .iter()
+            .enumerate()
+            .all(|(i, value)| !self.limited[i] || *value <= self.remaining[i])
+    }
+
+    /// Fraction of the remaining budget the recipe would use, summed over limited nutrients.
+    fn pressure(&self, menu: &FoodMenu) -> f32 {
+        nutrients(&menu.nutrition)
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.limited[*i] && self.remaining[*i] > 0.0)
+            .map(|(i, value)| value / self.remaining[i])
+            .sum()
+    }
+
+    fn take(&mut self, menu: &FoodMenu) {
+        for (i, value) in nutrients(&menu.nutrition).iter().enumerate() {
+            self.remaining[i] -= value;
+        }
+    }
+}
+
+/// A recipe picked for a meal slot; `meal_time` is kept so a day with a left-out
+/// slot is saved with every meal in its own slot.
+#[derive(Serialize, Debug, Clone)]
+pub struct PlannedMeal {
+    pub meal_time: i32,
+    #[serde(flatten)]
+    pub menu: FoodMenu,
+}
+
+/// Generates `days` days of meals from `pool` that stay within `limits` each day.
+/// A slot is left out when no remaining recipe fits the day's budget.
+pub fn generate_meal_plan(pool: &[FoodMenu], limits: &Nutrition, days: usize) -> Vec<Vec<PlannedMeal>> {
+    let daily_calories = if limits.calories > 0.0 {
+        limits.calories
+    } else {
+        DEFAULT_DAILY_CALORIES
+    };
+
+    // How many times each recipe has been used so far; fewer uses win ties.
+    let mut uses: HashMap<i32, usize> = HashMap::new();
+    let mut mealplans = Vec::with_capacity(days);
+
+    for _ in 0..days {
+        let mut budget = Budget::new(limits);
+        let mut day: Vec<PlannedMeal> = Vec::with_capacity(SLOT_CALORIE_SHARE.len());
+
+        for (slot, share) in SLOT_CALORIE_SHARE.into_iter().enumerate() {
+            let target = daily_calories * share;
+
+            let best = pool
+                .iter()
+                .filter(|menu| budget.fits(menu))
+                .filter(|menu| !day.iter().any(|picked| picked.menu.recipe_id == menu.recipe_id))
+                .map(|menu| {
+                    let used = uses.get(&menu.recipe_id).copied().unwrap_or(0);
+                    let calorie_gap = (menu.nutrition.calories - target).abs() / target;
+                    (used, calorie_gap + budget.pressure(menu), menu)
+                })
+                .min_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+                .map(|(_, _, menu)| menu);
+
+            if let Some(menu) = best {
+                budget.take(menu);
+                *uses.entry(menu.recipe_id).or_insert(0) += 1;
+                day.push(PlannedMeal {
+                    meal_time: slot as i32 + 1,
+                    menu: menu.clone(),
+                });
+            }
+        }
+
+        mealplans.push(day);
+    }
+
+    mealplans
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlanIssue {
+    /// The recipe does not exist.
+    UnknownRecipe { day: usize, recipe_id: i32 },
+    /// The recipe exists but contains an ingredient the user is allergic to.
+    AllergenRecipe { day: usize, recipe_id: i32 },
+    /// The day's total for a nutrient is above the user's limit.
+    LimitExceeded { day: usize, nutrient: &'static str, total: f32, limit: f32 },
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct PlanValidation {
+    pub valid: bool,
+    pub issues: Vec<PlanIssue>,
+}
+
+/// Checks a plan (recipe ids per day) against the user's allergy-safe `pool` and daily `limits`.
+/// `excluded` holds the ids that exist in the catalog but were filtered out of the pool,
+/// so they can be reported as allergens rather than unknown recipes.
+pub fn validate_meal_plan(
+    days: &[Vec<i32>],
+    pool: &[FoodMenu],
+    limits: &Nutrition,
+    excluded: &HashSet<i32>,
+) -> PlanValidation {
+    let by_id: HashMap<i32, &FoodMenu> = pool.iter().map(|menu| (menu.recipe_id, menu)).collect();
+    let limits = nutrients(limits);
+    let mut issues = Vec::new();
+
+    for (day, recipe_ids) in days.iter().enumerate() {
+        let mut totals = [0.0f32; 7];
+
+        for &recipe_id in recipe_ids {
+            match by_id.get(&recipe_id) {
+                Some(menu) => {
+                    for (total, value) in totals.iter_mut().zip(nutrients(&menu.nutrition)) {
+                        *total += value;
+                    }
+                }
+                None if excluded.contains(&recipe_id) => issues.push(PlanIssue::AllergenRecipe { day, recipe_id }),
+                None => issues.push(PlanIssue::UnknownRecipe { day, recipe_id }),
+            }
+        }
+
+        for (i, (total, limit)) in totals.iter().zip(limits).enumerate() {
+            if limit > 0.0 && *total > limit * (1.0 + LIMIT_TOLERANCE) {
+                issues.push(PlanIssue::LimitExceeded {
+                    day,
+                    nutrient: NUTRIENT_NAMES[i],
+                    total: *total,
+                    limit,
+                });
+            }
+        }
+    }
+
+    PlanValidation {
+        valid: issues.is_empty(),
+        issues,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn menu(recipe_id: i32, calories: f32, sodium: f32) -> FoodMenu {
+        FoodMenu {
+            name: format!("recipe {}", recipe_id),
+            nutrition: Nutrition { calories, sodium, ..Nutrition::default() },
+            recipe_id,
+            recipe_img_link: Vec::new(),
+        }
+    }
+
+    fn limits(calories: f32, sodium: f32) -> Nutrition {
+        Nutrition { calories, sodium, ..Nutrition::default() }
+    }
+
+    fn recipe_ids(plan: &[Vec<PlannedMeal>]) -> Vec<Vec<i32>> {
+        plan.iter().map(|day| day.iter().map(|meal| meal.menu.recipe_id).collect()).collect()
+    }
+
+    #[test]
+    fn days_stay_within_the_limits() {
+        let pool: Vec<FoodMenu> = (1..=12).map(|id| menu(id, 150.0 + 40.0 * id as f32, 90.0 * id as f32)).collect();
+        let limits = limits(1500.0, 1200.0);
+
+        let plan = generate_meal_plan(&pool, &limits, 7);
+
+        assert_eq!(plan.len(), 7);
+        for day in &plan {
+            let calories: f32 = day.iter().map(|meal| meal.menu.nutrition.calories).sum();
+            let sodium: f32 = day.iter().map(|meal| meal.menu.nutrition.sodium).sum();
+            assert!(calories <= 1500.0, "{} calories", calories);
+            assert!(sodium <= 1200.0, "{} sodium", sodium);
+        }
+        assert!(validate_meal_plan(&recipe_ids(&plan), &pool, &limits, &HashSet::new()).valid);
+    }
+
+    #[test]
+    fn recipes_over_a_limit_are_never_picked() {
+        let pool = vec![menu(1, 400.0, 100.0), menu(2, 450.0, 2500.0), menu(3, 500.0, 200.0)];
+
+        let plan = generate_meal_plan(&pool, &limits(1800.0, 2000.0), 5);
+
+        assert!(plan.iter().flatten().all(|meal| meal.menu.recipe_id != 2));
+    }
+
+    #[test]
+    fn nutrients_without_a_limit_do_not_restrict() {
+        let pool = vec![menu(1, 400.0, 5000.0), menu(2, 600.0, 5000.0)];
+
+        let plan = generate_meal_plan(&pool, &limits(0.0, 0.0), 1);
+
+        assert_eq!(recipe_ids(&plan), vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn an_empty_catalog_gives_empty_days() {
+        let plan = generate_meal_plan(&[], &limits(1800.0, 2000.0), 3);
+
+        assert_eq!(plan.len(), 3);
+        assert!(plan.iter().all(|day| day.is_empty()));
+    }
+
+    #[test]
+    fn left_out_slots_keep_the_slots_of_the_picked_meals() {
+        // After breakfast and lunch nothing else fits the calorie budget
+        let pool = vec![menu(1, 250.0, 0.0), menu(2, 350.0, 0.0), menu(3, 900.0, 0.0)];
+
+        let plan = generate_meal_plan(&pool, &limits(1000.0, 0.0), 1);
+
+        let slots: Vec<(i32, i32)> = plan[0].iter().map(|meal| (meal.meal_time, meal.menu.recipe_id)).collect();
+        assert_eq!(slots, vec![(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn planned_meals_carry_their_slot() {
+        let pool: Vec<FoodMenu> = (1..=4).map(|id| menu(id, 300.0, 0.0)).collect();
+
+        let plan = generate_meal_plan(&pool, &limits(1800.0, 0.0), 1);
+
+        let meal_times: Vec<i32> = plan[0].iter().map(|meal| meal.meal_time).collect();
+        assert_eq!(meal_times, vec![1, 2, 3, 4]);
+        let json = serde_json::to_value(&plan[0][0]).unwrap();
+        assert_eq!(json["meal_time"], 1);
+        assert_eq!(json["recipe_id"], plan[0][0].menu.recipe_id);
+    }
+
+    #[test]
+    fn validation_reports_unknown_and_allergen_recipes() {
+        let pool = vec![menu(1, 300.0, 100.0)];
+        let excluded = HashSet::from([7]);
+
+        let validation = validate_meal_plan(&[vec![1, 7, 9]], &pool, &limits(1800.0, 2000.0), &excluded);
+
+        assert!(!validation.valid);
+        assert_eq!(
+            validation.issues,
+            vec![
+                PlanIssue::AllergenRecipe { day: 0, recipe_id: 7 },
+                PlanIssue::UnknownRecipe { day: 0, recipe_id: 9 },
+            ]
+        );
+    }
+
+    #[test]
+    fn validation_reports_days_over_a_limit() {
+        let pool = vec![menu(1, 900.0, 600.0), menu(2, 900.0, 600.0)];
+
+        let validation = validate_meal_plan(&[vec![1], vec![1, 2]], &pool, &limits(1500.0, 2000.0), &HashSet::new());
+
+        assert_eq!(
+            validation.issues,
+            vec![PlanIssue::LimitExceeded { day: 1, nutrient: "calories", total: 1800.0, limit: 1500.0 }]
+        );
+    }
+
+    #[test]
+    fn validation_tolerates_rounding_at_the_limit() {
+        let pool = vec![menu(1, 1500.5, 0.0)];
+
+        let validation = validate_meal_plan(&[vec![1]], &pool, &limits(1500.0, 0.0), &HashSet::new());
+
+        assert!(validation.valid);
+    }
+}
@@ -0,0 +1,797 @@
+//! Recipes as self-contained documents, for moving them in and out of the database.
+//!
+//! A [`RecipeRecord`] carries a recipe with everything hanging off it, and refers
+//! to ingredients, nutrients and allergens by name rather than id so files can be
+//! written by hand. Imports match existing recipes by name and replace them.
+//!
+//! Records are exchanged as a JSON array or as CSV with one row per recipe, the
+//! layout our dietitians keep in spreadsheets (see [`read_csv`]). Every import is
+//! validated first; a dry run stops there and reports what would change.
+
+use crate::schema::{
+    ingredient_allergies, ingredients, nutrients, recipes, recipes_ingredient_allergies, recipes_ingredients,
+    recipes_nutrients,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Units accepted for ingredient amounts besides those already in the database.
+pub const INGREDIENT_UNITS: [&str; 10] = ["g", "kg", "mg", "ml", "l", "tsp", "tbsp", "cup", "piece", "slice"];
+
+/// Units accepted for `calories_unit` besides those already in the database.
+pub const CALORIE_UNITS: [&str; 2] = ["kcal", "kj"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RecipeFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl FromStr for RecipeFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "json" => Ok(RecipeFormat::Json),
+            "csv" => Ok(RecipeFormat::Csv),
+            _ => Err(format!("unknown format '{}', expected json or csv", value)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct IngredientAmount {
+    pub ingredient_name: String,
+    pub amount: i32,
+    pub ingredient_unit: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct RecipeRecord {
+    pub recipe_name: String,
+    #[serde(default)]
+    pub recipe_method: Vec<String>, // One entry per step
+    pub calories: f64,
+    pub calories_unit: String,
+    #[serde(default)]
+    pub recipe_img_link: Vec<String>,
+    #[serde(default)]
+    pub food_category: Vec<String>,
+    #[serde(default)]
+    pub dish_type: Vec<String>,
+    #[serde(default)]
+    pub ingredients: Vec<IngredientAmount>,
+    #[serde(default)]
+    pub nutrients: BTreeMap<String, f64>, // Nutrient name, e.g. "protein", to quantity
+    #[serde(default)]
+    pub allergens: Vec<String>,
+}
+
+/// A problem that stops a record from being imported.
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct ImportIssue {
+    pub recipe: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl ImportIssue {
+    fn new(record: &RecipeRecord, field: &str, message: String) -> Self {
+        ImportIssue {
+            recipe: record.recipe_name.clone(),
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, ToSchema)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: usize,
+    pub updated: usize,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// The file could not be read as recipes.
+    Parse(String),
+    /// Records refer to names the database does not know, or are otherwise unusable.
+    Invalid(Vec<ImportIssue>),
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Parse(message) => write!(f, "invalid file: {}", message),
+            ImportError::Invalid(issues) => {
+                write!(f, "{} issue(s)", issues.len())?;
+                for issue in issues {
+                    write!(f, "\n  {} ({}): {}", issue.recipe, issue.field, issue.message)?;
+                }
+                Ok(())
+            }
+            ImportError::Database(err) => write!(f, "database error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<diesel::result::Error> for ImportError {
+    fn from(err: diesel::result::Error) -> Self {
+        ImportError::Database(err)
+    }
+}
+
+fn strings(values: Option<Vec<Option<String>>>) -> Vec<String> {
+    values.unwrap_or_default().into_iter().flatten().collect()
+}
+
+fn nullable_strings(values: &[String]) -> Vec<Option<String>> {
+    values.iter().cloned().map(Some).collect()
+}
+
+/// Every recipe with its ingredients, nutrients and allergens, ordered by recipe id.
+pub fn export_recipes(conn: &mut PgConnection) -> QueryResult<Vec<RecipeRecord>> {
+    let rows = recipes::table
+        .order(recipes::recipe_id.asc())
+        .select((
+            recipes::recipe_id,
+            recipes::recipe_name,
+            recipes::recipe_method,
+            recipes::calories,
+            recipes::calories_unit,
+            recipes::recipe_img_link,
+            recipes::food_category,
+            recipes::dish_type,
+        ))
+        .load::<(
+            i32,
+            String,
+            Option<Vec<Option<String>>>,
+            f64,
+            String,
+            Option<Vec<Option<String>>>,
+            Vec<Option<String>>,
+            Option<Vec<Option<String>>>,
+        )>(conn)?;
+
+    let mut ingredient_map: HashMap<i32, Vec<IngredientAmount>> = HashMap::new();
+    for (recipe_id, ingredient_name, amount, ingredient_unit) in recipes_ingredients::table
+        .inner_join(ingredients::table)
+        .order(recipes_ingredients::recipes_ingredients_id.asc())
+        .select((
+            recipes_ingredients::recipe_id,
+            ingredients::ingredient_name,
+            recipes_ingredients::amount,
+            recipes_ingredients::ingredient_unit,
+        ))
+        .load::<(i32, String, i32, String)>(conn)?
+    {
+        ingredient_map.entry(recipe_id).or_default().push(IngredientAmount {
+            ingredient_name,
+            amount,
+            ingredient_unit,
+        });
+    }
+
+    let mut nutrient_map: HashMap<i32, BTreeMap<String, f64>> = HashMap::new();
+    for (recipe_id, name, quantity) in recipes_nutrients::table
+        .inner_join(nutrients::table)
+        .select((recipes_nutrients::recipe_id, nutrients::name, recipes_nutrients::quantity))
+        .load::<(i32, String, f64)>(conn)?
+    {
+        *nutrient_map.entry(recipe_id).or_default().entry(name).or_default() += quantity;
+    }
+
+    let mut allergen_map: HashMap<i32, Vec<String>> = HashMap::new();
+    for (recipe_id, name) in recipes_ingredient_allergies::table
+        .inner_join(ingredient_allergies::table)
+        .order(ingredient_allergies::ingredient_allergy_name.asc())
+        .select((recipes_ingredient_allergies::recipe_id, ingredient_allergies::ingredient_allergy_name))
+        .load::<(i32, String)>(conn)?
+    {
+        allergen_map.entry(recipe_id).or_default().push(name);
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(recipe_id, recipe_name, recipe_method, calories, calories_unit, recipe_img_link, food_category, dish_type)| {
+                RecipeRecord {
+                    recipe_name,
+                    recipe_method: strings(recipe_method),
+                    calories,
+                    calories_unit,
+                    recipe_img_link: strings(recipe_img_link),
+                    food_category: strings(Some(food_category)),
+                    dish_type: strings(dish_type),
+                    ingredients: ingredient_map.remove(&recipe_id).unwrap_or_default(),
+                    nutrients: nutrient_map.remove(&recipe_id).unwrap_or_default(),
+                    allergens: allergen_map.remove(&recipe_id).unwrap_or_default(),
+                }
+            },
+        )
+        .collect())
+}
+
+/// Name lookups for the reference tables a record points into.
+struct ReferenceIds {
+    ingredients: HashMap<String, i32>,
+    nutrients: HashMap<String, i32>,
+    allergens: HashMap<String, i32>,
+    ingredient_units: HashSet<String>,
+    calorie_units: HashSet<String>,
+}
+
+impl ReferenceIds {
+    /// Ingredients are found by Thai or English name; nutrient and allergen names and units ignore case.
+    fn load(conn: &mut PgConnection) -> QueryResult<Self> {
+        let mut ingredient_ids = HashMap::new();
+        for (id, name, name_eng) in ingredients::table
+            .select((ingredients::ingredient_id, ingredients::ingredient_name, ingredients::ingredient_name_eng))
+            .load::<(i32, String, Option<String>)>(conn)?
+        {
+            if let Some(name_eng) = name_eng {
+                ingredient_ids.entry(name_eng).or_insert(id);
+            }
+            ingredient_ids.insert(name, id);
+        }
+
+        let nutrient_ids = nutrients::table
+            .select((nutrients::name, nutrients::nutrient_id))
+            .load::<(String, i32)>(conn)?
+            .into_iter()
+            .map(|(name, id)| (name.to_lowercase(), id))
+            .collect();
+
+        let allergen_ids = ingredient_allergies::table
+            .select((ingredient_allergies::ingredient_allergy_name, ingredient_allergies::ingredient_allergy_id))
+            .load::<(String, i32)>(conn)?
+            .into_iter()
+            .map(|(name, id)| (name.to_lowercase(), id))
+            .collect();
+
+        let ingredient_units = recipes_ingredients::table
+            .select(recipes_ingredients::ingredient_unit)
+            .distinct()
+            .load::<String>(conn)?
+            .into_iter()
+            .chain(INGREDIENT_UNITS.iter().map(|unit| unit.to_string()))
+            .map(|unit| unit.to_lowercase())
+            .collect();
+
+        let calorie_units = recipes::table
+            .select(recipes::calories_unit)
+            .distinct()
+            .load::<String>(conn)?
+            .into_iter()
+            .chain(CALORIE_UNITS.iter().map(|unit| unit.to_string()))
+            .map(|unit| unit.to_lowercase())
+            .collect();
+
+        Ok(ReferenceIds {
+            ingredients: ingredient_ids,
+            nutrients: nutrient_ids,
+            allergens: allergen_ids,
+            ingredient_units,
+            calorie_units,
+        })
+    }
+
+    fn ingredient(&self, name: &str) -> Option<i32> {
+        self.ingredients.get(name).copied()
+    }
+
+    fn nutrient(&self, name: &str) -> Option<i32> {
+        self.nutrients.get(&name.to_lowercase()).copied()
+    }
+
+    fn allergen(&self, name: &str) -> Option<i32> {
+        self.allergens.get(&name.to_lowercase()).copied()
+    }
+
+    /// Everything wrong with the records, in file order.
+    fn validate(&self, records: &[RecipeRecord]) -> Vec<ImportIssue> {
+        let mut issues = Vec::new();
+        let mut seen = HashSet::new();
+
+        for record in records {
+            let mut issue = |field: &str, message: String| issues.push(ImportIssue::new(record, field, message));
+
+            if record.recipe_name.trim().is_empty() {
+                issue("recipe_name", "recipe_name is empty".to_string());
+            } else if !seen.insert(record.recipe_name.as_str()) {
+                issue("recipe_name", "recipe appears more than once in the file".to_string());
+            }
+            if !record.calories.is_finite() || record.calories < 0.0 {
+                issue("calories", format!("invalid calories {}", record.calories));
+            }
+            if !self.calorie_units.contains(&record.calories_unit.to_lowercase()) {
+                issue("calories_unit", format!("unknown unit '{}'", record.calories_unit));
+            }
+
+            for ingredient in &record.ingredients {
+                if self.ingredient(&ingredient.ingredient_name).is_none() {
+                    issue("ingredients", format!("unknown ingredient '{}'", ingredient.ingredient_name));
+                }
+                if !self.ingredient_units.contains(&ingredient.ingredient_unit.to_lowercase()) {
+                    issue(
+                        "ingredients",
+                        format!("unknown unit '{}' for '{}'", ingredient.ingredient_unit, ingredient.ingredient_name),
+                    );
+                }
+                if ingredient.amount < 0 {
+                    issue("ingredients", format!("negative amount for '{}'", ingredient.ingredient_name));
+                }
+            }
+
+            for (name, quantity) in &record.nutrients {
+                if self.nutrient(name).is_none() {
+                    issue("nutrients", format!("unknown nutrient '{}'", name));
+                } else if !quantity.is_finite() || *quantity < 0.0 {
+                    issue("nutrients", format!("invalid quantity {} for '{}'", quantity, name));
+                }
+            }
+
+            for name in &record.allergens {
+                if self.allergen(name).is_none() {
+                    issue("allergens", format!("unknown allergen '{}'", name));
+                }
+            }
+        }
+
+        issues
+    }
+}
+
+// Validation guarantees every name resolves; this only guards the writes against a gap there.
+fn resolved(id: Option<i32>, record: &RecipeRecord, field: &str) -> Result<i32, ImportError> {
+    id.ok_or_else(|| ImportError::Invalid(vec![ImportIssue::new(record, field, "name did not resolve".to_string())]))
+}
+
+/// Creates or replaces each recipe by name, with its ingredients, nutrients and allergens,
+/// all in one transaction. Nothing is written if any record has an issue, or on a dry run.
+/// Callers serving requests must invalidate the recipe catalog after a real import.
+pub fn import_recipes(conn: &mut PgConnection, records: &[RecipeRecord], dry_run: bool) -> Result<ImportReport, ImportError> {
+    conn.transaction(|conn| {
+        let ids = ReferenceIds::load(conn)?;
+        let issues = ids.validate(records);
+        if !issues.is_empty() {
+            return Err(ImportError::Invalid(issues));
+        }
+
+        let mut report = ImportReport {
+            dry_run,
+            ..ImportReport::default()
+        };
+
+        if dry_run {
+            let names: Vec<&str> = records.iter().map(|record| record.recipe_name.as_str()).collect();
+            let existing: HashSet<String> = recipes::table
+                .filter(recipes::recipe_name.eq_any(&names))
+                .select(recipes::recipe_name)
+                .load::<String>(conn)?
+                .into_iter()
+                .collect();
+            report.updated = records.iter().filter(|record| existing.contains(&record.recipe_name)).count();
+            report.created = records.len() - report.updated;
+            return Ok(report);
+        }
+
+        for record in records {
+            let values = (
+                recipes::recipe_name.eq(&record.recipe_name),
+                recipes::recipe_method.eq(nullable_strings(&record.recipe_method)),
+                recipes::calories.eq(record.calories),
+                recipes::calories_unit.eq(&record.calories_unit),
+                recipes::recipe_img_link.eq(nullable_strings(&record.recipe_img_link)),
+                recipes::food_category.eq(nullable_strings(&record.food_category)),
+                recipes::dish_type.eq(nullable_strings(&record.dish_type)),
+            );
+
+            let existing: Option<i32> = recipes::table
+                .filter(recipes::recipe_name.eq(&record.recipe_name))
+                .select(recipes::recipe_id)
+                .order(recipes::recipe_id.asc())
+                .first(conn)
+                .optional()?;
+
+            let recipe_id = match existing {
+                Some(recipe_id) => {
+                    diesel::update(recipes::table.filter(recipes::recipe_id.eq(recipe_id)))
+                        .set(values)
+                        .execute(conn)?;
+                    diesel::delete(recipes_ingredients::table.filter(recipes_ingredients::recipe_id.eq(recipe_id)))
+                        .execute(conn)?;
+                    diesel::delete(recipes_nutrients::table.filter(recipes_nutrients::recipe_id.eq(recipe_id)))
+                        .execute(conn)?;
+                    diesel::delete(
+                        recipes_ingredient_allergies::table.filter(recipes_ingredient_allergies::recipe_id.eq(recipe_id)),
+                    )
+                    .execute(conn)?;
+                    report.updated += 1;
+                    recipe_id
+                }
+                None => {
+                    report.created += 1;
+                    diesel::insert_into(recipes::table)
+                        .values(values)
+                        .returning(recipes::recipe_id)
+                        .get_result(conn)?
+                }
+            };
+
+            for ingredient in &record.ingredients {
+                diesel::insert_into(recipes_ingredients::table)
+                    .values((
+                        recipes_ingredients::recipe_id.eq(recipe_id),
+                        recipes_ingredients::ingredient_id.eq(resolved(ids.ingredient(&ingredient.ingredient_name), record, "ingredients")?),
+                        recipes_ingredients::amount.eq(ingredient.amount),
+                        recipes_ingredients::ingredient_unit.eq(&ingredient.ingredient_unit),
+                    ))
+                    .execute(conn)?;
+            }
+
+            for (name, quantity) in &record.nutrients {
+                diesel::insert_into(recipes_nutrients::table)
+                    .values((
+                        recipes_nutrients::recipe_id.eq(recipe_id),
+                        recipes_nutrients::nutrient_id.eq(resolved(ids.nutrient(name), record, "nutrients")?),
+                        recipes_nutrients::quantity.eq(quantity),
+                    ))
+                    .execute(conn)?;
+            }
+
+            for name in &record.allergens {
+                diesel::insert_into(recipes_ingredient_allergies::table)
+                    .values((
+                        recipes_ingredient_allergies::recipe_id.eq(recipe_id),
+                        recipes_ingredient_allergies::ingredient_allergy_id.eq(resolved(ids.allergen(name), record, "allergens")?),
+                    ))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+            }
+        }
+
+        Ok(report)
+    })
+}
+
+/// Separates the values of list columns in CSV, e.g. `main | soup`.
+const LIST_SEPARATOR: char = '|';
+
+/// Makes the next [`LIST_SEPARATOR`] or escape part of the value, e.g. `Stir \| fold`.
+const LIST_ESCAPE: char = '\\';
+
+/// Prefix of the CSV columns holding nutrient quantities, e.g. `nutrient_protein`.
+const NUTRIENT_PREFIX: &str = "nutrient_";
+
+const CSV_COLUMNS: [&str; 9] = [
+    "recipe_name",
+    "calories",
+    "calories_unit",
+    "food_category",
+    "dish_type",
+    "recipe_method",
+    "recipe_img_link",
+    "ingredients",
+    "allergens",
+];
+
+fn split_list(cell: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut value = String::new();
+    let mut chars = cell.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // A backslash before anything else is kept, so hand-written paths survive
+            LIST_ESCAPE => match chars.next_if(|next| *next == LIST_SEPARATOR || *next == LIST_ESCAPE) {
+                Some(escaped) => value.push(escaped),
+                None => value.push(LIST_ESCAPE),
+            },
+            LIST_SEPARATOR => values.push(std::mem::take(&mut value)),
+            c => value.push(c),
+        }
+    }
+    values.push(value);
+
+    values
+        .iter()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn join_list(values: &[String]) -> String {
+    let escaped: Vec<String> = values
+        .iter()
+        .map(|value| {
+            value
+                .replace(LIST_ESCAPE, &format!("{0}{0}", LIST_ESCAPE))
+                .replace(LIST_SEPARATOR, &format!("{}{}", LIST_ESCAPE, LIST_SEPARATOR))
+        })
+        .collect();
+    escaped.join(&format!(" {} ", LIST_SEPARATOR))
+}
+
+/// Parses `name amount unit`; the name may contain spaces, the amount and unit may not.
+fn parse_ingredient(value: &str) -> Result<IngredientAmount, String> {
+    let mut parts = value.rsplitn(3, char::is_whitespace);
+    let (Some(unit), Some(amount), Some(name)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(format!("ingredient '{}' is not 'name amount unit'", value));
+    };
+    let amount = amount
+        .parse()
+        .map_err(|_| format!("ingredient '{}' has a non-integer amount", value))?;
+    Ok(IngredientAmount {
+        ingredient_name: name.trim().to_string(),
+        amount,
+        ingredient_unit: unit.to_string(),
+    })
+}
+
+/// Reads recipes from CSV with a header row.
+///
+/// Columns are those of [`RecipeRecord`]; list columns hold `|`-separated values,
+/// with `\|` for a `|` inside a value and `\\` for a backslash before one,
+/// `ingredients` holds `name amount unit` entries (`Egg 1 piece | Rice 200 g`) and
+/// each `nutrient_<name>` column holds that nutrient's quantity, empty when unknown.
+/// Only `recipe_name`, `calories` and `calories_unit` are required.
+pub fn read_csv(input: &[u8]) -> Result<Vec<RecipeRecord>, ImportError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(input);
+    let headers = reader.headers().map_err(|err| ImportError::Parse(err.to_string()))?.clone();
+
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    for required in ["recipe_name", "calories", "calories_unit"] {
+        if column(required).is_none() {
+            return Err(ImportError::Parse(format!("missing column '{}'", required)));
+        }
+    }
+    let nutrient_columns: Vec<(usize, String)> = headers
+        .iter()
+        .enumerate()
+        .filter_map(|(index, header)| header.strip_prefix(NUTRIENT_PREFIX).map(|name| (index, name.to_string())))
+        .collect();
+
+    let mut records = Vec::new();
+    for (index, row) in reader.records().enumerate() {
+        let line = index + 2; // 1-based, after the header
+        let row = row.map_err(|err| ImportError::Parse(format!("row {}: {}", line, err)))?;
+        let cell = |name: &str| column(name).and_then(|index| row.get(index)).unwrap_or("");
+        let row_error = |message: String| ImportError::Parse(format!("row {}: {}", line, message));
+
+        let calories = cell("calories")
+            .parse()
+            .map_err(|_| row_error(format!("invalid calories '{}'", cell("calories"))))?;
+
+        let ingredients = split_list(cell("ingredients"))
+            .iter()
+            .map(|value| parse_ingredient(value))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(row_error)?;
+
+        let mut nutrients = BTreeMap::new();
+        for (index, name) in &nutrient_columns {
+            let value = row.get(*index).unwrap_or("");
+            if value.is_empty() {
+                continue;
+            }
+            let quantity = value
+                .parse()
+                .map_err(|_| row_error(format!("invalid {}{} '{}'", NUTRIENT_PREFIX, name, value)))?;
+            nutrients.insert(name.clone(), quantity);
+        }
+
+        records.push(RecipeRecord {
+            recipe_name: cell("recipe_name").to_string(),
+            recipe_method: split_list(cell("recipe_method")),
+            calories,
+            calories_unit: cell("calories_unit").to_string(),
+            recipe_img_link: split_list(cell("recipe_img_link")),
+            food_category: split_list(cell("food_category")),
+            dish_type: split_list(cell("dish_type")),
+            ingredients,
+            nutrients,
+            allergens: split_list(cell("allergens")),
+        });
+    }
+
+    Ok(records)
+}
+
+/// Writes recipes in the layout [`read_csv`] reads, with a column for every nutrient any recipe has.
+pub fn write_csv(records: &[RecipeRecord]) -> Result<String, csv::Error> {
+    let nutrient_names: BTreeSet<&String> = records.iter().flat_map(|record| record.nutrients.keys()).collect();
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let header: Vec<String> = CSV_COLUMNS
+        .iter()
+        .map(|column| column.to_string())
+        .chain(nutrient_names.iter().map(|name| format!("{}{}", NUTRIENT_PREFIX, name)))
+        .collect();
+    writer.write_record(&header)?;
+
+    for record in records {
+        let ingredients: Vec<String> = record
+            .ingredients
+            .iter()
+            .map(|ingredient| format!("{} {} {}", ingredient.ingredient_name, ingredient.amount, ingredient.ingredient_unit))
+            .collect();
+
+        let mut row = vec![
+            record.recipe_name.clone(),
+            record.calories.to_string(),
+            record.calories_unit.clone(),
+            join_list(&record.food_category),
+            join_list(&record.dish_type),
+            join_list(&record.recipe_method),
+            join_list(&record.recipe_img_link),
+            join_list(&ingredients),
+            join_list(&record.allergens),
+        ];
+        row.extend(
+            nutrient_names
+                .iter()
+                .map(|name| record.nutrients.get(*name).map(|quantity| quantity.to_string()).unwrap_or_default()),
+        );
+        writer.write_record(&row)?;
+    }
+
+    let bytes = writer.into_inner().map_err(|err| err.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Parses an uploaded file in the given format.
+pub fn read_records(format: RecipeFormat, input: &[u8]) -> Result<Vec<RecipeRecord>, ImportError> {
+    match format {
+        RecipeFormat::Json => serde_json::from_slice(input).map_err(|err| ImportError::Parse(err.to_string())),
+        RecipeFormat::Csv => read_csv(input),
+    }
+}
+
+/// Serializes recipes in the given format.
+pub fn write_records(format: RecipeFormat, records: &[RecipeRecord]) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(match format {
+        RecipeFormat::Json => serde_json::to_string_pretty(records)?,
+        RecipeFormat::Csv => write_csv(records)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &str) -> RecipeRecord {
+        RecipeRecord {
+            recipe_name: name.to_string(),
+            recipe_method: vec!["Boil the rice".to_string()],
+            calories: 250.5,
+            calories_unit: "kcal".to_string(),
+            recipe_img_link: vec!["https://example.com/rice.png".to_string()],
+            food_category: vec!["main".to_string(), "rice".to_string()],
+            dish_type: vec!["soup".to_string()],
+            ingredients: vec![IngredientAmount {
+                ingredient_name: "Jasmine rice".to_string(),
+                amount: 200,
+                ingredient_unit: "g".to_string(),
+            }],
+            nutrients: BTreeMap::from([("protein".to_string(), 4.5), ("sodium".to_string(), 120.0)]),
+            allergens: vec!["Egg".to_string()],
+        }
+    }
+
+    fn reference_ids() -> ReferenceIds {
+        ReferenceIds {
+            ingredients: HashMap::from([("Jasmine rice".to_string(), 1), ("ข้าว".to_string(), 1)]),
+            nutrients: HashMap::from([("protein".to_string(), 6), ("sodium".to_string(), 7)]),
+            allergens: HashMap::from([("egg".to_string(), 1)]),
+            ingredient_units: HashSet::from(["g".to_string()]),
+            calorie_units: HashSet::from(["kcal".to_string()]),
+        }
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        let mut plain = record("Rice soup");
+        plain.nutrients.clear();
+        let records = vec![record("Fried rice"), plain];
+
+        let csv = write_csv(&records).unwrap();
+        assert!(csv.starts_with("recipe_name,calories,calories_unit,food_category,"), "{}", csv);
+        assert_eq!(read_csv(csv.as_bytes()).unwrap(), records);
+    }
+
+    #[test]
+    fn csv_keeps_separators_commas_and_newlines_inside_values() {
+        let mut tricky = record("Rice, egg | soup");
+        tricky.recipe_method = vec![
+            "Mix A | B, then stir".to_string(),
+            "Serve hot\nwith lime".to_string(),
+            "Ends with a backslash \\".to_string(),
+            "Keeps \\| as typed".to_string(),
+        ];
+        tricky.ingredients[0].ingredient_name = "Rice | brown".to_string();
+        let records = vec![tricky];
+
+        let csv = write_csv(&records).unwrap();
+        assert_eq!(read_csv(csv.as_bytes()).unwrap(), records);
+    }
+
+    #[test]
+    fn hand_written_csv_is_read() {
+        let csv = "recipe_name,calories,calories_unit,food_category,ingredients,nutrient_protein\n\
+                   Omelette, 180 ,kcal,main | egg,Egg 2 piece | Fish sauce 5 ml,\n";
+        let records = read_csv(csv.as_bytes()).unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].food_category, vec!["main", "egg"]);
+        assert_eq!(records[0].ingredients[1].ingredient_name, "Fish sauce");
+        assert!(records[0].nutrients.is_empty());
+        assert!(records[0].recipe_method.is_empty());
+    }
+
+    #[test]
+    fn csv_errors_name_the_row() {
+        let missing = read_csv(b"recipe_name,calories\nRice,100\n").unwrap_err();
+        assert_eq!(missing.to_string(), "invalid file: missing column 'calories_unit'");
+
+        let csv = "recipe_name,calories,calories_unit\nRice,100,kcal\nSoup,lots,kcal\n";
+        let invalid = read_csv(csv.as_bytes()).unwrap_err();
+        assert_eq!(invalid.to_string(), "invalid file: row 3: invalid calories 'lots'");
+    }
+
+    #[test]
+    fn ingredients_are_name_amount_unit() {
+        assert_eq!(
+            parse_ingredient("Fish sauce 15 ml").unwrap(),
+            IngredientAmount { ingredient_name: "Fish sauce".to_string(), amount: 15, ingredient_unit: "ml".to_string() }
+        );
+        assert_eq!(parse_ingredient("ไข่ 2 piece").unwrap().ingredient_name, "ไข่");
+        assert!(parse_ingredient("Egg 2").unwrap_err().contains("not 'name amount unit'"));
+        assert!(parse_ingredient("Egg two piece").unwrap_err().contains("non-integer amount"));
+    }
+
+    #[test]
+    fn known_names_validate() {
+        let mut thai_name = record("Khao tom");
+        thai_name.ingredients[0].ingredient_name = "ข้าว".to_string();
+        thai_name.allergens = vec!["EGG".to_string()];
+
+        assert_eq!(reference_ids().validate(&[record("Fried rice"), thai_name]), Vec::new());
+    }
+
+    #[test]
+    fn unknown_names_are_reported() {
+        let mut unknown = record("Fried rice");
+        unknown.ingredients[0].ingredient_name = "Dragon fruit".to_string();
+        unknown.ingredients[0].ingredient_unit = "handful".to_string();
+        unknown.allergens = vec!["Egg".to_string(), "Peanut".to_string()];
+        unknown.nutrients.insert("vitamin_z".to_string(), 1.0);
+
+        let messages: Vec<(String, String)> = reference_ids()
+            .validate(&[unknown, record("Fried rice")])
+            .into_iter()
+            .map(|issue| (issue.field, issue.message))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                ("ingredients".to_string(), "unknown ingredient 'Dragon fruit'".to_string()),
+                ("ingredients".to_string(), "unknown unit 'handful' for 'Dragon fruit'".to_string()),
+                ("nutrients".to_string(), "unknown nutrient 'vitamin_z'".to_string()),
+                ("allergens".to_string(), "unknown allergen 'Peanut'".to_string()),
+                ("recipe_name".to_string(), "recipe appears more than once in the file".to_string()),
+            ]
+        );
+    }
+}
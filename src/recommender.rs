@@ -0,0 +1,583 @@
+//! Client for the external AI meal plan recommender.
+//!
+//! Handlers talk to the recommender through the [`Recommender`] trait so the
+//! HTTP backend can be swapped for [`MockRecommender`] in offline environments
+//! (`AI_RECOMMENDER_URL=mock`). The tests point [`HttpRecommender`] at a local
+//! stub server instead, to exercise its retries and circuit breaker.
+//!
+//! Only signs of an outage (connection errors, timeouts and 5xx answers) count
+//! towards opening the circuit; a 4xx means the recommender is up and refused
+//! that one request.
+
+use crate::config::Settings;
+use async_trait::async_trait;
+use metrics::{counter, histogram};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+pub const DEFAULT_RECOMMENDER_URL: &str = "https://ai-rec-1025044834972.asia-southeast1.run.app";
+
+/// Timeout of [`Recommender::ping`].
+const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug)]
+pub enum RecommenderError {
+    /// Too many consecutive failures; calls are rejected until the cooldown ends.
+    CircuitOpen,
+    /// The request could not be sent or timed out.
+    Request(reqwest::Error),
+    /// The recommender answered with a non-success status.
+    Status(StatusCode),
+    /// The response body was not valid JSON.
+    Decode(reqwest::Error),
+}
+
+impl fmt::Display for RecommenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecommenderError::CircuitOpen => write!(f, "recommender circuit is open"),
+            RecommenderError::Request(err) => write!(f, "failed to send request: {}", err),
+            RecommenderError::Status(status) => write!(f, "recommender returned {}", status),
+            RecommenderError::Decode(err) => write!(f, "failed to parse response: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for RecommenderError {}
+
+impl RecommenderError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            RecommenderError::CircuitOpen | RecommenderError::Decode(_) => false,
+            RecommenderError::Request(_) => true,
+            RecommenderError::Status(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+
+    /// Whether the error suggests the recommender is down, which counts towards opening the circuit.
+    fn is_outage(&self) -> bool {
+        match self {
+            RecommenderError::Request(_) => true,
+            RecommenderError::Status(status) => status.is_server_error(),
+            RecommenderError::CircuitOpen | RecommenderError::Decode(_) => false,
+        }
+    }
+
+    /// Label for `recommender_errors_total`.
+    fn reason(&self) -> &'static str {
+        match self {
+            RecommenderError::CircuitOpen => "circuit_open",
+            RecommenderError::Request(_) => "request",
+            RecommenderError::Status(_) => "status",
+            RecommenderError::Decode(_) => "decode",
+        }
+    }
+}
+
+#[async_trait]
+pub trait Recommender: Send + Sync {
+    /// Generates a new meal plan (`POST /ai`).
+    async fn generate(&self, request: &Value) -> Result<Value, RecommenderError>;
+
+    /// Regenerates a meal plan from an existing one (`POST /ai_update`).
+    async fn update(&self, request: &Value) -> Result<Value, RecommenderError>;
+
+    /// Payload contract the recommender expects; see [`crate::identity::PayloadIdentity`].
+    fn contract(&self) -> ContractVersion {
+        ContractVersion::V1
+    }
+
+    /// Checks the recommender can be reached, for readiness probes.
+    async fn ping(&self) -> Result<(), RecommenderError> {
+        Ok(())
+    }
+}
+
+/// Version of the request payload contract spoken with the recommender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContractVersion {
+    /// Original payloads, with the user ids labelled inconsistently.
+    #[default]
+    V1,
+    /// Both user ids under their own names, plus `contract_version`.
+    V2,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecommenderConfig {
+    pub base_url: String, // "mock" answers every call with an empty plan, for local development
+    pub generate_path: String,
+    pub update_path: String,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub max_retries: u32,
+    pub retry_backoff: Duration,
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+    pub contract: ContractVersion,
+}
+
+impl Default for RecommenderConfig {
+    fn default() -> Self {
+        RecommenderConfig {
+            base_url: DEFAULT_RECOMMENDER_URL.to_string(),
+            generate_path: "/ai".to_string(),
+            update_path: "/ai_update".to_string(),
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(5),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(200),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+            contract: ContractVersion::V1,
+        }
+    }
+}
+
+impl RecommenderConfig {
+    /// Reads `AI_RECOMMENDER_URL`, `AI_RECOMMENDER_GENERATE_PATH`, `AI_RECOMMENDER_UPDATE_PATH`,
+    /// `AI_RECOMMENDER_TIMEOUT_SECS`, `AI_RECOMMENDER_CONNECT_TIMEOUT_SECS`,
+    /// `AI_RECOMMENDER_MAX_RETRIES`, `AI_RECOMMENDER_RETRY_BACKOFF_MS` (doubled per retry),
+    /// `AI_RECOMMENDER_FAILURE_THRESHOLD` and `AI_RECOMMENDER_COOLDOWN_SECS` (the circuit
+    /// breaker) and `AI_RECOMMENDER_CONTRACT` (`1` or `2`), keeping the defaults for
+    /// anything unset.
+    pub fn from_settings(settings: &mut Settings) -> Self {
+        let defaults = RecommenderConfig::default();
+
+        let config = RecommenderConfig {
+            base_url: settings.string("AI_RECOMMENDER_URL", &defaults.base_url).trim_end_matches('/').to_string(),
+            generate_path: settings.string("AI_RECOMMENDER_GENERATE_PATH", &defaults.generate_path),
+            update_path: settings.string("AI_RECOMMENDER_UPDATE_PATH", &defaults.update_path),
+            timeout: settings.secs("AI_RECOMMENDER_TIMEOUT_SECS", defaults.timeout),
+            connect_timeout: settings.secs("AI_RECOMMENDER_CONNECT_TIMEOUT_SECS", defaults.connect_timeout),
+            max_retries: settings.parse("AI_RECOMMENDER_MAX_RETRIES", defaults.max_retries),
+            retry_backoff: settings
+                .parse_optional("AI_RECOMMENDER_RETRY_BACKOFF_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.retry_backoff),
+            failure_threshold: settings.parse("AI_RECOMMENDER_FAILURE_THRESHOLD", defaults.failure_threshold),
+            cooldown: settings.secs("AI_RECOMMENDER_COOLDOWN_SECS", defaults.cooldown),
+            contract: match settings.string("AI_RECOMMENDER_CONTRACT", "1").trim() {
+                "1" => ContractVersion::V1,
+                "2" => ContractVersion::V2,
+                other => {
+                    settings.error(format!("AI_RECOMMENDER_CONTRACT={:?} is invalid: expected 1 or 2", other));
+                    ContractVersion::V1
+                }
+            },
+        };
+
+        let is_url = config.base_url.starts_with("http://") || config.base_url.starts_with("https://");
+        if config.base_url != "mock" && !is_url {
+            settings.error(format!("AI_RECOMMENDER_URL={:?} is not an http(s) URL or \"mock\"", config.base_url));
+        }
+        for (key, path) in [
+            ("AI_RECOMMENDER_GENERATE_PATH", &config.generate_path),
+            ("AI_RECOMMENDER_UPDATE_PATH", &config.update_path),
+        ] {
+            if !path.starts_with('/') {
+                settings.error(format!("{}={:?} must start with /", key, path));
+            }
+        }
+        if config.timeout.is_zero() {
+            settings.error("AI_RECOMMENDER_TIMEOUT_SECS must be positive");
+        }
+        if config.failure_threshold == 0 {
+            settings.error("AI_RECOMMENDER_FAILURE_THRESHOLD must be positive");
+        }
+
+        config
+    }
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Recommender backed by the remote HTTP service, sharing one connection pool.
+pub struct HttpRecommender {
+    client: Client,
+    config: RecommenderConfig,
+    circuit: Mutex<CircuitState>,
+}
+
+impl HttpRecommender {
+    pub fn new(config: RecommenderConfig) -> Result<Self, reqwest::Error> {
+        let client = Client::builder()
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            .build()?;
+
+        Ok(HttpRecommender {
+            client,
+            config,
+            circuit: Mutex::new(CircuitState::default()),
+        })
+    }
+
+    fn circuit_allows(&self) -> bool {
+        let mut circuit = self.circuit.lock().unwrap();
+        match circuit.open_until {
+            Some(until) if Instant::now() < until => false,
+            Some(_) => {
+                // Cooldown over: let the next call through as a probe.
+                circuit.open_until = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn record_success(&self) {
+        let mut circuit = self.circuit.lock().unwrap();
+        circuit.consecutive_failures = 0;
+        circuit.open_until = None;
+    }
+
+    fn record_failure(&self) {
+        let mut circuit = self.circuit.lock().unwrap();
+        circuit.consecutive_failures += 1;
+        if circuit.consecutive_failures >= self.config.failure_threshold {
+            warn!(
+                failures = circuit.consecutive_failures,
+                cooldown = ?self.config.cooldown,
+                "Recommender keeps failing, opening circuit"
+            );
+            circuit.open_until = Some(Instant::now() + self.config.cooldown);
+        }
+    }
+
+    async fn send_once(&self, url: &str, request: &Value) -> Result<Value, RecommenderError> {
+        let response = self
+            .client
+            .post(url)
+            .json(request)
+            .send()
+            .await
+            .map_err(RecommenderError::Request)?;
+
+        if !response.status().is_success() {
+            return Err(RecommenderError::Status(response.status()));
+        }
+
+        response.json::<Value>().await.map_err(RecommenderError::Decode)
+    }
+
+    async fn post(&self, path: &str, request: &Value) -> Result<Value, RecommenderError> {
+        let result = self.post_with_retries(path, request).await;
+        if let Err(err) = &result {
+            counter!("recommender_errors_total", "endpoint" => path.to_string(), "reason" => err.reason()).increment(1);
+        }
+        result
+    }
+
+    async fn post_with_retries(&self, path: &str, request: &Value) -> Result<Value, RecommenderError> {
+        if !self.circuit_allows() {
+            return Err(RecommenderError::CircuitOpen);
+        }
+
+        let url = format!("{}{}", self.config.base_url, path);
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let result = self.send_once(&url, request).await;
+            let outcome = if result.is_ok() { "ok" } else { "error" };
+            histogram!("recommender_request_duration_seconds", "endpoint" => path.to_string(), "outcome" => outcome)
+                .record(started.elapsed().as_secs_f64());
+
+            match result {
+                Ok(value) => {
+                    self.record_success();
+                    return Ok(value);
+                }
+                Err(err) if err.is_retryable() && attempt < self.config.max_retries => {
+                    let backoff = self.config.retry_backoff * 2u32.pow(attempt);
+                    warn!(%url, error = %err, ?backoff, "Recommender call failed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(err) if err.is_outage() => {
+                    self.record_failure();
+                    return Err(err);
+                }
+                Err(err) => {
+                    // It answered, so it is up; a refused request says nothing about its health
+                    self.record_success();
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Recommender for HttpRecommender {
+    async fn generate(&self, request: &Value) -> Result<Value, RecommenderError> {
+        self.post(&self.config.generate_path, request).await
+    }
+
+    async fn update(&self, request: &Value) -> Result<Value, RecommenderError> {
+        self.post(&self.config.update_path, request).await
+    }
+
+    fn contract(&self) -> ContractVersion {
+        self.config.contract
+    }
+
+    /// Any answer counts: the base URL need not have a route, and a probe must
+    /// not open the circuit or wait for the full request timeout.
+    async fn ping(&self) -> Result<(), RecommenderError> {
+        if !self.circuit_allows() {
+            return Err(RecommenderError::CircuitOpen);
+        }
+        self.client
+            .get(&self.config.base_url)
+            .timeout(PING_TIMEOUT)
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(RecommenderError::Request)
+    }
+}
+
+/// Recommender that answers every call with a fixed response, without network access.
+pub struct MockRecommender {
+    response: Value,
+}
+
+impl MockRecommender {
+    pub fn new(response: Value) -> Self {
+        MockRecommender { response }
+    }
+}
+
+#[async_trait]
+impl Recommender for MockRecommender {
+    async fn generate(&self, _request: &Value) -> Result<Value, RecommenderError> {
+        Ok(self.response.clone())
+    }
+
+    async fn update(&self, _request: &Value) -> Result<Value, RecommenderError> {
+        Ok(self.response.clone())
+    }
+}
+
+/// A generated meal plan as returned by the recommender (and by the built-in planner).
+/// Fields other than the recipe ids are passed through to the client untouched.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AiMealPlan {
+    pub mealplans: Vec<Vec<AiMenu>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AiMenu {
+    pub recipe_id: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meal_time: Option<i32>, // Set by the built-in planner; saved by position when missing
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl AiMealPlan {
+    /// The recipe ids of each day, in meal order.
+    pub fn recipe_ids(&self) -> Vec<Vec<i32>> {
+        self.mealplans
+            .iter()
+            .map(|day| day.iter().map(|menu| menu.recipe_id).collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use serde_json::json;
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    /// Answers each request with the next scripted status (200 with an empty plan
+    /// once the script runs out) and counts the requests.
+    #[derive(Clone, Default)]
+    struct Stub {
+        script: Arc<Mutex<VecDeque<StatusCode>>>,
+        requests: Arc<Mutex<usize>>,
+    }
+
+    impl Stub {
+        fn answer(&self, statuses: &[StatusCode]) {
+            self.script.lock().unwrap().extend(statuses);
+        }
+
+        fn requests(&self) -> usize {
+            *self.requests.lock().unwrap()
+        }
+    }
+
+    async fn stub_handler(State(stub): State<Stub>) -> (StatusCode, Json<Value>) {
+        *stub.requests.lock().unwrap() += 1;
+        let status = stub.script.lock().unwrap().pop_front().unwrap_or(StatusCode::OK);
+        (status, Json(json!({ "mealplans": [] })))
+    }
+
+    async fn serve_stub() -> (Stub, String) {
+        let stub = Stub::default();
+        let app = Router::new().route("/ai", post(stub_handler)).with_state(stub.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (stub, base_url)
+    }
+
+    fn recommender(base_url: &str, max_retries: u32, failure_threshold: u32, cooldown: Duration) -> HttpRecommender {
+        HttpRecommender::new(RecommenderConfig {
+            base_url: base_url.to_string(),
+            max_retries,
+            retry_backoff: Duration::from_millis(1),
+            failure_threshold,
+            cooldown,
+            ..RecommenderConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn mock_answers_with_its_fixed_response() {
+        // What `AI_RECOMMENDER_URL=mock` runs with
+        let recommender: Arc<dyn Recommender> = Arc::new(MockRecommender::new(json!({ "mealplans": [[{ "recipe_id": 1 }]] })));
+
+        let generated = recommender.generate(&json!({ "days": 1 })).await.unwrap();
+        let plan: AiMealPlan = serde_json::from_value(generated).unwrap();
+        assert_eq!(plan.recipe_ids(), vec![vec![1]]);
+        assert_eq!(recommender.update(&json!({})).await.unwrap(), json!({ "mealplans": [[{ "recipe_id": 1 }]] }));
+        assert_eq!(recommender.contract(), ContractVersion::V1);
+        assert!(recommender.ping().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_up_to_max_retries() {
+        let (stub, base_url) = serve_stub().await;
+        let recommender = recommender(&base_url, 2, 5, Duration::from_secs(60));
+
+        stub.answer(&[StatusCode::SERVICE_UNAVAILABLE; 3]);
+        let err = recommender.generate(&json!({})).await.unwrap_err();
+        assert!(matches!(err, RecommenderError::Status(StatusCode::SERVICE_UNAVAILABLE)), "{}", err);
+        assert_eq!(stub.requests(), 3);
+
+        // A retry that succeeds hides the failure
+        stub.answer(&[StatusCode::BAD_GATEWAY]);
+        assert!(recommender.generate(&json!({})).await.is_ok());
+        assert_eq!(stub.requests(), 5);
+    }
+
+    #[tokio::test]
+    async fn circuit_opens_after_consecutive_failures() {
+        let (stub, base_url) = serve_stub().await;
+        let recommender = recommender(&base_url, 0, 2, Duration::from_secs(60));
+
+        stub.answer(&[StatusCode::INTERNAL_SERVER_ERROR; 2]);
+        assert!(recommender.generate(&json!({})).await.is_err());
+        assert!(recommender.generate(&json!({})).await.is_err());
+
+        // Rejected without reaching the recommender
+        let err = recommender.generate(&json!({})).await.unwrap_err();
+        assert!(matches!(err, RecommenderError::CircuitOpen), "{}", err);
+        assert!(matches!(recommender.ping().await, Err(RecommenderError::CircuitOpen)));
+        assert_eq!(stub.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn connection_errors_open_the_circuit() {
+        // Nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let recommender = recommender(&base_url, 0, 1, Duration::from_secs(60));
+
+        let err = recommender.generate(&json!({})).await.unwrap_err();
+        assert!(matches!(err, RecommenderError::Request(_)), "{}", err);
+
+        let err = recommender.generate(&json!({})).await.unwrap_err();
+        assert!(matches!(err, RecommenderError::CircuitOpen), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn circuit_closes_when_a_call_succeeds_after_the_cooldown() {
+        let (stub, base_url) = serve_stub().await;
+        let recommender = recommender(&base_url, 0, 2, Duration::from_millis(50));
+
+        stub.answer(&[StatusCode::INTERNAL_SERVER_ERROR; 2]);
+        assert!(recommender.generate(&json!({})).await.is_err());
+        assert!(recommender.generate(&json!({})).await.is_err());
+        assert!(matches!(recommender.generate(&json!({})).await, Err(RecommenderError::CircuitOpen)));
+
+        // Half open: the first call after the cooldown goes through
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(recommender.generate(&json!({})).await.is_ok());
+        assert_eq!(stub.requests(), 3);
+
+        // The failure count started over, so one failure does not reopen it
+        stub.answer(&[StatusCode::INTERNAL_SERVER_ERROR]);
+        assert!(matches!(recommender.generate(&json!({})).await, Err(RecommenderError::Status(_))));
+        assert!(recommender.generate(&json!({})).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn failed_probe_after_the_cooldown_reopens_the_circuit() {
+        let (stub, base_url) = serve_stub().await;
+        let recommender = recommender(&base_url, 0, 2, Duration::from_millis(50));
+
+        stub.answer(&[StatusCode::INTERNAL_SERVER_ERROR; 3]);
+        assert!(recommender.generate(&json!({})).await.is_err());
+        assert!(recommender.generate(&json!({})).await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(matches!(recommender.generate(&json!({})).await, Err(RecommenderError::Status(_))));
+        assert!(matches!(recommender.generate(&json!({})).await, Err(RecommenderError::CircuitOpen)));
+        assert_eq!(stub.requests(), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_neither_retry_nor_open_the_circuit() {
+        let (stub, base_url) = serve_stub().await;
+        let recommender = recommender(&base_url, 2, 2, Duration::from_secs(60));
+
+        stub.answer(&[StatusCode::BAD_REQUEST, StatusCode::UNPROCESSABLE_ENTITY, StatusCode::NOT_FOUND]);
+        for expected in [StatusCode::BAD_REQUEST, StatusCode::UNPROCESSABLE_ENTITY, StatusCode::NOT_FOUND] {
+            let err = recommender.generate(&json!({})).await.unwrap_err();
+            assert!(matches!(err, RecommenderError::Status(status) if status == expected), "{}", err);
+        }
+        assert_eq!(stub.requests(), 3);
+
+        assert!(recommender.generate(&json!({})).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn client_errors_reset_the_failure_count() {
+        let (stub, base_url) = serve_stub().await;
+        let recommender = recommender(&base_url, 0, 2, Duration::from_secs(60));
+
+        stub.answer(&[StatusCode::SERVICE_UNAVAILABLE, StatusCode::BAD_REQUEST, StatusCode::SERVICE_UNAVAILABLE]);
+        for _ in 0..3 {
+            assert!(recommender.generate(&json!({})).await.is_err());
+        }
+
+        // Two 503s, but not in a row
+        assert!(recommender.generate(&json!({})).await.is_ok());
+        assert_eq!(stub.requests(), 4);
+    }
+}
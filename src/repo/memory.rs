@@ -0,0 +1,563 @@
+//! In-memory repository for exercising the service layer without Postgres.
+//!
+//! Collections are public so a caller can seed exactly the rows it needs.
+//! Ids are handed out from a single counter shared by every kind of row,
+//! which is enough to keep them unique within one repository.
+
+use super::{
+    AdminRepo, ClaimedJob, IngredientRepo, JobRepo, MealPlanFilter, MealPlanRepo, NutrientRepo, RecipeRepo, TemplateRepo,
+    UserRepo, ACTIVE_MEAL_PLAN_INDEX,
+};
+use crate::auth::Role;
+use crate::authz::OwnedMealPlanRecipe;
+use crate::catalog::CatalogRecipe;
+use crate::identity::{LineUserId, UserId};
+use crate::db::Database;
+use crate::error::AppResult;
+use crate::jobs::{is_finished, STATUS_FAILED, STATUS_PENDING, STATUS_RUNNING};
+use crate::models::{Admin, MealPlanJob};
+use crate::routes::ingredient::Ingredient;
+use crate::routes::mealplan::{MealPlanEntry, Nutrition, Recipe, RecipeInfo};
+use crate::routes::recipe::UpdateRecipe;
+use crate::routes::template::TemplateEntry;
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind, Error as DieselError};
+use diesel::QueryResult;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct MemoryUser {
+    pub user_id: UserId,
+    pub line_id: LineUserId,
+    pub allergy_ids: HashSet<i32>,
+    pub limits: Nutrition,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryMealPlan {
+    pub meal_plan_id: i32,
+    pub user_id: UserId,
+    pub name: String,
+    pub date: NaiveDate,
+    pub archived_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryMealPlanRecipe {
+    pub meal_plan_recipe_id: i32,
+    pub meal_plan_id: i32,
+    pub recipe_id: i32,
+    pub meal_time: i32,
+    pub ischecked: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryTemplateRecipe {
+    pub template_recipe_id: i32,
+    pub template_id: i32,
+    pub day_index: usize,
+    pub recipe_id: i32,
+    pub meal_time: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryRepo {
+    pub users: Vec<MemoryUser>,
+    pub recipes: Vec<CatalogRecipe>,
+    pub meal_plans: Vec<MemoryMealPlan>,
+    pub meal_plan_recipes: Vec<MemoryMealPlanRecipe>,
+    pub templates: Vec<TemplateEntry>, // `mealplans` stays empty; the days are in `template_recipes`
+    pub template_recipes: Vec<MemoryTemplateRecipe>,
+    pub ingredients: Vec<Ingredient>,
+    pub jobs: Vec<MealPlanJob>,
+    pub admins: Vec<Admin>,
+    last_id: i32,
+}
+
+impl MemoryRepo {
+    pub fn new() -> Self {
+        MemoryRepo::default()
+    }
+
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn user(&self, user_id: UserId) -> Option<&MemoryUser> {
+        self.users.iter().find(|user| user.user_id == user_id)
+    }
+
+    fn active_plans(&self, user_id: UserId, start_date: NaiveDate, end_date: NaiveDate) -> Vec<&MemoryMealPlan> {
+        let mut plans: Vec<&MemoryMealPlan> = self
+            .meal_plans
+            .iter()
+            .filter(|plan| plan.user_id == user_id && plan.archived_at.is_none())
+            .filter(|plan| plan.date >= start_date && plan.date <= end_date)
+            .collect();
+        plans.sort_by_key(|plan| plan.date);
+        plans
+    }
+}
+
+impl UserRepo for MemoryRepo {
+    fn find_user(&mut self, line_id: &LineUserId) -> QueryResult<Option<UserId>> {
+        Ok(self.users.iter().find(|user| &user.line_id == line_id).map(|user| user.user_id))
+    }
+
+    fn allergy_ids(&mut self, user_id: UserId) -> QueryResult<HashSet<i32>> {
+        Ok(self.user(user_id).map(|user| user.allergy_ids.clone()).unwrap_or_default())
+    }
+}
+
+impl RecipeRepo for MemoryRepo {
+    fn load_catalog(&mut self) -> QueryResult<Vec<CatalogRecipe>> {
+        let mut recipes = self.recipes.clone();
+        recipes.sort_by_key(|recipe| recipe.menu.recipe_id);
+        Ok(recipes)
+    }
+
+    // Only the fields the catalog carries are applied; the rest live in Postgres alone.
+    fn update_recipe(&mut self, recipe_id: i32, changes: UpdateRecipe) -> QueryResult<usize> {
+        let Some(recipe) = self.recipes.iter_mut().find(|recipe| recipe.menu.recipe_id == recipe_id) else {
+            return Ok(0);
+        };
+        if let Some(name) = changes.recipe_name {
+            recipe.menu.name = name;
+        }
+        if let Some(img_link) = changes.recipe_img_link {
+            recipe.menu.recipe_img_link = img_link.into_iter().flatten().collect();
+        }
+        Ok(1)
+    }
+
+    fn delete_recipe(&mut self, recipe_id: i32) -> QueryResult<usize> {
+        let before = self.recipes.len();
+        self.recipes.retain(|recipe| recipe.menu.recipe_id != recipe_id);
+        Ok(before - self.recipes.len())
+    }
+}
+
+impl NutrientRepo for MemoryRepo {
+    fn daily_limits(&mut self, user_id: UserId) -> QueryResult<Nutrition> {
+        Ok(self.user(user_id).map(|user| user.limits.clone()).unwrap_or_default())
+    }
+}
+
+impl MealPlanRepo for MemoryRepo {
+    fn atomically<T, F>(&mut self, f: F) -> QueryResult<T>
+    where
+        F: FnOnce(&mut Self) -> QueryResult<T>,
+    {
+        let snapshot = self.clone();
+        let result = f(self);
+        if result.is_err() {
+            *self = snapshot;
+        }
+        result
+    }
+
+    fn latest_active_date(&mut self, user_id: UserId) -> QueryResult<Option<NaiveDate>> {
+        Ok(self
+            .meal_plans
+            .iter()
+            .filter(|plan| plan.user_id == user_id && plan.archived_at.is_none())
+            .map(|plan| plan.date)
+            .max())
+    }
+
+    fn active_dates(&mut self, user_id: UserId, start_date: NaiveDate, end_date: NaiveDate) -> QueryResult<Vec<NaiveDate>> {
+        Ok(self.active_plans(user_id, start_date, end_date).iter().map(|plan| plan.date).collect())
+    }
+
+    fn active_meal_plan_ids(&mut self, user_id: UserId, start_date: NaiveDate, end_date: NaiveDate) -> QueryResult<Vec<i32>> {
+        Ok(self.active_plans(user_id, start_date, end_date).iter().map(|plan| plan.meal_plan_id).collect())
+    }
+
+    fn create_meal_plan(&mut self, user_id: UserId, name: &str, date: NaiveDate) -> QueryResult<i32> {
+        if !self.active_plans(user_id, date, date).is_empty() {
+            return Err(DieselError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                Box::new(UniqueViolation(ACTIVE_MEAL_PLAN_INDEX)),
+            ));
+        }
+
+        let meal_plan_id = self.next_id();
+        self.meal_plans.push(MemoryMealPlan {
+            meal_plan_id,
+            user_id,
+            name: name.to_string(),
+            date,
+            archived_at: None,
+        });
+        Ok(meal_plan_id)
+    }
+
+    fn recipe_count(&mut self, meal_plan_id: i32) -> QueryResult<usize> {
+        Ok(self.meal_plan_recipes.iter().filter(|recipe| recipe.meal_plan_id == meal_plan_id).count())
+    }
+
+    fn clear_recipes(&mut self, meal_plan_id: i32) -> QueryResult<usize> {
+        let before = self.meal_plan_recipes.len();
+        self.meal_plan_recipes.retain(|recipe| recipe.meal_plan_id != meal_plan_id);
+        Ok(before - self.meal_plan_recipes.len())
+    }
+
+    fn add_recipe(&mut self, meal_plan_id: i32, recipe_id: i32, meal_time: i32) -> QueryResult<()> {
+        let meal_plan_recipe_id = self.next_id();
+        self.meal_plan_recipes.push(MemoryMealPlanRecipe {
+            meal_plan_recipe_id,
+            meal_plan_id,
+            recipe_id,
+            meal_time,
+            ischecked: false,
+        });
+        Ok(())
+    }
+
+    fn meal_plan_recipe_owner(&mut self, meal_plan_recipe_id: i32) -> QueryResult<Option<UserId>> {
+        let meal_plan_id = self
+            .meal_plan_recipes
+            .iter()
+            .find(|recipe| recipe.meal_plan_recipe_id == meal_plan_recipe_id)
+            .map(|recipe| recipe.meal_plan_id);
+        Ok(meal_plan_id.and_then(|id| self.meal_plans.iter().find(|plan| plan.meal_plan_id == id).map(|plan| plan.user_id)))
+    }
+
+    fn set_checked(&mut self, entry: OwnedMealPlanRecipe, checked: bool) -> QueryResult<usize> {
+        let mut updated = 0;
+        for recipe in self.meal_plan_recipes.iter_mut().filter(|recipe| recipe.meal_plan_recipe_id == entry.id()) {
+            recipe.ischecked = checked;
+            updated += 1;
+        }
+        Ok(updated)
+    }
+
+    fn delete_meal_plans(&mut self, meal_plan_ids: &[i32]) -> QueryResult<usize> {
+        self.meal_plan_recipes.retain(|recipe| !meal_plan_ids.contains(&recipe.meal_plan_id));
+        let before = self.meal_plans.len();
+        self.meal_plans.retain(|plan| !meal_plan_ids.contains(&plan.meal_plan_id));
+        Ok(before - self.meal_plans.len())
+    }
+
+    fn archive_meal_plans(&mut self, meal_plan_ids: &[i32]) -> QueryResult<usize> {
+        let now = chrono::Local::now().naive_local();
+        let mut archived = 0;
+        for plan in self.meal_plans.iter_mut().filter(|plan| meal_plan_ids.contains(&plan.meal_plan_id)) {
+            plan.archived_at = Some(now);
+            archived += 1;
+        }
+        Ok(archived)
+    }
+
+    fn list_meal_plans(&mut self, user_id: UserId, filter: MealPlanFilter) -> QueryResult<Vec<MealPlanEntry>> {
+        let entries = self
+            .meal_plans
+            .iter()
+            .filter(|plan| plan.user_id == user_id)
+            .filter(|plan| filter.include_archived || plan.archived_at.is_none())
+            .filter(|plan| filter.date.is_none_or(|date| plan.date == date))
+            .filter(|plan| filter.start_date.is_none_or(|start_date| plan.date >= start_date))
+            .filter(|plan| filter.end_date.is_none_or(|end_date| plan.date <= end_date))
+            .map(|plan| MealPlanEntry {
+                meal_plan_id: plan.meal_plan_id,
+                user_id: plan.user_id,
+                name: plan.name.clone(),
+                date: plan.date,
+                archived_at: plan.archived_at,
+                recipes: self
+                    .meal_plan_recipes
+                    .iter()
+                    .filter(|row| row.meal_plan_id == plan.meal_plan_id)
+                    .filter_map(|row| {
+                        let recipe = self.recipes.iter().find(|recipe| recipe.menu.recipe_id == row.recipe_id)?;
+                        Some(RecipeInfo {
+                            recipe_id: row.recipe_id,
+                            recipe_name: recipe.menu.name.clone(),
+                            recipe_img_link: recipe.menu.recipe_img_link.clone(),
+                            ischecked: Some(row.ischecked),
+                            meal_plan_recipe_id: row.meal_plan_recipe_id,
+                            meal_time: Some(row.meal_time),
+                            calories: recipe.menu.nutrition.calories as f64,
+                        })
+                    })
+                    .collect(),
+            })
+            // Like the inner join in Postgres, days without recipes are not listed
+            .filter(|entry| !entry.recipes.is_empty())
+            .collect();
+        Ok(entries)
+    }
+}
+
+impl MemoryRepo {
+    fn template_recipe_days(&self, template_id: i32) -> Vec<Vec<Recipe>> {
+        let mut rows: Vec<&MemoryTemplateRecipe> =
+            self.template_recipes.iter().filter(|row| row.template_id == template_id).collect();
+        rows.sort_by_key(|row| (row.day_index, row.meal_time, row.template_recipe_id));
+
+        let mut mealplans: Vec<Vec<Recipe>> = Vec::new();
+        for row in rows {
+            if mealplans.len() <= row.day_index {
+                mealplans.resize(row.day_index + 1, Vec::new());
+            }
+            mealplans[row.day_index].push(Recipe { recipe_id: Some(row.recipe_id), meal_time: Some(row.meal_time) });
+        }
+        mealplans
+    }
+}
+
+impl TemplateRepo for MemoryRepo {
+    fn list_templates(&mut self) -> QueryResult<Vec<TemplateEntry>> {
+        let mut templates: Vec<TemplateEntry> = self
+            .templates
+            .iter()
+            .map(|template| TemplateEntry {
+                mealplans: self.template_recipe_days(template.template_id),
+                ..template.clone()
+            })
+            .collect();
+        templates.sort_by_key(|template| template.template_id);
+        Ok(templates)
+    }
+
+    fn template_days(&mut self, template_id: i32) -> QueryResult<Option<Vec<Vec<Recipe>>>> {
+        if !self.templates.iter().any(|template| template.template_id == template_id) {
+            return Ok(None);
+        }
+        Ok(Some(self.template_recipe_days(template_id)))
+    }
+
+    fn create_template(&mut self, name: &str, created_by: Option<&str>) -> QueryResult<i32> {
+        let template_id = self.next_id();
+        self.templates.push(TemplateEntry {
+            template_id,
+            name: name.to_string(),
+            created_by: created_by.map(str::to_string),
+            created_at: chrono::Utc::now().naive_utc(),
+            mealplans: Vec::new(),
+        });
+        Ok(template_id)
+    }
+
+    fn add_template_recipe(&mut self, template_id: i32, day_index: usize, recipe_id: i32, meal_time: i32) -> QueryResult<()> {
+        let template_recipe_id = self.next_id();
+        self.template_recipes.push(MemoryTemplateRecipe {
+            template_recipe_id,
+            template_id,
+            day_index,
+            recipe_id,
+            meal_time,
+        });
+        Ok(())
+    }
+
+    fn delete_template(&mut self, template_id: i32) -> QueryResult<usize> {
+        self.template_recipes.retain(|row| row.template_id != template_id);
+        let before = self.templates.len();
+        self.templates.retain(|template| template.template_id != template_id);
+        Ok(before - self.templates.len())
+    }
+}
+
+impl IngredientRepo for MemoryRepo {
+    fn list_ingredients(&mut self) -> QueryResult<Vec<Ingredient>> {
+        Ok(self.ingredients.clone())
+    }
+
+    fn create_ingredient(&mut self, name: &str, name_eng: Option<&str>) -> QueryResult<()> {
+        let ingredient_id = self.next_id();
+        self.ingredients.push(Ingredient {
+            ingredient_id,
+            ingredient_name: name.to_string(),
+            ingredient_name_eng: name_eng.map(str::to_string),
+        });
+        Ok(())
+    }
+}
+
+impl AdminRepo for MemoryRepo {
+    fn find_admin(&mut self, email: &str) -> QueryResult<Option<Admin>> {
+        Ok(self.admins.iter().find(|admin| admin.admin_email == email).cloned())
+    }
+
+    fn list_admins(&mut self) -> QueryResult<Vec<Admin>> {
+        let mut admins = self.admins.clone();
+        admins.sort_by(|a, b| a.admin_email.cmp(&b.admin_email));
+        Ok(admins)
+    }
+
+    fn upsert_admin(&mut self, email: &str, password_hash: &str, role: Role) -> QueryResult<()> {
+        let admin = Admin {
+            admin_email: email.to_string(),
+            password_hash: Some(password_hash.to_string()),
+            role: role.as_str().to_string(),
+        };
+        match self.admins.iter_mut().find(|existing| existing.admin_email == email) {
+            Some(existing) => *existing = admin,
+            None => self.admins.push(admin),
+        }
+        Ok(())
+    }
+
+    fn delete_admin(&mut self, email: &str) -> QueryResult<usize> {
+        let before = self.admins.len();
+        self.admins.retain(|admin| admin.admin_email != email);
+        Ok(before - self.admins.len())
+    }
+}
+
+impl JobRepo for MemoryRepo {
+    fn insert_job(&mut self, job_id: Uuid, user_id: UserId, kind: &str, request: Value) -> QueryResult<()> {
+        let now = chrono::Utc::now().naive_utc();
+        self.jobs.push(MealPlanJob {
+            job_id,
+            user_id: user_id.0,
+            kind: kind.to_string(),
+            status: STATUS_PENDING.to_string(),
+            request,
+            result: None,
+            error: None,
+            attempts: 0,
+            created_at: now,
+            updated_at: now,
+        });
+        Ok(())
+    }
+
+    fn unfinished_job_count(&mut self, user_id: UserId) -> QueryResult<usize> {
+        Ok(self.jobs.iter().filter(|job| job.user_id == user_id.0 && !is_finished(&job.status)).count())
+    }
+
+    fn find_job(&mut self, job_id: Uuid) -> QueryResult<Option<MealPlanJob>> {
+        Ok(self.jobs.iter().find(|job| job.job_id == job_id).cloned())
+    }
+
+    fn claim_job(&mut self, job_id: Uuid, max_attempts: i32) -> QueryResult<Option<ClaimedJob>> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.job_id == job_id && job.status == STATUS_PENDING && job.attempts < max_attempts);
+        Ok(job.map(|job| {
+            job.status = STATUS_RUNNING.to_string();
+            job.attempts += 1;
+            job.updated_at = chrono::Utc::now().naive_utc();
+            ClaimedJob { kind: job.kind.clone(), request: job.request.clone(), attempt: job.attempts }
+        }))
+    }
+
+    fn finish_job(
+        &mut self,
+        job_id: Uuid,
+        attempt: i32,
+        status: &str,
+        result: Option<Value>,
+        error: Option<String>,
+    ) -> QueryResult<usize> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.job_id == job_id && job.status == STATUS_RUNNING && job.attempts == attempt);
+        let Some(job) = job else {
+            return Ok(0);
+        };
+        job.status = status.to_string();
+        job.result = result;
+        job.error = error;
+        job.updated_at = chrono::Utc::now().naive_utc();
+        Ok(1)
+    }
+
+    fn fail_exhausted_jobs(&mut self, max_attempts: i32, error: &str) -> QueryResult<usize> {
+        let mut failed = 0;
+        for job in self.jobs.iter_mut().filter(|job| !is_finished(&job.status) && job.attempts >= max_attempts) {
+            job.status = STATUS_FAILED.to_string();
+            job.error = Some(error.to_string());
+            failed += 1;
+        }
+        Ok(failed)
+    }
+
+    fn requeue_running_jobs(&mut self) -> QueryResult<usize> {
+        let mut requeued = 0;
+        for job in self.jobs.iter_mut().filter(|job| job.status == STATUS_RUNNING) {
+            job.status = STATUS_PENDING.to_string();
+            requeued += 1;
+        }
+        Ok(requeued)
+    }
+
+    fn pending_job_ids(&mut self) -> QueryResult<Vec<Uuid>> {
+        let mut pending: Vec<&MealPlanJob> = self.jobs.iter().filter(|job| job.status == STATUS_PENDING).collect();
+        pending.sort_by_key(|job| job.created_at);
+        Ok(pending.iter().map(|job| job.job_id).collect())
+    }
+
+    fn delete_finished_jobs(&mut self, retention: Duration) -> QueryResult<usize> {
+        let cutoff = chrono::Utc::now().naive_utc() - retention;
+        let before = self.jobs.len();
+        self.jobs.retain(|job| !is_finished(&job.status) || job.updated_at >= cutoff);
+        Ok(before - self.jobs.len())
+    }
+}
+
+/// [`Database`] over a shared [`MemoryRepo`], for code that runs on `Db` in production.
+#[derive(Clone, Default)]
+pub struct MemoryDb(pub Arc<Mutex<MemoryRepo>>);
+
+impl MemoryDb {
+    pub fn new(repo: MemoryRepo) -> Self {
+        MemoryDb(Arc::new(Mutex::new(repo)))
+    }
+}
+
+impl Database for MemoryDb {
+    type Repo = MemoryRepo;
+
+    fn run<T, F>(&self, f: F) -> impl Future<Output = AppResult<T>> + Send
+    where
+        F: FnOnce(&mut MemoryRepo) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        std::future::ready(f(&mut self.0.lock().unwrap()))
+    }
+}
+
+/// What Postgres reports when a write trips a unique index, named like the real one.
+#[derive(Debug)]
+struct UniqueViolation(&'static str);
+
+impl DatabaseErrorInformation for UniqueViolation {
+    fn message(&self) -> &str {
+        "duplicate key value violates unique constraint"
+    }
+
+    fn details(&self) -> Option<&str> {
+        None
+    }
+
+    fn hint(&self) -> Option<&str> {
+        None
+    }
+
+    fn table_name(&self) -> Option<&str> {
+        None
+    }
+
+    fn column_name(&self) -> Option<&str> {
+        None
+    }
+
+    fn constraint_name(&self) -> Option<&str> {
+        Some(self.0)
+    }
+
+    fn statement_position(&self) -> Option<i32> {
+        None
+    }
+}
@@ -0,0 +1,187 @@
+//! Data access behind traits.
+//!
+//! Handlers and the service layer go through these traits instead of writing
+//! Diesel queries inline. `PgConnection` implements all of them (see
+//! [`postgres`]), so inside [`crate::db::Db::run`] the connection is the
+//! repository. [`memory::MemoryRepo`] implements the same traits in memory, so
+//! the logic in [`crate::service`] can be exercised without a database.
+
+pub mod memory;
+pub mod postgres;
+
+use crate::auth::Role;
+use crate::authz::OwnedMealPlanRecipe;
+use crate::catalog::CatalogRecipe;
+use crate::identity::{LineUserId, UserId};
+use crate::models::{Admin, MealPlanJob};
+use crate::routes::ingredient::Ingredient;
+use crate::routes::mealplan::{MealPlanEntry, Nutrition, Recipe};
+use crate::routes::recipe::UpdateRecipe;
+use crate::routes::template::TemplateEntry;
+use chrono::NaiveDate;
+use diesel::QueryResult;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Unique index allowing one active meal plan per user and day; writes that
+/// would add a second fail with a unique violation naming it.
+pub const ACTIVE_MEAL_PLAN_INDEX: &str = "meal_plans_user_id_date_active_idx";
+
+pub trait UserRepo {
+    /// Internal id of the user with the given LINE id, if there is one.
+    fn find_user(&mut self, line_id: &LineUserId) -> QueryResult<Option<UserId>>;
+
+    /// Ingredient allergies recorded for the user.
+    fn allergy_ids(&mut self, user_id: UserId) -> QueryResult<HashSet<i32>>;
+}
+
+pub trait RecipeRepo {
+    /// Every recipe with its nutrient totals and allergens, as cached by [`crate::catalog::RecipeCatalog`].
+    fn load_catalog(&mut self) -> QueryResult<Vec<CatalogRecipe>>;
+
+    /// Applies the fields set in `changes` and returns how many recipes were updated.
+    fn update_recipe(&mut self, recipe_id: i32, changes: UpdateRecipe) -> QueryResult<usize>;
+
+    /// Deletes the recipe with its nutrients, ingredients and allergens and returns how many
+    /// recipes were deleted. Fails with a foreign key violation while a template or meal plan uses it.
+    fn delete_recipe(&mut self, recipe_id: i32) -> QueryResult<usize>;
+}
+
+pub trait NutrientRepo {
+    /// The user's daily limits; nutrients without a limit are 0.
+    fn daily_limits(&mut self, user_id: UserId) -> QueryResult<Nutrition>;
+}
+
+/// Which meal plans `MealPlanRepo::list_meal_plans` returns. Dates are inclusive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MealPlanFilter {
+    pub date: Option<NaiveDate>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub include_archived: bool,
+}
+
+/// Meal plans and their recipes. Unless stated otherwise only active (not archived) days are seen.
+pub trait MealPlanRepo {
+    /// Runs `f` so that either all of its writes happen or none do.
+    fn atomically<T, F>(&mut self, f: F) -> QueryResult<T>
+    where
+        Self: Sized,
+        F: FnOnce(&mut Self) -> QueryResult<T>;
+
+    fn latest_active_date(&mut self, user_id: UserId) -> QueryResult<Option<NaiveDate>>;
+
+    /// Dates in `[start_date, end_date]` that have a meal plan, ascending.
+    fn active_dates(&mut self, user_id: UserId, start_date: NaiveDate, end_date: NaiveDate) -> QueryResult<Vec<NaiveDate>>;
+
+    /// Ids of the meal plans in `[start_date, end_date]`, by date.
+    fn active_meal_plan_ids(&mut self, user_id: UserId, start_date: NaiveDate, end_date: NaiveDate) -> QueryResult<Vec<i32>>;
+
+    /// Fails with a unique violation on [`ACTIVE_MEAL_PLAN_INDEX`] if the day already has an active meal plan.
+    fn create_meal_plan(&mut self, user_id: UserId, name: &str, date: NaiveDate) -> QueryResult<i32>;
+
+    fn recipe_count(&mut self, meal_plan_id: i32) -> QueryResult<usize>;
+
+    /// Removes every recipe from the meal plan and returns how many there were.
+    fn clear_recipes(&mut self, meal_plan_id: i32) -> QueryResult<usize>;
+
+    fn add_recipe(&mut self, meal_plan_id: i32, recipe_id: i32, meal_time: i32) -> QueryResult<()>;
+
+    /// User whose meal plan the entry is on, if the entry exists.
+    fn meal_plan_recipe_owner(&mut self, meal_plan_recipe_id: i32) -> QueryResult<Option<UserId>>;
+
+    /// Returns how many meal plan recipes were updated.
+    fn set_checked(&mut self, entry: OwnedMealPlanRecipe, checked: bool) -> QueryResult<usize>;
+
+    /// Deletes the meal plans with their recipes and returns how many days were removed.
+    fn delete_meal_plans(&mut self, meal_plan_ids: &[i32]) -> QueryResult<usize>;
+
+    /// Hides the meal plans from the active calendar and returns how many days were archived.
+    fn archive_meal_plans(&mut self, meal_plan_ids: &[i32]) -> QueryResult<usize>;
+
+    /// Meal plans with their recipes; archived days too when the filter asks for them.
+    fn list_meal_plans(&mut self, user_id: UserId, filter: MealPlanFilter) -> QueryResult<Vec<MealPlanEntry>>;
+}
+
+/// Meal plan templates: named days of recipes that are copied into users' meal plans.
+pub trait TemplateRepo {
+    /// Every template with its days, by id.
+    fn list_templates(&mut self) -> QueryResult<Vec<TemplateEntry>>;
+
+    /// The template's days in the 2D recipe layout, ordered by meal time, or `None` if there is no such template.
+    fn template_days(&mut self, template_id: i32) -> QueryResult<Option<Vec<Vec<Recipe>>>>;
+
+    fn create_template(&mut self, name: &str, created_by: Option<&str>) -> QueryResult<i32>;
+
+    fn add_template_recipe(&mut self, template_id: i32, day_index: usize, recipe_id: i32, meal_time: i32) -> QueryResult<()>;
+
+    /// Deletes the template with its recipes and returns how many templates were removed.
+    fn delete_template(&mut self, template_id: i32) -> QueryResult<usize>;
+}
+
+pub trait IngredientRepo {
+    fn list_ingredients(&mut self) -> QueryResult<Vec<Ingredient>>;
+
+    fn create_ingredient(&mut self, name: &str, name_eng: Option<&str>) -> QueryResult<()>;
+}
+
+pub trait AdminRepo {
+    fn find_admin(&mut self, email: &str) -> QueryResult<Option<Admin>>;
+
+    /// Every admin, by email.
+    fn list_admins(&mut self) -> QueryResult<Vec<Admin>>;
+
+    /// Creates the admin, or resets the password and role of an existing one.
+    fn upsert_admin(&mut self, email: &str, password_hash: &str, role: Role) -> QueryResult<()>;
+
+    /// Returns how many admins were deleted.
+    fn delete_admin(&mut self, email: &str) -> QueryResult<usize>;
+}
+
+/// A job a worker has just moved to `running`.
+#[derive(Debug, Clone)]
+pub struct ClaimedJob {
+    pub kind: String,
+    pub request: Value,
+    pub attempt: i32, // Which run this is; the result is only recorded for the latest one
+}
+
+/// Background meal plan jobs; statuses are the `STATUS_*` constants in [`crate::jobs`].
+pub trait JobRepo {
+    /// Stores a new pending job that has not been run yet.
+    fn insert_job(&mut self, job_id: Uuid, user_id: UserId, kind: &str, request: Value) -> QueryResult<()>;
+
+    /// Jobs of the user that are pending or running.
+    fn unfinished_job_count(&mut self, user_id: UserId) -> QueryResult<usize>;
+
+    fn find_job(&mut self, job_id: Uuid) -> QueryResult<Option<MealPlanJob>>;
+
+    /// Moves the job from pending to running if it has attempts left, counting the attempt.
+    /// `None` if another worker got it first or it is out of attempts.
+    fn claim_job(&mut self, job_id: Uuid, max_attempts: i32) -> QueryResult<Option<ClaimedJob>>;
+
+    /// Records the outcome of run `attempt`, only while the job is still running that attempt,
+    /// and returns how many jobs were updated.
+    fn finish_job(
+        &mut self,
+        job_id: Uuid,
+        attempt: i32,
+        status: &str,
+        result: Option<Value>,
+        error: Option<String>,
+    ) -> QueryResult<usize>;
+
+    /// Marks unfinished jobs that have been started `max_attempts` times as failed with `error`.
+    fn fail_exhausted_jobs(&mut self, max_attempts: i32, error: &str) -> QueryResult<usize>;
+
+    /// Puts running jobs back to pending and returns how many there were.
+    fn requeue_running_jobs(&mut self) -> QueryResult<usize>;
+
+    /// Pending jobs, oldest first.
+    fn pending_job_ids(&mut self) -> QueryResult<Vec<Uuid>>;
+
+    /// Deletes finished jobs last updated more than `retention` ago and returns how many there were.
+    fn delete_finished_jobs(&mut self, retention: Duration) -> QueryResult<usize>;
+}
@@ -0,0 +1,628 @@
+//! Postgres implementations of the repository traits, directly on `PgConnection`.
+
+use super::{
+    AdminRepo, ClaimedJob, IngredientRepo, JobRepo, MealPlanFilter, MealPlanRepo, NutrientRepo, RecipeRepo, TemplateRepo, UserRepo,
+};
+use crate::auth::Role;
+use crate::authz::OwnedMealPlanRecipe;
+use crate::catalog::CatalogRecipe;
+use crate::identity::{resolve_user, LineUserId, UserId};
+use crate::jobs::{STATUS_FAILED, STATUS_PENDING, STATUS_RUNNING, STATUS_SUCCEEDED};
+use crate::models::{Admin, MealPlanJob};
+use crate::routes::ingredient::Ingredient;
+use crate::routes::mealplan::{FoodMenu, MealPlanEntry, Nutrition, Recipe, RecipeInfo};
+use crate::routes::recipe::UpdateRecipe;
+use crate::routes::template::TemplateEntry;
+use crate::schema::{
+    admins, ingredients, meal_plan_jobs, meal_plan_recipes, meal_plan_template_recipes, meal_plan_templates, meal_plans, recipes,
+    recipes_ingredient_allergies, recipes_ingredients, recipes_nutrients, users_ingredient_allergies, users_nutrients_limit_per_day,
+};
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::dsl::IntervalDsl;
+use diesel::prelude::*;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+impl UserRepo for PgConnection {
+    fn find_user(&mut self, line_id: &LineUserId) -> QueryResult<Option<UserId>> {
+        resolve_user(self, line_id).optional()
+    }
+
+    fn allergy_ids(&mut self, user_id: UserId) -> QueryResult<HashSet<i32>> {
+        let ids = users_ingredient_allergies::table
+            .filter(users_ingredient_allergies::user_id.eq(user_id))
+            .select(users_ingredient_allergies::ingredient_allergy_id)
+            .load::<i32>(self)?;
+        Ok(ids.into_iter().collect())
+    }
+}
+
+impl RecipeRepo for PgConnection {
+    fn load_catalog(&mut self) -> QueryResult<Vec<CatalogRecipe>> {
+        let rows = recipes::table
+            .left_join(recipes_nutrients::table.on(recipes::recipe_id.eq(recipes_nutrients::recipe_id)))
+            .group_by((recipes::recipe_id, recipes::recipe_name, recipes::recipe_img_link))
+            .order(recipes::recipe_id.asc())
+            .select((
+                recipes::recipe_id,
+                recipes::recipe_name,
+                recipes::recipe_img_link,
+                diesel::dsl::sum(diesel::dsl::case_when(recipes_nutrients::nutrient_id.eq(6), recipes_nutrients::quantity)).nullable(), // protein
+                diesel::dsl::sum(diesel::dsl::case_when(recipes_nutrients::nutrient_id.eq(2), recipes_nutrients::quantity)).nullable(), // carbs
+                diesel::dsl::sum(diesel::dsl::case_when(recipes_nutrients::nutrient_id.eq(3), recipes_nutrients::quantity)).nullable(), // fat
+                diesel::dsl::sum(diesel::dsl::case_when(recipes_nutrients::nutrient_id.eq(7), recipes_nutrients::quantity)).nullable(), // sodium
+                diesel::dsl::sum(diesel::dsl::case_when(recipes_nutrients::nutrient_id.eq(4), recipes_nutrients::quantity)).nullable(), // phosphorus
+                diesel::dsl::sum(diesel::dsl::case_when(recipes_nutrients::nutrient_id.eq(5), recipes_nutrients::quantity)).nullable(), // potassium
+                diesel::dsl::sum(diesel::dsl::case_when(recipes_nutrients::nutrient_id.eq(1), recipes_nutrients::quantity)).nullable(), // calories
+            ))
+            .load::<(
+                i32,
+                String,
+                Option<Vec<Option<String>>>,
+                Option<f64>,
+                Option<f64>,
+                Option<f64>,
+                Option<f64>,
+                Option<f64>,
+                Option<f64>,
+                Option<f64>,
+            )>(self)?;
+
+        let mut allergies: HashMap<i32, Vec<i32>> = HashMap::new();
+        for (recipe_id, allergy_id) in recipes_ingredient_allergies::table
+            .select((
+                recipes_ingredient_allergies::recipe_id,
+                recipes_ingredient_allergies::ingredient_allergy_id,
+            ))
+            .load::<(i32, i32)>(self)?
+        {
+            allergies.entry(recipe_id).or_default().push(allergy_id);
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|recipe| CatalogRecipe {
+                allergy_ids: allergies.remove(&recipe.0).unwrap_or_default(),
+                menu: FoodMenu {
+                    name: recipe.1,
+                    nutrition: Nutrition {
+                        calories: recipe.9.unwrap_or(0.0) as f32,
+                        carbs: recipe.4.unwrap_or(0.0) as f32,
+                        fat: recipe.5.unwrap_or(0.0) as f32,
+                        phosphorus: recipe.7.unwrap_or(0.0) as f32,
+                        potassium: recipe.8.unwrap_or(0.0) as f32,
+                        protein: recipe.3.unwrap_or(0.0) as f32,
+                        sodium: recipe.6.unwrap_or(0.0) as f32,
+                    },
+                    recipe_id: recipe.0,
+                    recipe_img_link: recipe.2.unwrap_or_default().into_iter().flatten().collect(),
+                },
+            })
+            .collect())
+    }
+
+    fn update_recipe(&mut self, recipe_id: i32, changes: UpdateRecipe) -> QueryResult<usize> {
+        diesel::update(recipes::table.filter(recipes::recipe_id.eq(recipe_id)))
+            .set((
+                changes.recipe_name.map(|name| recipes::recipe_name.eq(name)),
+                changes.recipe_method.map(|method| recipes::recipe_method.eq(method)),
+                changes.calories.map(|calories| recipes::calories.eq(calories)),
+                changes.calories_unit.map(|unit| recipes::calories_unit.eq(unit)),
+                changes.recipe_img_link.map(|img_link| recipes::recipe_img_link.eq(img_link)),
+                changes.food_category.map(|category| recipes::food_category.eq(category)),
+                changes.dish_type.map(|dish| recipes::dish_type.eq(dish)),
+            ))
+            .execute(self)
+    }
+
+    fn delete_recipe(&mut self, recipe_id: i32) -> QueryResult<usize> {
+        self.transaction(|conn| {
+            // Allergens cascade; nutrients and ingredients have no foreign key to clean up after them
+            diesel::delete(recipes_nutrients::table.filter(recipes_nutrients::recipe_id.eq(recipe_id))).execute(conn)?;
+            diesel::delete(recipes_ingredients::table.filter(recipes_ingredients::recipe_id.eq(recipe_id))).execute(conn)?;
+            diesel::delete(recipes::table.filter(recipes::recipe_id.eq(recipe_id))).execute(conn)
+        })
+    }
+}
+
+impl NutrientRepo for PgConnection {
+    fn daily_limits(&mut self, user_id: UserId) -> QueryResult<Nutrition> {
+        let nutrition_limits = users_nutrients_limit_per_day::table
+            .filter(users_nutrients_limit_per_day::user_id.eq(user_id))
+            .select((
+                users_nutrients_limit_per_day::nutrient_id,
+                users_nutrients_limit_per_day::nutrient_limit,
+            ))
+            .load::<(Option<i32>, Option<f64>)>(self)?;
+
+        let mut nutrition_map = Nutrition::default();
+        for (nutrient_id, nutrient_limit) in nutrition_limits {
+            let limit = nutrient_limit.unwrap_or(0.0) as f32;
+            match nutrient_id {
+                Some(1) => nutrition_map.calories = limit,
+                Some(2) => nutrition_map.carbs = limit,
+                Some(3) => nutrition_map.fat = limit,
+                Some(4) => nutrition_map.phosphorus = limit,
+                Some(5) => nutrition_map.potassium = limit,
+                Some(6) => nutrition_map.protein = limit,
+                Some(7) => nutrition_map.sodium = limit,
+                _ => (),
+            }
+        }
+
+        Ok(nutrition_map)
+    }
+}
+
+impl MealPlanRepo for PgConnection {
+    fn atomically<T, F>(&mut self, f: F) -> QueryResult<T>
+    where
+        F: FnOnce(&mut Self) -> QueryResult<T>,
+    {
+        Connection::transaction(self, f)
+    }
+
+    fn latest_active_date(&mut self, user_id: UserId) -> QueryResult<Option<NaiveDate>> {
+        meal_plans::table
+            .filter(meal_plans::user_id.eq(user_id))
+            .filter(meal_plans::archived_at.is_null())
+            .select(meal_plans::date)
+            .order(meal_plans::date.desc())
+            .first::<NaiveDate>(self)
+            .optional()
+    }
+
+    fn active_dates(&mut self, user_id: UserId, start_date: NaiveDate, end_date: NaiveDate) -> QueryResult<Vec<NaiveDate>> {
+        meal_plans::table
+            .filter(meal_plans::user_id.eq(user_id))
+            .filter(meal_plans::archived_at.is_null())
+            .filter(meal_plans::date.between(start_date, end_date))
+            .select(meal_plans::date)
+            .order(meal_plans::date.asc())
+            .load(self)
+    }
+
+    fn active_meal_plan_ids(&mut self, user_id: UserId, start_date: NaiveDate, end_date: NaiveDate) -> QueryResult<Vec<i32>> {
+        meal_plans::table
+            .filter(meal_plans::user_id.eq(user_id))
+            .filter(meal_plans::archived_at.is_null())
+            .filter(meal_plans::date.between(start_date, end_date))
+            .select(meal_plans::meal_plan_id)
+            .order(meal_plans::date.asc())
+            .load(self)
+    }
+
+    fn create_meal_plan(&mut self, user_id: UserId, name: &str, date: NaiveDate) -> QueryResult<i32> {
+        diesel::insert_into(meal_plans::table)
+            .values((
+                meal_plans::user_id.eq(user_id),
+                meal_plans::name.eq(name),
+                meal_plans::date.eq(date),
+            ))
+            .returning(meal_plans::meal_plan_id)
+            .get_result(self)
+    }
+
+    fn recipe_count(&mut self, meal_plan_id: i32) -> QueryResult<usize> {
+        let count: i64 = meal_plan_recipes::table
+            .filter(meal_plan_recipes::meal_plan_id.eq(meal_plan_id))
+            .count()
+            .get_result(self)?;
+        Ok(count as usize)
+    }
+
+    fn clear_recipes(&mut self, meal_plan_id: i32) -> QueryResult<usize> {
+        diesel::delete(meal_plan_recipes::table.filter(meal_plan_recipes::meal_plan_id.eq(meal_plan_id))).execute(self)
+    }
+
+    fn add_recipe(&mut self, meal_plan_id: i32, recipe_id: i32, meal_time: i32) -> QueryResult<()> {
+        diesel::insert_into(meal_plan_recipes::table)
+            .values((
+                meal_plan_recipes::meal_plan_id.eq(meal_plan_id),
+                meal_plan_recipes::recipe_id.eq(recipe_id),
+                meal_plan_recipes::ischecked.eq(false),
+                meal_plan_recipes::meal_time.eq(Some(meal_time)),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn meal_plan_recipe_owner(&mut self, meal_plan_recipe_id: i32) -> QueryResult<Option<UserId>> {
+        meal_plan_recipes::table
+            .inner_join(meal_plans::table)
+            .filter(meal_plan_recipes::meal_plan_recipe_id.eq(meal_plan_recipe_id))
+            .select(meal_plans::user_id)
+            .first(self)
+            .optional()
+    }
+
+    fn set_checked(&mut self, entry: OwnedMealPlanRecipe, checked: bool) -> QueryResult<usize> {
+        diesel::update(meal_plan_recipes::table.filter(meal_plan_recipes::meal_plan_recipe_id.eq(entry.id())))
+            .set(meal_plan_recipes::ischecked.eq(checked))
+            .execute(self)
+    }
+
+    fn delete_meal_plans(&mut self, meal_plan_ids: &[i32]) -> QueryResult<usize> {
+        diesel::delete(meal_plan_recipes::table.filter(meal_plan_recipes::meal_plan_id.eq_any(meal_plan_ids)))
+            .execute(self)?;
+        diesel::delete(meal_plans::table.filter(meal_plans::meal_plan_id.eq_any(meal_plan_ids))).execute(self)
+    }
+
+    fn archive_meal_plans(&mut self, meal_plan_ids: &[i32]) -> QueryResult<usize> {
+        diesel::update(meal_plans::table.filter(meal_plans::meal_plan_id.eq_any(meal_plan_ids)))
+            .set(meal_plans::archived_at.eq(diesel::dsl::now.nullable()))
+            .execute(self)
+    }
+
+    fn list_meal_plans(&mut self, user_id: UserId, filter: MealPlanFilter) -> QueryResult<Vec<MealPlanEntry>> {
+        let mut query = meal_plans::table
+            .inner_join(
+                meal_plan_recipes::table
+                    .on(meal_plans::meal_plan_id.eq(meal_plan_recipes::meal_plan_id)),
+            )
+            .inner_join(recipes::table.on(meal_plan_recipes::recipe_id.eq(recipes::recipe_id)))
+            .filter(meal_plans::user_id.eq(user_id))
+            .into_boxed();
+
+        if !filter.include_archived {
+            query = query.filter(meal_plans::archived_at.is_null());
+        }
+        if let Some(date) = filter.date {
+            query = query.filter(meal_plans::date.eq(date));
+        }
+        if let Some(start_date) = filter.start_date {
+            query = query.filter(meal_plans::date.ge(start_date));
+        }
+        if let Some(end_date) = filter.end_date {
+            query = query.filter(meal_plans::date.le(end_date));
+        }
+
+        let results = query
+            .select((
+                meal_plans::meal_plan_id,
+                meal_plans::user_id,
+                meal_plans::name,
+                meal_plans::date,
+                meal_plans::archived_at,
+                meal_plan_recipes::meal_plan_recipe_id,
+                meal_plan_recipes::recipe_id,
+                meal_plan_recipes::meal_time,
+                recipes::recipe_name,
+                recipes::recipe_img_link,
+                recipes::calories,
+                meal_plan_recipes::ischecked,
+            ))
+            .load::<(
+                i32,
+                UserId,
+                String,
+                NaiveDate,
+                Option<NaiveDateTime>,
+                i32,
+                i32,
+                Option<i32>,
+                String,
+                Option<Vec<Option<String>>>,
+                f64,
+                Option<bool>,
+            )>(self)?;
+
+        // Group the rows by meal plan
+        let mut meal_plans_map: HashMap<i32, MealPlanEntry> = HashMap::new();
+        for (
+            meal_plan_id,
+            user_id,
+            name,
+            date,
+            archived_at,
+            meal_plan_recipe_id,
+            recipe_id,
+            meal_time,
+            recipe_name,
+            recipe_img_link,
+            calories,
+            ischecked,
+        ) in results
+        {
+            let meal_plan_entry = meal_plans_map
+                .entry(meal_plan_id)
+                .or_insert_with(|| MealPlanEntry {
+                    meal_plan_id,
+                    user_id,
+                    name,
+                    date,
+                    archived_at,
+                    recipes: Vec::new(),
+                });
+
+            meal_plan_entry.recipes.push(RecipeInfo {
+                recipe_id,
+                recipe_name,
+                recipe_img_link: recipe_img_link
+                    .unwrap_or_default()
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                ischecked,
+                meal_plan_recipe_id,
+                meal_time,
+                calories,
+            });
+        }
+
+        Ok(meal_plans_map.into_values().collect())
+    }
+}
+
+/// Loads the recipes of the given templates into the 2D recipe layout, ordered by day and meal time.
+/// Templates without recipes are left out of the map.
+fn load_template_days(conn: &mut PgConnection, template_ids: &[i32]) -> QueryResult<HashMap<i32, Vec<Vec<Recipe>>>> {
+    let rows = meal_plan_template_recipes::table
+        .filter(meal_plan_template_recipes::template_id.eq_any(template_ids))
+        .order((
+            meal_plan_template_recipes::template_id.asc(),
+            meal_plan_template_recipes::day_index.asc(),
+            meal_plan_template_recipes::meal_time.asc(),
+            meal_plan_template_recipes::template_recipe_id.asc(),
+        ))
+        .select((
+            meal_plan_template_recipes::template_id,
+            meal_plan_template_recipes::day_index,
+            meal_plan_template_recipes::recipe_id,
+            meal_plan_template_recipes::meal_time,
+        ))
+        .load::<(i32, i32, i32, Option<i32>)>(conn)?;
+
+    let mut templates: HashMap<i32, Vec<Vec<Recipe>>> = HashMap::new();
+    for (template_id, day_index, recipe_id, meal_time) in rows {
+        let mealplans = templates.entry(template_id).or_default();
+        let day_index = day_index as usize;
+        if mealplans.len() <= day_index {
+            mealplans.resize(day_index + 1, Vec::new());
+        }
+        mealplans[day_index].push(Recipe { recipe_id: Some(recipe_id), meal_time });
+    }
+
+    Ok(templates)
+}
+
+impl TemplateRepo for PgConnection {
+    fn list_templates(&mut self) -> QueryResult<Vec<TemplateEntry>> {
+        let templates = meal_plan_templates::table
+            .order(meal_plan_templates::template_id.asc())
+            .select((
+                meal_plan_templates::template_id,
+                meal_plan_templates::name,
+                meal_plan_templates::created_by,
+                meal_plan_templates::created_at,
+            ))
+            .load::<(i32, String, Option<String>, NaiveDateTime)>(self)?;
+
+        let template_ids: Vec<i32> = templates.iter().map(|template| template.0).collect();
+        let mut days = load_template_days(self, &template_ids)?;
+
+        Ok(templates
+            .into_iter()
+            .map(|(template_id, name, created_by, created_at)| TemplateEntry {
+                template_id,
+                name,
+                created_by,
+                created_at,
+                mealplans: days.remove(&template_id).unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    fn template_days(&mut self, template_id: i32) -> QueryResult<Option<Vec<Vec<Recipe>>>> {
+        let exists: bool = diesel::select(diesel::dsl::exists(
+            meal_plan_templates::table.filter(meal_plan_templates::template_id.eq(template_id)),
+        ))
+        .get_result(self)?;
+
+        if !exists {
+            return Ok(None);
+        }
+
+        Ok(Some(load_template_days(self, &[template_id])?.remove(&template_id).unwrap_or_default()))
+    }
+
+    fn create_template(&mut self, name: &str, created_by: Option<&str>) -> QueryResult<i32> {
+        diesel::insert_into(meal_plan_templates::table)
+            .values((
+                meal_plan_templates::name.eq(name),
+                meal_plan_templates::created_by.eq(created_by),
+            ))
+            .returning(meal_plan_templates::template_id)
+            .get_result(self)
+    }
+
+    fn add_template_recipe(&mut self, template_id: i32, day_index: usize, recipe_id: i32, meal_time: i32) -> QueryResult<()> {
+        diesel::insert_into(meal_plan_template_recipes::table)
+            .values((
+                meal_plan_template_recipes::template_id.eq(template_id),
+                meal_plan_template_recipes::day_index.eq(day_index as i32),
+                meal_plan_template_recipes::recipe_id.eq(recipe_id),
+                meal_plan_template_recipes::meal_time.eq(Some(meal_time)),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_template(&mut self, template_id: i32) -> QueryResult<usize> {
+        diesel::delete(meal_plan_template_recipes::table.filter(meal_plan_template_recipes::template_id.eq(template_id)))
+            .execute(self)?;
+        diesel::delete(meal_plan_templates::table.filter(meal_plan_templates::template_id.eq(template_id))).execute(self)
+    }
+}
+
+impl IngredientRepo for PgConnection {
+    fn list_ingredients(&mut self) -> QueryResult<Vec<Ingredient>> {
+        ingredients::table
+            .select((
+                ingredients::ingredient_id,
+                ingredients::ingredient_name,
+                ingredients::ingredient_name_eng,
+            ))
+            .load::<Ingredient>(self)
+    }
+
+    fn create_ingredient(&mut self, name: &str, name_eng: Option<&str>) -> QueryResult<()> {
+        diesel::insert_into(ingredients::table)
+            .values((ingredients::ingredient_name.eq(name), ingredients::ingredient_name_eng.eq(name_eng)))
+            .execute(self)?;
+        Ok(())
+    }
+}
+
+impl AdminRepo for PgConnection {
+    fn find_admin(&mut self, email: &str) -> QueryResult<Option<Admin>> {
+        admins::table
+            .filter(admins::admin_email.eq(email))
+            .select(Admin::as_select())
+            .first(self)
+            .optional()
+    }
+
+    fn list_admins(&mut self) -> QueryResult<Vec<Admin>> {
+        admins::table
+            .order(admins::admin_email.asc())
+            .select(Admin::as_select())
+            .load(self)
+    }
+
+    fn upsert_admin(&mut self, email: &str, password_hash: &str, role: Role) -> QueryResult<()> {
+        diesel::insert_into(admins::table)
+            .values((
+                admins::admin_email.eq(email),
+                admins::password_hash.eq(password_hash),
+                admins::role.eq(role.as_str()),
+            ))
+            .on_conflict(admins::admin_email)
+            .do_update()
+            .set((admins::password_hash.eq(password_hash), admins::role.eq(role.as_str())))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_admin(&mut self, email: &str) -> QueryResult<usize> {
+        diesel::delete(admins::table.filter(admins::admin_email.eq(email))).execute(self)
+    }
+}
+
+impl JobRepo for PgConnection {
+    fn insert_job(&mut self, job_id: Uuid, user_id: UserId, kind: &str, request: Value) -> QueryResult<()> {
+        diesel::insert_into(meal_plan_jobs::table)
+            .values((
+                meal_plan_jobs::job_id.eq(job_id),
+                meal_plan_jobs::user_id.eq(user_id),
+                meal_plan_jobs::kind.eq(kind),
+                meal_plan_jobs::status.eq(STATUS_PENDING),
+                meal_plan_jobs::request.eq(request),
+                meal_plan_jobs::attempts.eq(0),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn unfinished_job_count(&mut self, user_id: UserId) -> QueryResult<usize> {
+        let count: i64 = meal_plan_jobs::table
+            .filter(meal_plan_jobs::user_id.eq(user_id))
+            .filter(meal_plan_jobs::status.eq_any([STATUS_PENDING, STATUS_RUNNING]))
+            .count()
+            .get_result(self)?;
+        Ok(count as usize)
+    }
+
+    fn find_job(&mut self, job_id: Uuid) -> QueryResult<Option<MealPlanJob>> {
+        meal_plan_jobs::table
+            .find(job_id)
+            .select(MealPlanJob::as_select())
+            .first(self)
+            .optional()
+    }
+
+    fn claim_job(&mut self, job_id: Uuid, max_attempts: i32) -> QueryResult<Option<ClaimedJob>> {
+        // Only one worker may move a job out of pending, and only while it has attempts left.
+        let claimed = diesel::update(
+            meal_plan_jobs::table
+                .filter(meal_plan_jobs::job_id.eq(job_id))
+                .filter(meal_plan_jobs::status.eq(STATUS_PENDING))
+                .filter(meal_plan_jobs::attempts.lt(max_attempts)),
+        )
+        .set((
+            meal_plan_jobs::status.eq(STATUS_RUNNING),
+            meal_plan_jobs::attempts.eq(meal_plan_jobs::attempts + 1),
+            meal_plan_jobs::updated_at.eq(diesel::dsl::now),
+        ))
+        .returning((meal_plan_jobs::kind, meal_plan_jobs::request, meal_plan_jobs::attempts))
+        .get_result::<(String, Value, i32)>(self)
+        .optional()?;
+        Ok(claimed.map(|(kind, request, attempt)| ClaimedJob { kind, request, attempt }))
+    }
+
+    fn finish_job(
+        &mut self,
+        job_id: Uuid,
+        attempt: i32,
+        status: &str,
+        result: Option<Value>,
+        error: Option<String>,
+    ) -> QueryResult<usize> {
+        diesel::update(
+            meal_plan_jobs::table
+                .filter(meal_plan_jobs::job_id.eq(job_id))
+                .filter(meal_plan_jobs::status.eq(STATUS_RUNNING))
+                .filter(meal_plan_jobs::attempts.eq(attempt)),
+        )
+        .set((
+            meal_plan_jobs::status.eq(status),
+            meal_plan_jobs::result.eq(result),
+            meal_plan_jobs::error.eq(error),
+            meal_plan_jobs::updated_at.eq(diesel::dsl::now),
+        ))
+        .execute(self)
+    }
+
+    fn fail_exhausted_jobs(&mut self, max_attempts: i32, error: &str) -> QueryResult<usize> {
+        diesel::update(
+            meal_plan_jobs::table
+                .filter(meal_plan_jobs::status.eq_any([STATUS_PENDING, STATUS_RUNNING]))
+                .filter(meal_plan_jobs::attempts.ge(max_attempts)),
+        )
+        .set((
+            meal_plan_jobs::status.eq(STATUS_FAILED),
+            meal_plan_jobs::error.eq(error),
+            meal_plan_jobs::updated_at.eq(diesel::dsl::now),
+        ))
+        .execute(self)
+    }
+
+    fn requeue_running_jobs(&mut self) -> QueryResult<usize> {
+        diesel::update(meal_plan_jobs::table.filter(meal_plan_jobs::status.eq(STATUS_RUNNING)))
+            .set((
+                meal_plan_jobs::status.eq(STATUS_PENDING),
+                meal_plan_jobs::updated_at.eq(diesel::dsl::now),
+            ))
+            .execute(self)
+    }
+
+    fn pending_job_ids(&mut self) -> QueryResult<Vec<Uuid>> {
+        meal_plan_jobs::table
+            .filter(meal_plan_jobs::status.eq(STATUS_PENDING))
+            .order(meal_plan_jobs::created_at.asc())
+            .select(meal_plan_jobs::job_id)
+            .load(self)
+    }
+
+    fn delete_finished_jobs(&mut self, retention: Duration) -> QueryResult<usize> {
+        let retention_secs = retention.as_secs() as i64;
+        diesel::delete(
+            meal_plan_jobs::table
+                .filter(meal_plan_jobs::status.eq_any([STATUS_SUCCEEDED, STATUS_FAILED]))
+                .filter(meal_plan_jobs::updated_at.lt(diesel::dsl::now - retention_secs.seconds())),
+        )
+        .execute(self)
+    }
+}
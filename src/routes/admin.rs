@@ -0,0 +1,294 @@
+use crate::auth::{authenticate, hash_password, AdminUser, AuthConfig, IssuedToken, Role};
+use crate::catalog::RecipeCatalog;
+use crate::db::Db;
+use crate::error::{AppError, AppJson, AppPath, AppQuery, AppResult, ErrorBody};
+use crate::recipe_io::{export_recipes, import_recipes, read_records, write_records, ImportReport, RecipeFormat, RecipeRecord};
+use crate::repo::AdminRepo;
+use crate::openapi::{AdminErrors, AdminProfile, AdminSaved, PublicErrors, StatusMessage};
+use axum::body::Bytes;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+
+/// Shortest password accepted for a new admin.
+const MIN_PASSWORD_LEN: usize = 8;
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct LoginPayload {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CreateAdminPayload {
+    pub email: String,
+    pub password: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct AdminEntry {
+    pub email: String,
+    pub role: String,
+    pub can_log_in: bool, // False for admins from before passwords were hashed
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RecipeExportQuery {
+    #[serde(default)]
+    pub format: RecipeFormat,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RecipeImportQuery {
+    #[serde(default)]
+    pub format: RecipeFormat,
+    #[serde(default)]
+    pub dry_run: bool, // Validate and report what would change without writing
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/recipes/export",
+    tag = "v1",
+    params(RecipeExportQuery),
+    responses(
+        (status = 200, description = "All recipes in the requested format", content((Vec<RecipeRecord> = "application/json"), (String = "text/csv"))),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn export_recipe_file(
+    AppQuery(query): AppQuery<RecipeExportQuery>,
+    Extension(db): Extension<Db>,
+) -> AppResult<impl IntoResponse> {
+    let records = db
+        .run(|conn| export_recipes(conn).map_err(AppError::internal("Failed to export recipes")))
+        .await?;
+
+    let body = write_records(query.format, &records).map_err(AppError::internal("Failed to write recipes"))?;
+    let content_type = match query.format {
+        RecipeFormat::Json => "application/json",
+        RecipeFormat::Csv => "text/csv; charset=utf-8",
+    };
+
+    Ok(([(header::CONTENT_TYPE, content_type)], body))
+}
+
+/// Takes the raw file as the body; the format comes from the query string, not the content type.
+#[utoipa::path(
+    post,
+    path = "/admin/recipes/import",
+    tag = "v1",
+    params(RecipeImportQuery),
+    request_body(description = "The file, in the format named by `format`", content((Vec<RecipeRecord> = "application/json"), (String = "text/csv"))),
+    responses(
+        (status = 200, description = "What was (or with `dry_run` would be) created and updated", body = ImportReport),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn import_recipe_file(
+    AppQuery(query): AppQuery<RecipeImportQuery>,
+    Extension(db): Extension<Db>,
+    Extension(catalog): Extension<Arc<RecipeCatalog>>,
+    body: Bytes,
+) -> AppResult<Json<ImportReport>> {
+    let records = read_records(query.format, &body)?;
+    if records.is_empty() {
+        return Err(AppError::bad_request("The file contains no recipes"));
+    }
+
+    let dry_run = query.dry_run;
+    let report = db
+        .run(move |conn| Ok(import_recipes(conn, &records, dry_run)?))
+        .await?;
+
+    if !report.dry_run {
+        catalog.invalidate();
+    }
+
+    info!(
+        dry_run = report.dry_run,
+        created = report.created,
+        updated = report.updated,
+        "Imported recipes"
+    );
+
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/login",
+    tag = "v1",
+    request_body = LoginPayload,
+    responses(
+        (status = 200, description = "Admin token", body = IssuedToken),
+        (status = 401, description = "Unknown email or wrong password", body = ErrorBody),
+        PublicErrors,
+    ),
+)]
+#[axum::debug_handler]
+pub async fn admin_login(
+    Extension(db): Extension<Db>,
+    Extension(auth): Extension<Arc<AuthConfig>>,
+    AppJson(payload): AppJson<LoginPayload>,
+) -> AppResult<Json<IssuedToken>> {
+    // 1. Check the password on the blocking pool, argon2 is deliberately slow
+    let admin = db
+        .run(move |conn| {
+            authenticate(conn, &payload.email, &payload.password).map_err(AppError::internal("Error fetching admin"))
+        })
+        .await?;
+
+    // 2. Same answer for unknown emails and wrong passwords
+    let Some(admin) = admin else {
+        return Err(AppError::Unauthorized("Invalid email or password".to_string()));
+    };
+    let role: Role = admin.role.parse().map_err(AppError::internal("Admin has an unknown role"))?;
+
+    // 3. Issue the token
+    let token = auth
+        .issue(&admin.admin_email, role)
+        .map_err(AppError::internal("Failed to issue token"))?;
+
+    info!(email = %admin.admin_email, %role, "Admin logged in");
+
+    Ok(Json(token))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/me",
+    tag = "v1",
+    responses(
+        (status = 200, description = "The signed-in admin", body = AdminProfile),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn current_admin(admin: AdminUser) -> Json<AdminProfile> {
+    Json(AdminProfile { email: admin.email, role: admin.role })
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/admins",
+    tag = "v1",
+    responses(
+        (status = 200, description = "All admins", body = Vec<AdminEntry>),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn get_admins(Extension(db): Extension<Db>) -> AppResult<Json<Vec<AdminEntry>>> {
+    db.run(|conn| {
+        let admins = conn
+            .list_admins()
+            .map_err(AppError::internal("Error fetching admins"))?;
+
+        Ok(admins
+            .into_iter()
+            .map(|admin| AdminEntry {
+                can_log_in: admin.password_hash.is_some(),
+                email: admin.admin_email,
+                role: admin.role,
+            })
+            .collect())
+    })
+    .await
+    .map(Json)
+}
+
+/// Creates an admin, or resets an existing admin's password and role.
+#[utoipa::path(
+    post,
+    path = "/admin/admins",
+    tag = "v1",
+    request_body = CreateAdminPayload,
+    responses(
+        (status = 200, description = "Admin created or updated", body = AdminSaved),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn create_admin(
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CreateAdminPayload>,
+) -> AppResult<Json<AdminSaved>> {
+    let email = payload.email.trim().to_string();
+    if !email.contains('@') {
+        return Err(AppError::bad_request("email must be an email address"));
+    }
+    if payload.password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(AppError::bad_request(format!(
+            "password must be at least {} characters",
+            MIN_PASSWORD_LEN
+        )));
+    }
+
+    db.run(move |conn| {
+        let hash = hash_password(&payload.password).map_err(AppError::internal("Failed to hash password"))?;
+        conn.upsert_admin(&email, &hash, payload.role).map_err(AppError::internal("Failed to save admin"))?;
+
+        info!(%email, role = %payload.role, "Saved admin");
+
+        Ok(AdminSaved {
+            status: "success",
+            message: "Admin saved successfully",
+            email,
+            role: payload.role,
+        })
+    })
+    .await
+    .map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/admins/{email}",
+    tag = "v1",
+    params(("email" = String, Path, description = "Email of the admin")),
+    responses(
+        (status = 200, description = "Admin deleted", body = StatusMessage),
+        (status = 404, description = "Admin not found", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn delete_admin(
+    admin: AdminUser,
+    AppPath(email): AppPath<String>,
+    Extension(db): Extension<Db>,
+) -> AppResult<Json<StatusMessage>> {
+    if email == admin.email {
+        return Err(AppError::bad_request("Admins cannot delete themselves"));
+    }
+
+    db.run(move |conn| {
+        let affected_rows = conn
+            .delete_admin(&email)
+            .map_err(AppError::internal("Failed to delete admin"))?;
+
+        if affected_rows == 0 {
+            return Err(AppError::not_found("Admin not found"));
+        }
+
+        Ok(StatusMessage::success("Admin deleted successfully"))
+    })
+    .await
+    .map(Json)
+}
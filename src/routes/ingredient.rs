@@ -1,63 +1,75 @@
 use axum::{Extension, Json};
-use axum::http::StatusCode;
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
 use serde::{Serialize, Deserialize};
-use std::sync::Arc;
-use crate::schema::ingredients::dsl::*;
-use serde_json::json;
+use crate::db::Db;
+use crate::error::{AppError, AppJson, AppResult};
+use crate::repo::IngredientRepo;
+use crate::openapi::{AdminErrors, PublicErrors, StatusMessage};
+use tracing::info;
+use utoipa::ToSchema;
 
-#[derive(Serialize, Queryable)]
+#[derive(Serialize, Queryable, Debug, Clone, ToSchema)]
 pub struct Ingredient {
     pub ingredient_id: i32,
     pub ingredient_name: String,
     pub ingredient_name_eng: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct CreateIngredientPayload {
     pub ingredient_name: String,
     pub ingredient_name_eng: Option<String>, // Optional field
 }
 
-pub type DbPool = Pool<ConnectionManager<PgConnection>>;
-
+#[utoipa::path(
+    get,
+    path = "/ingredients",
+    tag = "v1",
+    responses(
+        (status = 200, description = "All ingredients", body = Vec<Ingredient>),
+        PublicErrors,
+    ),
+)]
 pub async fn get_ingredients(
-    Extension(db_pool): Extension<Arc<DbPool>>,
-) -> Result<Json<Vec<Ingredient>>, axum::http::StatusCode> {
-    let mut conn = db_pool.get().map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let results = ingredients
-        .select((ingredient_id, ingredient_name, ingredient_name_eng))
-        .load::<Ingredient>(&mut conn)
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    Extension(db): Extension<Db>,
+) -> AppResult<Json<Vec<Ingredient>>> {
+    let results = db
+        .run(|conn| {
+            conn.list_ingredients()
+                .map_err(AppError::internal("Error fetching ingredients"))
+        })
+        .await?;
 
     Ok(Json(results))
 }
 
+#[utoipa::path(
+    post,
+    path = "/create_ingredient",
+    tag = "v1",
+    request_body = CreateIngredientPayload,
+    responses(
+        (status = 200, description = "Ingredient created", body = StatusMessage),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
 pub async fn create_ingredient(
-    Extension(db_pool): Extension<Arc<DbPool>>,
-    Json(payload): Json<CreateIngredientPayload>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let mut conn = db_pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    diesel::insert_into(ingredients)
-        .values((
-            ingredient_name.eq(payload.ingredient_name.clone()),
-            ingredient_name_eng.eq(payload.ingredient_name_eng.clone()),
-        ))
-        .execute(&mut conn)
-        .map_err(|err| {
-            eprintln!("Failed to insert ingredient: {}", err);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CreateIngredientPayload>,
+) -> AppResult<Json<StatusMessage>> {
+    let (name, name_eng) = (payload.ingredient_name.clone(), payload.ingredient_name_eng.clone());
+    db.run(move |conn| {
+        conn.create_ingredient(&name, name_eng.as_deref())
+            .map_err(AppError::internal("Failed to insert ingredient"))
+    })
+    .await?;
 
-    println!(
-        "Created ingredient with name: {} and name_eng: {:?}",
-        &payload.ingredient_name, &payload.ingredient_name_eng
+    info!(
+        name = %payload.ingredient_name,
+        name_eng = ?payload.ingredient_name_eng,
+        "Created ingredient"
     );
 
-    Ok(Json(json!({
-        "status": "success",
-        "message": "Ingredient created successfully"
-    })))
+    Ok(Json(StatusMessage::success("Ingredient created successfully")))
 }
\ No newline at end of file
@@ -0,0 +1,174 @@
+use crate::db::Db;
+use crate::error::{AppError, AppJson, AppPath, AppQuery, AppResult, ErrorBody};
+use crate::identity::LineUserId;
+use crate::jobs::{is_finished, JobQueue, KIND_GENERATE, KIND_UPDATE, STATUS_PENDING};
+use crate::line_auth::LineUser;
+use crate::repo::JobRepo;
+use crate::routes::mealplan::{MealPlanRequest, UpdateMealPlanRequest};
+use crate::service::{check_days, require_user};
+use crate::openapi::{JobAccepted, UserErrors};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Longest a status request may wait for a job to finish.
+const MAX_WAIT_SECS: u64 = 60;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobStatusQuery {
+    pub wait: Option<u64>, // Seconds to wait for the job to finish before answering
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JobStatusResponse {
+    pub job_id: Uuid,
+    pub kind: String,
+    pub status: String,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+async fn submit(
+    db: &Db,
+    queue: &JobQueue,
+    user_line_id: LineUserId,
+    kind: &'static str,
+    request: serde_json::Value,
+) -> AppResult<(StatusCode, Json<JobAccepted>)> {
+    let queue = queue.clone();
+    let (user_id, job_id) = db
+        .run(move |conn| {
+            let user_id = require_user(conn, &user_line_id)?;
+
+            let job_id = queue.submit(conn, user_id, kind, request)?;
+            Ok((user_id, job_id))
+        })
+        .await?;
+
+    info!(kind, %job_id, %user_id, "Submitted job");
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobAccepted { job_id, status: STATUS_PENDING }),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/ai_meal_plan_jobs",
+    tag = "v1",
+    request_body = MealPlanRequest,
+    responses(
+        (status = 202, description = "Job queued; poll it for the result of `/ai_meal_plan`", body = JobAccepted),
+        (status = 429, description = "The queue is full or the user has too many unfinished jobs", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn submit_ai_meal_plan_job(
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    Extension(queue): Extension<JobQueue>,
+    AppJson(mut payload): AppJson<MealPlanRequest>,
+) -> AppResult<(StatusCode, Json<JobAccepted>)> {
+    check_days(payload.data.days)?;
+    payload.data.u_id = line_id;
+    let request = serde_json::to_value(&payload).map_err(AppError::internal("Failed to serialize request JSON"))?;
+    submit(&db, &queue, payload.data.u_id, KIND_GENERATE, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/update_meal_plan_jobs",
+    tag = "v1",
+    request_body = UpdateMealPlanRequest,
+    responses(
+        (status = 202, description = "Job queued; poll it for the result of `/update_meal_plan`", body = JobAccepted),
+        (status = 429, description = "The queue is full or the user has too many unfinished jobs", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn submit_update_meal_plan_job(
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    Extension(queue): Extension<JobQueue>,
+    AppJson(mut payload): AppJson<UpdateMealPlanRequest>,
+) -> AppResult<(StatusCode, Json<JobAccepted>)> {
+    payload.check_days()?;
+    payload.user_line_id = line_id;
+    let request = serde_json::to_value(&payload).map_err(AppError::internal("Failed to serialize request JSON"))?;
+    submit(&db, &queue, payload.user_line_id, KIND_UPDATE, request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/meal_plan_jobs/{job_id}",
+    tag = "v1",
+    params(("job_id" = Uuid, Path, description = "Job id"), JobStatusQuery),
+    responses(
+        (status = 200, description = "The job, finished or as it was when `wait` ran out", body = JobStatusResponse),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn get_meal_plan_job(
+    LineUser(line_id): LineUser,
+    AppPath(job_id): AppPath<Uuid>,
+    AppQuery(query): AppQuery<JobStatusQuery>,
+    Extension(db): Extension<Db>,
+    Extension(queue): Extension<JobQueue>,
+) -> AppResult<Json<JobStatusResponse>> {
+    // Subscribe before the first read so a completion in between is not missed.
+    let mut finished = queue.subscribe();
+    let deadline = Instant::now() + Duration::from_secs(query.wait.unwrap_or(0).min(MAX_WAIT_SECS));
+
+    let user_id = db.run(move |conn| require_user(conn, &line_id)).await?;
+
+    loop {
+        // Other users' jobs look the same as missing ones
+        let job = db
+            .run(move |conn| {
+                conn.find_job(job_id)
+                    .map_err(AppError::internal("Error fetching job"))?
+                    .filter(|job| job.user_id == user_id.0)
+                    .ok_or_else(|| AppError::not_found("Job not found"))
+            })
+            .await?;
+
+        if is_finished(&job.status) || Instant::now() >= deadline {
+            return Ok(Json(JobStatusResponse {
+                job_id: job.job_id,
+                kind: job.kind,
+                status: job.status,
+                result: job.result,
+                error: job.error,
+                attempts: job.attempts,
+                created_at: job.created_at,
+                updated_at: job.updated_at,
+            }));
+        }
+
+        // Wait for this job (or the deadline) before reading it again; other jobs'
+        // completions are skipped here. A lagged receiver may have missed ours, so it re-reads.
+        loop {
+            match tokio::time::timeout_at(deadline, finished.recv()).await {
+                Ok(Ok(id)) if id != job_id => continue,
+                _ => break,
+            }
+        }
+    }
+}
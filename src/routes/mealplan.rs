@@ -1,16 +1,24 @@
-use crate::schema::{
-    meal_plan_recipes, meal_plans, recipes, recipes_nutrients, recipes_ingredient_allergies, users,
-    users_ingredient_allergies, users_nutrients_limit_per_day,
+use crate::authz::authorize_meal_plan_recipe;
+use crate::catalog::RecipeCatalog;
+use crate::db::{Database, Db};
+use crate::error::{parse_date, AppError, AppJson, AppResult, ErrorBody};
+use crate::identity::{set_response_identity, LineUserId, PayloadIdentity, UserId};
+use crate::line_auth::LineUser;
+use crate::logging::redact;
+use crate::planner::{generate_meal_plan, validate_meal_plan};
+use crate::recommender::{AiMealPlan, ContractVersion, Recommender};
+use crate::repo::{MealPlanFilter, MealPlanRepo};
+use crate::service::{
+    check_days, check_meal_times, check_mealplan_days, meal_time_for, recommendation_context, require_user, save_meal_plan,
+    RecommendationContext,
 };
-use axum::http::StatusCode;
+use crate::openapi::{GeneratedMealPlan, MealPlanSaved, MealPlansRemoved, StatusMessage, UserErrors};
 use axum::{Extension, Json};
-use chrono::NaiveDate;
-use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
-use reqwest::Client;
+use chrono::{NaiveDate, NaiveDateTime};
+use metrics::counter;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, ToSchema)]
 pub struct Nutrition {
     pub calories: f32,
     pub carbs: f32,
@@ -21,27 +29,45 @@ pub struct Nutrition {
     pub sodium: f32,
 }
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
 pub struct Recipe {
     pub recipe_id: Option<i32>, // Change recipe_id to Option<i32>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meal_time: Option<i32>, // 1 breakfast to 4 snack; taken from the position in the day when missing
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OverwritePolicy {
+    #[default]
+    Append,  // Add recipes to days that already have a meal plan
+    Replace, // Drop the recipes of overlapping days before inserting
+    Fail,    // Reject the request if any day already has a meal plan
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct CreateMealPlanPayload {
-    pub user_line_id: String,        // The user_line_id field
     pub mealplans: Vec<Vec<Recipe>>, // A 2D vector representing the meal plans
+    pub start_date: Option<String>,  // YYYY-MM-DD, defaults to the day after the latest plan
+    #[serde(default)]
+    pub overwrite: OverwritePolicy,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct GetMealPlanRequest {
-    pub user_line_id: String,
     pub date: Option<String>,
+    pub start_date: Option<String>, // Inclusive range, YYYY-MM-DD
+    pub end_date: Option<String>,
+    #[serde(default)]
+    pub include_archived: bool, // Also return archived days, for history and reporting
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct RecipeInfo {
     pub recipe_id: i32,
     pub recipe_name: String,
@@ -52,47 +78,78 @@ pub struct RecipeInfo {
     pub calories: f64,         // Add calories
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct MealPlanEntry {
     pub meal_plan_id: i32,
-    pub user_id: i32,
+    pub user_id: UserId,
     pub name: String,
     pub date: NaiveDate,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<NaiveDateTime>,
     pub recipes: Vec<RecipeInfo>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, ToSchema)]
 pub struct GetMealPlanResponse {
     pub meal_plans: Vec<MealPlanEntry>,
 }
 
-#[derive(Serialize, Debug)]
-pub struct ErrorResponse {
-    pub error: String,
-}
-
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct UserAlreadyEatPayload {
     pub meal_plan_recipe_id: i32,
     pub ischecked: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteMode {
+    #[default]
+    Delete,  // Remove the days and their recipes
+    Archive, // Hide the days from the calendar but keep them for history
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct DeleteMealPlanPayload {
+    pub date: Option<String>,       // A single day, or
+    pub start_date: Option<String>, // an inclusive range
+    pub end_date: Option<String>,
+    #[serde(default)]
+    pub mode: DeleteMode,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct EditMealPlanPayload {
-    pub user_line_id: String,
     pub date: String,
     pub recipes: Vec<Recipe>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct MealPlanRequest {
     pub data: MealPlanRequestData,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Generator {
+    #[default]
+    Auto,  // Use the AI service, falling back to the built-in planner if it fails
+    Ai,    // Use the AI service only
+    Local, // Use the built-in planner only
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct MealPlanRequestData {
-    pub u_id: String,
+    // Set from the LINE ID token; kept in the struct because background jobs store the whole request
+    #[serde(alias = "user_line_id", default)]
+    pub u_id: LineUserId,
     pub days: i32,
+    #[serde(default)]
+    pub generator: Generator,
+    #[serde(default)]
+    pub persist: bool, // Save the validated plan with the same rules as create_meal_plan
+    pub start_date: Option<String>,
+    #[serde(default)]
+    pub overwrite: OverwritePolicy,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -105,22 +162,34 @@ pub struct FoodMenu {
 
 #[derive(Serialize, Debug)]
 pub struct ResponseData {
-    pub user_line_id: String,
+    #[serde(flatten)]
+    pub identity: PayloadIdentity,
     pub days: i32,
     pub food_menus: Vec<FoodMenu>,
     pub nutrition_limit_per_day: Nutrition,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct UpdateMealPlanRequest {
-    pub user_id: String,
+    // Set from the LINE ID token, like MealPlanRequestData::u_id
+    #[serde(alias = "user_id", default)]
+    pub user_line_id: LineUserId,
     pub days: i32,
     pub mealplans: Vec<Vec<Recipe>>, // Use Recipe for mealplans
 }
 
+impl UpdateMealPlanRequest {
+    /// 400 unless `days` and the days in `mealplans` are within [`crate::service::MAX_PLAN_DAYS`].
+    pub fn check_days(&self) -> AppResult<()> {
+        check_days(self.days)?;
+        check_mealplan_days(&self.mealplans)
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct UpdateMealPlanResponse {
-    pub user_line_id: String,
+    #[serde(flatten)]
+    pub identity: PayloadIdentity,
     pub days: i32,
     pub nutrition_limit_per_day: Nutrition,
     pub food_menus: Vec<FoodMenu>,
@@ -129,586 +198,497 @@ pub struct UpdateMealPlanResponse {
 
 #[derive(Serialize, Debug)]
 pub struct UpdateMealPlanRequestWithoutDays {
-    pub user_id: String,
+    #[serde(flatten)]
+    pub identity: PayloadIdentity,
     pub mealplans: Vec<Vec<FoodMenu>>,
 }
 
-pub type DbPool = Pool<ConnectionManager<PgConnection>>;
-
+#[utoipa::path(
+    post,
+    path = "/create_meal_plan",
+    tag = "v1",
+    request_body = CreateMealPlanPayload,
+    responses(
+        (status = 200, description = "Meal plan saved", body = MealPlanSaved),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
 #[axum::debug_handler]
 pub async fn create_meal_plan(
-    Extension(db_pool): Extension<Arc<DbPool>>,
-    Json(payload): Json<CreateMealPlanPayload>,
-) -> Result<Json<serde_json::Value>, Json<serde_json::Value>> {
-    println!("Received create_meal_plan payload: {:?}", payload);
-
-    let mut conn = db_pool.get().map_err(|err| {
-        println!("Failed to connect to the database: {}", err);
-        Json(json!({ "status": "error", "message": "Failed to connect to the database" }))
-    })?;
-
-    // 1. Fetch user_id from user_line_id
-    let user_id: i32 = users::table
-        .filter(users::user_line_id.eq(&payload.user_line_id))
-        .select(users::user_id)
-        .first(&mut conn)
-        .map_err(|err| {
-            println!("Failed to fetch user: {}", err);
-            Json(json!({ "status": "error", "message": "User not found" }))
-        })?;
-
-    println!("Fetched user_id: {}", user_id);
-
-    // 2. Find the latest meal plan date for the user
-    let latest_date: Option<NaiveDate> = meal_plans::table
-        .filter(meal_plans::user_id.eq(user_id))
-        .select(meal_plans::date)
-        .order(meal_plans::date.desc())
-        .first::<NaiveDate>(&mut conn)
-        .optional()
-        .map_err(|err| {
-            println!("Failed to fetch latest meal plan date: {}", err);
-            Json(json!({ "status": "error", "message": "Failed to fetch latest meal plan date" }))
-        })?;
-
-    let today = chrono::Local::now().date_naive();
-    let start_date = match latest_date {
-        Some(date) if date >= today => date + chrono::Duration::days(1), // Start from the next day if the latest date is in the future or today
-        _ => today, // Start from today if no meal plans exist or the latest date is in the past
-    };
-
-    println!("Starting meal plan creation from date: {}", start_date);
-
-    // 3. Create new meal plans
-    let transaction_result = {
-        let conn = &mut conn;
-        conn.transaction::<_, diesel::result::Error, _>(|conn| {
-            for (day_index, day_mealplans) in payload.mealplans.iter().enumerate() {
-                let meal_plan_date = start_date + chrono::Duration::days(day_index as i64);
-                println!("Creating meal plan for date: {}", meal_plan_date);
-
-                let meal_plan_name = format!("Meal Plan {}", meal_plan_date.format("%d/%m/%Y"));
-
-                let meal_plan_id: i32 = diesel::insert_into(meal_plans::table)
-                    .values((
-                        meal_plans::user_id.eq(user_id),
-                        meal_plans::name.eq(meal_plan_name),
-                        meal_plans::date.eq(meal_plan_date),
-                    ))
-                    .returning(meal_plans::meal_plan_id)
-                    .get_result(conn)?;
-
-                println!("Created meal_plan_id: {}", meal_plan_id);
-
-                for (recipe_index, recipe) in day_mealplans.iter().enumerate() {
-                    if let Some(recipe_id) = recipe.recipe_id {
-                        println!("Processing recipe_id: {}", recipe_id);
-
-                        // Determine meal_time
-                        let meal_time = if recipe_index < 4 {
-                            (recipe_index + 1) as i32 // 1, 2, 3, 4 for the first four recipes
-                        } else {
-                            4 // 4 for all subsequent recipes
-                        };
-
-                        diesel::insert_into(meal_plan_recipes::table)
-                            .values((
-                                meal_plan_recipes::meal_plan_id.eq(meal_plan_id),
-                                meal_plan_recipes::recipe_id.eq(recipe_id),
-                                meal_plan_recipes::ischecked.eq(false),
-                                meal_plan_recipes::meal_time.eq(Some(meal_time)), // Assign meal_time
-                            ))
-                            .execute(conn)?;
-                    }
-                }
-            }
-            Ok(())
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CreateMealPlanPayload>,
+) -> AppResult<Json<MealPlanSaved>> {
+    db.run(move |conn| {
+        // 1. Fetch user_id of the signed-in LINE user
+        let user_id = require_user(conn, &line_id)?;
+
+        // 2. Resolve the start date, reject overlapping days if asked to and create one meal plan per day,
+        //    reusing the existing row for a day so a user never has two plans on one date
+        let start_date = save_meal_plan(
+            conn,
+            user_id,
+            payload.start_date.as_deref(),
+            &payload.mealplans,
+            payload.overwrite,
+            "create",
+        )?;
+
+        info!(%user_id, days = payload.mealplans.len(), "Meal plan created");
+        Ok(MealPlanSaved {
+            status: "success",
+            message: "Meal plan created successfully",
+            start_date: start_date.format("%Y-%m-%d").to_string(),
         })
-    };
-
-    transaction_result.map_err(|err| {
-        println!("Failed to create meal plan: {}", err);
-        Json(json!({ "status": "error", "message": "Failed to create meal plan" }))
-    })?;
-
-    println!("Meal plan created successfully");
-    Ok(Json(
-        json!({ "status": "success", "message": "Meal plan created successfully" }),
-    ))
+    })
+    .await
+    .map(Json)
 }
 
+#[utoipa::path(
+    post,
+    path = "/get_meal_plan",
+    tag = "v1",
+    request_body = GetMealPlanRequest,
+    responses(
+        (status = 200, description = "Meal plans with their recipes", body = GetMealPlanResponse),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
 #[axum::debug_handler]
 pub async fn get_meal_plan(
-    Extension(db_pool): Extension<Arc<DbPool>>,
-    Json(payload): Json<GetMealPlanRequest>,
-) -> Result<Json<GetMealPlanResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let mut conn = db_pool.get().map_err(|err| {
-        eprintln!("Failed to connect to the database: {}", err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to connect to the database".to_string(),
-            }),
-        )
-    })?;
-
-    // 1. Fetch user_id from user_line_id
-    let user_id: i32 = users::table
-        .filter(users::user_line_id.eq(&payload.user_line_id))
-        .select(users::user_id)
-        .first(&mut conn)
-        .map_err(|_| {
-            (
-                StatusCode::NOT_FOUND,
-                Json(ErrorResponse {
-                    error: "User not found".to_string(),
-                }),
-            )
-        })?;
-
-    // 2. Build the query
-    let mut query = meal_plans::table
-        .inner_join(
-            meal_plan_recipes::table
-                .on(meal_plans::meal_plan_id.eq(meal_plan_recipes::meal_plan_id)),
-        )
-        .inner_join(recipes::table.on(meal_plan_recipes::recipe_id.eq(recipes::recipe_id)))
-        .filter(meal_plans::user_id.eq(user_id))
-        .into_boxed();
-
-    if let Some(date_str) = &payload.date {
-        let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d").map_err(|_| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse {
-                    error: "Invalid date format. Use YYYY-MM-DD".to_string(),
-                }),
-            )
-        })?;
-        query = query.filter(meal_plans::date.eq(date));
-    }
-
-    // 3. Fetch meal plans
-    let results = query
-        .select((
-            meal_plans::meal_plan_id,
-            meal_plans::user_id,
-            meal_plans::name,
-            meal_plans::date,
-            meal_plan_recipes::meal_plan_recipe_id,
-            meal_plan_recipes::recipe_id,
-            meal_plan_recipes::meal_time, // Include meal_time
-            recipes::recipe_name,
-            recipes::recipe_img_link,
-            recipes::calories, // Include calories
-            meal_plan_recipes::ischecked,
-        ))
-        .load::<(
-            i32,
-            i32,
-            String,
-            NaiveDate,
-            i32,
-            i32,
-            Option<i32>,
-            String,
-            Option<Vec<Option<String>>>,
-            f64,
-            Option<bool>,
-        )>(&mut conn)
-        .map_err(|err| {
-            eprintln!("Database error fetching meal plans: {}", err);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Error fetching meal plans".to_string(),
-                }),
-            )
-        })?;
-
-    // 4. Organize the data into the desired structure
-    let mut meal_plans_map: HashMap<i32, MealPlanEntry> = HashMap::new();
-    for (
-        meal_plan_id,
-        user_id,
-        name,
-        date,
-        meal_plan_recipe_id,
-        recipe_id,
-        meal_time,
-        recipe_name,
-        recipe_img_link,
-        calories,
-        ischecked,
-    ) in results
-    {
-        let meal_plan_entry = meal_plans_map
-            .entry(meal_plan_id)
-            .or_insert_with(|| MealPlanEntry {
-                meal_plan_id,
-                user_id,
-                name,
-                date,
-                recipes: Vec::new(),
-            });
-
-        meal_plan_entry.recipes.push(RecipeInfo {
-            recipe_id,
-            recipe_name,
-            recipe_img_link: recipe_img_link
-                .unwrap_or_default()
-                .into_iter()
-                .filter_map(|x| x)
-                .collect(),
-            ischecked,
-            meal_plan_recipe_id,
-            meal_time,
-            calories,
-        });
-    }
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<GetMealPlanRequest>,
+) -> AppResult<Json<GetMealPlanResponse>> {
+    db.run(move |conn| {
+        // 1. Fetch user_id of the signed-in LINE user
+        let user_id = require_user(conn, &line_id)?;
+
+        // 2. Build the filter
+        let filter = MealPlanFilter {
+            date: payload.date.as_deref().map(parse_date).transpose()?,
+            start_date: payload.start_date.as_deref().map(parse_date).transpose()?,
+            end_date: payload.end_date.as_deref().map(parse_date).transpose()?,
+            include_archived: payload.include_archived,
+        };
+
+        // 3. Fetch the meal plans grouped with their recipes
+        let meal_plans = conn
+            .list_meal_plans(user_id, filter)
+            .map_err(AppError::internal("Error fetching meal plans"))?;
+
+        Ok(GetMealPlanResponse { meal_plans })
+    })
+    .await
+    .map(Json)
+}
 
-    let meal_plans: Vec<MealPlanEntry> = meal_plans_map.into_values().collect();
+#[utoipa::path(
+    patch,
+    path = "/user_already_eat",
+    tag = "v1",
+    request_body = UserAlreadyEatPayload,
+    responses(
+        (status = 200, description = "Meal plan recipe updated", body = StatusMessage),
+        (status = 403, description = "The recipe is on another user's meal plan", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn user_already_eat(
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<UserAlreadyEatPayload>,
+) -> AppResult<Json<StatusMessage>> {
+    db.run(move |conn| {
+        // 1. Check the entry is on one of the signed-in user's meal plans
+        let user_id = require_user(conn, &line_id)?;
+        let entry = authorize_meal_plan_recipe(conn, user_id, payload.meal_plan_recipe_id)?;
+
+        // 2. Update the ischecked field for the given meal_plan_recipe_id
+        let affected_rows = conn
+            .set_checked(entry, payload.ischecked)
+            .map_err(AppError::internal("Failed to update meal plan recipe"))?;
+
+        if affected_rows == 0 {
+            return Err(AppError::not_found("Meal plan recipe not found"));
+        }
 
-    Ok(Json(GetMealPlanResponse { meal_plans }))
+        info!(
+            meal_plan_recipe_id = payload.meal_plan_recipe_id,
+            ischecked = payload.ischecked,
+            "Updated meal plan recipe"
+        );
+        counter!("meals_checked_total", "checked" => payload.ischecked.to_string()).increment(1);
+
+        Ok(StatusMessage::success("Meal plan recipe updated successfully"))
+    })
+    .await
+    .map(Json)
 }
 
+#[utoipa::path(
+    delete,
+    path = "/delete_meal_plan",
+    tag = "v1",
+    request_body = DeleteMealPlanPayload,
+    responses(
+        (status = 200, description = "Meal plan days deleted or archived", body = MealPlansRemoved),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
 #[axum::debug_handler]
-pub async fn user_already_eat(
-    Extension(db_pool): Extension<Arc<DbPool>>,
-    Json(payload): Json<UserAlreadyEatPayload>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
-    let mut conn = db_pool.get().map_err(|err| {
-        eprintln!("Failed to connect to the database: {}", err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to connect to the database".to_string(),
-            }),
-        )
-    })?;
-
-    // Update the ischecked field for the given meal_plan_recipe_id
-    diesel::update(meal_plan_recipes::table.filter(meal_plan_recipes::meal_plan_recipe_id.eq(payload.meal_plan_recipe_id)))
-        .set(meal_plan_recipes::ischecked.eq(payload.ischecked))
-        .execute(&mut conn)
-        .map_err(|err| {
-            eprintln!("Failed to update ischecked: {}", err);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Failed to update meal plan recipe".to_string(),
-                }),
-            )
-        })?;
-
-    println!(
-        "Updated meal_plan_recipe_id {} with ischecked = {}",
-        payload.meal_plan_recipe_id, payload.ischecked
-    );
+pub async fn delete_meal_plan(
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<DeleteMealPlanPayload>,
+) -> AppResult<Json<MealPlansRemoved>> {
+    db.run(move |conn| {
+        // 1. Fetch user_id of the signed-in LINE user
+        let user_id = require_user(conn, &line_id)?;
+
+        // 2. Resolve the date range: either a single date or both ends of a range
+        let (start_date, end_date) = match (&payload.date, &payload.start_date, &payload.end_date) {
+            (Some(date), None, None) => {
+                let date = parse_date(date)?;
+                (date, date)
+            }
+            (None, Some(start), Some(end)) => (parse_date(start)?, parse_date(end)?),
+            _ => {
+                return Err(AppError::bad_request(
+                    "Provide either date or both start_date and end_date",
+                ));
+            }
+        };
+
+        if start_date > end_date {
+            return Err(AppError::bad_request("start_date must not be after end_date"));
+        }
+
+        // 3. Delete or archive the active meal plans in the range
+        let transaction_result = conn.atomically(|conn| {
+            let meal_plan_ids = conn.active_meal_plan_ids(user_id, start_date, end_date)?;
+
+            if meal_plan_ids.is_empty() {
+                return Ok(0);
+            }
 
-    Ok(Json(json!({
-        "status": "success",
-        "message": "Meal plan recipe updated successfully"
-    })))
+            match payload.mode {
+                DeleteMode::Delete => conn.delete_meal_plans(&meal_plan_ids),
+                DeleteMode::Archive => conn.archive_meal_plans(&meal_plan_ids),
+            }
+        });
+
+        let affected_days = transaction_result.map_err(AppError::internal("Failed to delete meal plans"))?;
+
+        if affected_days == 0 {
+            return Err(AppError::not_found("Meal plan not found for the given date"));
+        }
+
+        info!(
+            mode = ?payload.mode,
+            affected_days,
+            %user_id,
+            %start_date,
+            %end_date,
+            "Removed meal plan days"
+        );
+
+        Ok(MealPlansRemoved {
+            status: "success",
+            message: match payload.mode {
+                DeleteMode::Delete => "Meal plan deleted successfully",
+                DeleteMode::Archive => "Meal plan archived successfully",
+            },
+            days: affected_days,
+        })
+    })
+    .await
+    .map(Json)
 }
 
+#[utoipa::path(
+    patch,
+    path = "/edit_meal_plan",
+    tag = "v1",
+    request_body = EditMealPlanPayload,
+    responses(
+        (status = 200, description = "Recipes of the day replaced", body = StatusMessage),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
 #[axum::debug_handler]
 pub async fn edit_meal_plan(
-    Extension(db_pool): Extension<Arc<DbPool>>,
-    Json(payload): Json<EditMealPlanPayload>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
-    let mut conn = db_pool.get().map_err(|err| {
-        eprintln!("Failed to connect to the database: {}", err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to connect to the database".to_string(),
-            }),
-        )
-    })?;
-
-    // 1. Fetch user_id from user_line_id
-    let user_id: i32 = users::table
-        .filter(users::user_line_id.eq(&payload.user_line_id))
-        .select(users::user_id)
-        .first(&mut conn)
-        .map_err(|_| {
-            (
-                StatusCode::NOT_FOUND,
-                Json(ErrorResponse {
-                    error: "User not found".to_string(),
-                }),
-            )
-        })?;
-
-    // 2. Parse the date and find the meal_plan_id
-    let date = NaiveDate::parse_from_str(&payload.date, "%Y-%m-%d").map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Invalid date format. Use YYYY-MM-DD".to_string(),
-            }),
-        )
-    })?;
-
-    let meal_plan_id: i32 = meal_plans::table
-        .filter(meal_plans::user_id.eq(user_id))
-        .filter(meal_plans::date.eq(date))
-        .select(meal_plans::meal_plan_id)
-        .first(&mut conn)
-        .map_err(|_| {
-            (
-                StatusCode::NOT_FOUND,
-                Json(ErrorResponse {
-                    error: "Meal plan not found for the given date".to_string(),
-                }),
-            )
-        })?;
-
-    println!("Found meal_plan_id: {}", meal_plan_id);
-
-    // 3. Delete existing recipes for the meal_plan_id
-    diesel::delete(meal_plan_recipes::table.filter(meal_plan_recipes::meal_plan_id.eq(meal_plan_id)))
-        .execute(&mut conn)
-        .map_err(|err| {
-            eprintln!("Failed to delete old recipes: {}", err);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Failed to delete old recipes".to_string(),
-                }),
-            )
-        })?;
-
-    println!("Deleted old recipes for meal_plan_id: {}", meal_plan_id);
-
-    // 4. Insert new recipes
-    let transaction_result = {
-        let conn = &mut conn;
-        conn.transaction::<_, diesel::result::Error, _>(|conn| {
-            for (recipe_index, recipe) in payload.recipes.iter().enumerate() {
-                let meal_time = if recipe_index < 4 {
-                    (recipe_index + 1) as i32 // 1, 2, 3, 4 for the first four recipes
-                } else {
-                    4 // 4 for all subsequent recipes
-                };
-
-                diesel::insert_into(meal_plan_recipes::table)
-                    .values((
-                        meal_plan_recipes::meal_plan_id.eq(meal_plan_id),
-                        meal_plan_recipes::recipe_id.eq(recipe.recipe_id
-                            .ok_or_else(|| diesel::result::Error::RollbackTransaction)?),
-                        meal_plan_recipes::ischecked.eq(false),
-                        meal_plan_recipes::meal_time.eq(Some(meal_time)),
-                    ))
-                    .execute(conn)?;
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<EditMealPlanPayload>,
+) -> AppResult<Json<StatusMessage>> {
+    db.run(move |conn| {
+        // 1. Fetch user_id of the signed-in LINE user
+        let user_id = require_user(conn, &line_id)?;
+
+        // 2. Parse the date and find the meal_plan_id
+        let date = parse_date(&payload.date)?;
+
+        if payload.recipes.iter().any(|recipe| recipe.recipe_id.is_none()) {
+            return Err(AppError::bad_request("Every recipe needs a recipe_id"));
+        }
+        check_meal_times(std::slice::from_ref(&payload.recipes))?;
+
+        let meal_plan_id = conn
+            .active_meal_plan_ids(user_id, date, date)
+            .map_err(AppError::internal("Failed to fetch meal plan"))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppError::not_found("Meal plan not found for the given date"))?;
+
+        // 3. Replace the recipes of the meal plan
+        let transaction_result = conn.atomically(|conn| {
+            conn.clear_recipes(meal_plan_id)?;
+            let recipes = payload.recipes.iter().filter_map(|recipe| Some((recipe.recipe_id?, recipe.meal_time)));
+            for (recipe_index, (recipe_id, meal_time)) in recipes.enumerate() {
+                conn.add_recipe(meal_plan_id, recipe_id, meal_time.unwrap_or_else(|| meal_time_for(recipe_index)))?;
             }
             Ok(())
-        })
-    };
+        });
 
-    transaction_result.map_err(|err| {
-        eprintln!("Failed to insert new recipes: {}", err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Failed to insert new recipes".to_string(),
-            }),
-        )
-    })?;
+        transaction_result.map_err(AppError::internal("Failed to replace recipes"))?;
 
-    println!("Updated meal plan successfully for meal_plan_id: {}", meal_plan_id);
+        info!(meal_plan_id, "Updated meal plan");
 
-    Ok(Json(json!({
-        "status": "success",
-        "message": "Meal plan updated successfully"
-    })))
+        Ok(StatusMessage::success("Meal plan updated successfully"))
+    })
+    .await
+    .map(Json)
 }
 
+#[utoipa::path(
+    post,
+    path = "/ai_meal_plan",
+    tag = "v1",
+    request_body = MealPlanRequest,
+    responses(
+        (status = 200, description = "Generated meal plan", body = GeneratedMealPlan),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        (status = 422, description = "`persist` was set but the plan failed validation; `details.issues` lists why", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
 #[axum::debug_handler]
 pub async fn ai_meal_plan(
-    Extension(db_pool): Extension<Arc<DbPool>>,
-    Json(payload): Json<MealPlanRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let mut conn = db_pool.get().map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Database connection error".to_string(),
-        )
-    })?;
-
-    // 1. Fetch user information
-    let user = users::table
-        .filter(users::user_line_id.eq(&payload.data.u_id))
-        .select((users::user_id, users::user_line_id))
-        .first::<(i32, Option<String>)>(&mut conn)
-        .map_err(|_| (StatusCode::NOT_FOUND, "User not found".to_string()))?;
-
-    let user_id = user.0;
-
-    // 2. Fetch food menus that the user is not allergic to
-    let filtered_recipes = recipes::table
-        .left_join(recipes_nutrients::table.on(recipes::recipe_id.eq(recipes_nutrients::recipe_id)))
-        .filter(diesel::dsl::not(diesel::dsl::exists(
-            recipes_ingredient_allergies::table
-                .inner_join(users_ingredient_allergies::table.on(
-                    recipes_ingredient_allergies::ingredient_allergy_id
-                        .eq(users_ingredient_allergies::ingredient_allergy_id),
-                ))
-                .filter(users_ingredient_allergies::user_id.eq(user_id))
-                .filter(recipes_ingredient_allergies::recipe_id.eq(recipes::recipe_id)),
-        )))
-        .group_by((recipes::recipe_id, recipes::recipe_name, recipes::recipe_img_link))
-        .select((
-            recipes::recipe_id,
-            recipes::recipe_name,
-            recipes::recipe_img_link,
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // protein
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // carbs
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // fat
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // sodium
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // phosphorus
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // potassium
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // calories
-        ))
-        .load::<(
-            i32,
-            String,
-            Option<Vec<Option<String>>>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-        )>(&mut conn)
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Error fetching filtered recipes".to_string(),
-            )
-        })?;
-
-    // 3. Fetch the user's daily nutrition limits
-    let nutrition_limits = users_nutrients_limit_per_day::table
-        .filter(users_nutrients_limit_per_day::user_id.eq(user_id))
-        .select((
-            users_nutrients_limit_per_day::nutrient_id,
-            users_nutrients_limit_per_day::nutrient_limit,
-        ))
-        .load::<(Option<i32>, Option<f64>)>(&mut conn)
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Error fetching nutrition limits".to_string(),
-            )
-        })?;
-
-    let mut nutrition_map = Nutrition {
-        calories: 0.0,
-        carbs: 0.0,
-        fat: 0.0,
-        phosphorus: 0.0,
-        potassium: 0.0,
-        protein: 0.0,
-        sodium: 0.0,
-    };
-
-    for (nutrient_id, nutrient_limit) in nutrition_limits {
-        match nutrient_id {
-            Some(1) => nutrition_map.calories = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(2) => nutrition_map.carbs = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(3) => nutrition_map.fat = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(4) => nutrition_map.phosphorus = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(5) => nutrition_map.potassium = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(6) => nutrition_map.protein = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(7) => nutrition_map.sodium = nutrient_limit.unwrap_or(0.0) as f32,
-            _ => (),
-        }
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    Extension(recommender): Extension<Arc<dyn Recommender>>,
+    Extension(catalog): Extension<Arc<RecipeCatalog>>,
+    AppJson(mut payload): AppJson<MealPlanRequest>,
+) -> AppResult<Json<serde_json::Value>> {
+    payload.data.u_id = line_id;
+    let (mut plan, to_save) = generate_ai_meal_plan(&db, recommender.as_ref(), &catalog, payload).await?;
+    if let Some(to_save) = to_save {
+        plan = db
+            .run(move |conn| {
+                to_save.save(conn, &mut plan, "ai")?;
+                Ok(plan)
+            })
+            .await?;
     }
+    Ok(Json(plan))
+}
 
-    // 4. Convert the filtered recipes into the required `FoodMenu` format
-    let food_menus: Vec<FoodMenu> = filtered_recipes
-        .into_iter()
-        .map(|recipe| FoodMenu {
-            name: recipe.1,
-            nutrition: Nutrition {
-                calories: recipe.9.unwrap_or(0.0) as f32,
-                carbs: recipe.4.unwrap_or(0.0) as f32,
-                fat: recipe.5.unwrap_or(0.0) as f32,
-                phosphorus: recipe.7.unwrap_or(0.0) as f32,
-                potassium: recipe.8.unwrap_or(0.0) as f32,
-                protein: recipe.3.unwrap_or(0.0) as f32,
-                sodium: recipe.6.unwrap_or(0.0) as f32,
-            },
-            recipe_id: recipe.0,
-            recipe_img_link: recipe
-                .2
-                .unwrap_or_default()
-                .into_iter()
-                .filter_map(|x| x)
-                .collect(),
-        })
-        .collect();
+/// A generated plan that passed validation and was asked to be saved (`persist`).
+/// Saving is left to the caller so it can happen in the caller's transaction.
+#[derive(Debug)]
+pub struct PlanToSave {
+    pub user_id: UserId,
+    pub requested_start: Option<String>,
+    pub mealplans: Vec<Vec<Recipe>>,
+    pub overwrite: OverwritePolicy,
+}
+
+impl PlanToSave {
+    /// Saves the days and adds the start date used to `plan`.
+    pub fn save<R: MealPlanRepo>(&self, repo: &mut R, plan: &mut serde_json::Value, source: &'static str) -> AppResult<()> {
+        let start_date = save_meal_plan(
+            repo,
+            self.user_id,
+            self.requested_start.as_deref(),
+            &self.mealplans,
+            self.overwrite,
+            source,
+        )?;
+        plan["start_date"] = json!(start_date.format("%Y-%m-%d").to_string());
+        Ok(())
+    }
+}
 
-    // 5. Construct the request payload
+/// Generates a meal plan for the user and, when `persist` is set and it passed validation,
+/// the days to save; shared by `ai_meal_plan` and background jobs.
+pub async fn generate_ai_meal_plan<D: Database>(
+    db: &D,
+    recommender: &dyn Recommender,
+    catalog: &Arc<RecipeCatalog>,
+    payload: MealPlanRequest,
+) -> AppResult<(serde_json::Value, Option<PlanToSave>)> {
+    // 1. Fetch the user, the food menus they are not allergic to and their daily nutrition limits
+    check_days(payload.data.days)?;
+    let line_id = payload.data.u_id.clone();
+    let catalog = catalog.clone();
+    let RecommendationContext { user_id, catalog_recipes, food_menus, nutrition_limit_per_day } = db
+        .run(move |conn| recommendation_context(conn, &catalog, &line_id))
+        .await?;
+
+    // 2. Construct the request payload
     let response_data = ResponseData {
-        user_line_id: user_id.to_string(), // Send user_id but label it as user_line_id
+        identity: match recommender.contract() {
+            ContractVersion::V1 => PayloadIdentity::V1InternalIdAsLineId {
+                user_line_id: user_id.to_string(),
+            },
+            ContractVersion::V2 => PayloadIdentity::v2(user_id, &payload.data.u_id),
+        },
         days: payload.data.days,
         food_menus,
-        nutrition_limit_per_day: nutrition_map,
+        nutrition_limit_per_day,
+    };
+
+    let local_meal_plan = || {
+        let days = payload.data.days as usize; // Checked above
+        let mealplans = generate_meal_plan(
+            &response_data.food_menus,
+            &response_data.nutrition_limit_per_day,
+            days,
+        );
+        json!({
+            "user_line_id": payload.data.u_id,
+            "days": payload.data.days,
+            "mealplans": mealplans,
+            "generator": "local",
+        })
     };
 
-    // Print request before sending
-    println!("Sending request to AI service: {:#?}", response_data);
+    let plan_json = if payload.data.generator == Generator::Local {
+        local_meal_plan()
+    } else {
+        // 3. Send the request to the AI recommender
+        let request_json = serde_json::to_value(&response_data)
+            .map_err(AppError::internal("Failed to serialize request JSON"))?;
+        debug!(request = %redact(&request_json), "Sending request to AI service");
+
+        let response = recommender
+            .generate(&request_json)
+            .await
+            .map_err(|err| AppError::Upstream(format!("AI service error: {}", err)));
+
+        // 4. Use the response from the external API, or the built-in plan if it failed
+        match response {
+            Ok(response) => response,
+            Err(err) if payload.data.generator == Generator::Auto => {
+                warn!(error = %err, "AI service failed, falling back to the built-in planner");
+                local_meal_plan()
+            }
+            Err(err) => return Err(err),
+        }
+    };
 
-    // 6. Send a POST request to the external AI service
-    let client = Client::new();
-    let api_url = "https://ai-rec-1025044834972.asia-southeast1.run.app/ai";
+    // 5. Validate the plan against the catalog, the user's allergies and daily limits
+    let mut plan: AiMealPlan = serde_json::from_value(plan_json)
+        .map_err(|err| AppError::Upstream(format!("Invalid AI response: {}", err)))?;
+    let plan_days = plan.recipe_ids();
+    set_response_identity(&mut plan.extra, &payload.data.u_id);
 
-    let response = client
-        .post(api_url)
-        .json(&response_data)
-        .send()
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to send request".to_string(),
-            )
-        })?
-        .json::<serde_json::Value>()
-        .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to parse response".to_string(),
-            )
-        })?;
+    let pool_ids: HashSet<i32> = response_data.food_menus.iter().map(|menu| menu.recipe_id).collect();
+    let outside_pool: Vec<i32> = plan_days
+        .iter()
+        .flatten()
+        .filter(|recipe_id| !pool_ids.contains(recipe_id))
+        .copied()
+        .collect();
+    let catalog_ids: HashSet<i32> = catalog_recipes.iter().map(|recipe| recipe.menu.recipe_id).collect();
+    let excluded: HashSet<i32> = outside_pool.into_iter().filter(|recipe_id| catalog_ids.contains(recipe_id)).collect();
+
+    let validation = validate_meal_plan(
+        &plan_days,
+        &response_data.food_menus,
+        &response_data.nutrition_limit_per_day,
+        &excluded,
+    );
+
+    // 6. Hand back the days to save, only when the plan passed validation
+    let to_save = if payload.data.persist {
+        if !validation.valid {
+            return Err(AppError::Unprocessable(
+                "Meal plan failed validation".to_string(),
+                Some(json!({ "issues": validation.issues })),
+            ));
+        }
+
+        let mealplans: Vec<Vec<Recipe>> = plan
+            .mealplans
+            .iter()
+            .map(|day| {
+                day.iter()
+                    .map(|menu| Recipe { recipe_id: Some(menu.recipe_id), meal_time: menu.meal_time })
+                    .collect()
+            })
+            .collect();
+        Some(PlanToSave {
+            user_id,
+            requested_start: payload.data.start_date.clone(),
+            mealplans,
+            overwrite: payload.data.overwrite,
+        })
+    } else {
+        None
+    };
+
+    plan.extra.insert("persisted".to_string(), json!(payload.data.persist));
+    plan.extra.insert("validation".to_string(), json!(validation));
 
-    // 7. Return the response from the external API
-    Ok(Json(response))
+    // 7. Return the validated plan
+    Ok((json!(plan), to_save))
 }
 
+#[utoipa::path(
+    post,
+    path = "/update_meal_plan",
+    tag = "v1",
+    request_body = UpdateMealPlanRequest,
+    responses(
+        (status = 200, description = "Reworked meal plan as returned by the recommender", body = Object),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
 #[axum::debug_handler]
 pub async fn update_meal_plan(
-    Extension(db_pool): Extension<Arc<DbPool>>,
-    Json(payload): Json<UpdateMealPlanRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    let mut conn = db_pool.get().map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database connection error".to_string()))?;
-
-    // 1. Fetch user information
-    let user = users::table
-        .filter(users::user_line_id.eq(&payload.user_id))
-        .select((users::user_id, users::user_line_id))
-        .first::<(i32, Option<String>)>(&mut conn)
-        .map_err(|_| (StatusCode::NOT_FOUND, "User not found".to_string()))?;
-
-    let user_id = user.0;
-    let user_line_id = user.1.ok_or((StatusCode::NOT_FOUND, "User LINE ID not found".to_string()))?;
-
-    // 2. Validate and filter mealplans
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    Extension(recommender): Extension<Arc<dyn Recommender>>,
+    Extension(catalog): Extension<Arc<RecipeCatalog>>,
+    AppJson(mut payload): AppJson<UpdateMealPlanRequest>,
+) -> AppResult<Json<serde_json::Value>> {
+    payload.user_line_id = line_id;
+    regenerate_ai_meal_plan(&db, recommender.as_ref(), &catalog, payload).await.map(Json)
+}
+
+/// Asks the recommender to rework an existing meal plan; shared by `update_meal_plan` and background jobs.
+pub async fn regenerate_ai_meal_plan<D: Database>(
+    db: &D,
+    recommender: &dyn Recommender,
+    catalog: &Arc<RecipeCatalog>,
+    payload: UpdateMealPlanRequest,
+) -> AppResult<serde_json::Value> {
+    let user_line_id = &payload.user_line_id;
+
+    // 1. Validate and filter mealplans
+    payload.check_days()?;
     let valid_mealplans: Vec<Vec<Recipe>> = payload
         .mealplans
         .iter()
@@ -720,94 +700,14 @@ pub async fn update_meal_plan(
         })
         .collect();
 
-    // 3. Fetch food menus that the user is not allergic to
-    let filtered_recipes = recipes::table
-        .left_join(recipes_nutrients::table.on(recipes::recipe_id.eq(recipes_nutrients::recipe_id)))
-        .filter(diesel::dsl::not(diesel::dsl::exists(
-            recipes_ingredient_allergies::table
-                .inner_join(users_ingredient_allergies::table.on(
-                    recipes_ingredient_allergies::ingredient_allergy_id.eq(users_ingredient_allergies::ingredient_allergy_id),
-                ))
-                .filter(users_ingredient_allergies::user_id.eq(user_id))
-                .filter(recipes_ingredient_allergies::recipe_id.eq(recipes::recipe_id)),
-        )))
-        .group_by((recipes::recipe_id, recipes::recipe_name, recipes::recipe_img_link))
-        .select((
-            recipes::recipe_id,
-            recipes::recipe_name,
-            recipes::recipe_img_link,
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // protein
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // carbs
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // fat
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // sodium
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // phosphorus
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // potassium
-            diesel::dsl::sum(recipes_nutrients::quantity).nullable(), // calories
-        ))
-        .load::<(
-            i32,
-            String,
-            Option<Vec<Option<String>>>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-            Option<f64>,
-        )>(&mut conn)
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Error fetching filtered recipes".to_string()))?;
-
-    // 4. Fetch the user's daily nutrition limits
-    let nutrition_limits = users_nutrients_limit_per_day::table
-        .filter(users_nutrients_limit_per_day::user_id.eq(user_id))
-        .select((users_nutrients_limit_per_day::nutrient_id, users_nutrients_limit_per_day::nutrient_limit))
-        .load::<(Option<i32>, Option<f64>)>(&mut conn)
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Error fetching nutrition limits".to_string()))?;
-
-    let mut nutrition_map = Nutrition {
-        calories: 0.0,
-        carbs: 0.0,
-        fat: 0.0,
-        phosphorus: 0.0,
-        potassium: 0.0,
-        protein: 0.0,
-        sodium: 0.0,
-    };
-
-    for (nutrient_id, nutrient_limit) in nutrition_limits {
-        match nutrient_id {
-            Some(1) => nutrition_map.calories = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(2) => nutrition_map.carbs = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(3) => nutrition_map.fat = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(4) => nutrition_map.phosphorus = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(5) => nutrition_map.potassium = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(6) => nutrition_map.protein = nutrient_limit.unwrap_or(0.0) as f32,
-            Some(7) => nutrition_map.sodium = nutrient_limit.unwrap_or(0.0) as f32,
-            _ => (),
-        }
-    }
-
-    // 5. Convert the filtered recipes into the required `FoodMenu` format
-    let food_menus: Vec<FoodMenu> = filtered_recipes
-        .into_iter()
-        .map(|recipe| FoodMenu {
-            name: recipe.1,
-            nutrition: Nutrition {
-                calories: recipe.9.unwrap_or(0.0) as f32,
-                carbs: recipe.4.unwrap_or(0.0) as f32,
-                fat: recipe.5.unwrap_or(0.0) as f32,
-                phosphorus: recipe.7.unwrap_or(0.0) as f32,
-                potassium: recipe.8.unwrap_or(0.0) as f32,
-                protein: recipe.3.unwrap_or(0.0) as f32,
-                sodium: recipe.6.unwrap_or(0.0) as f32,
-            },
-            recipe_id: recipe.0,
-            recipe_img_link: recipe.2.unwrap_or_default().into_iter().filter_map(|x| x).collect(),
-        })
-        .collect();
+    // 2. Fetch the user, the food menus they are not allergic to and their daily nutrition limits
+    let line_id = user_line_id.clone();
+    let catalog = catalog.clone();
+    let RecommendationContext { user_id, food_menus, nutrition_limit_per_day, .. } = db
+        .run(move |conn| recommendation_context(conn, &catalog, &line_id))
+        .await?;
 
-    // 6. Construct the detailed mealplans
+    // 3. Construct the detailed mealplans
     let detailed_mealplans: Vec<Vec<FoodMenu>> = valid_mealplans
         .iter()
         .map(|day| {
@@ -819,42 +719,114 @@ pub async fn update_meal_plan(
         })
         .collect();
 
-    // 7. Construct the response to send to the external API
+    // 4. Construct the response to send to the external API
+    let (identity, mealplan_identity) = match recommender.contract() {
+        ContractVersion::V1 => (
+            PayloadIdentity::V1LineId { user_line_id: user_line_id.clone() },
+            PayloadIdentity::V1LineIdAsUserId { user_id: user_line_id.clone() },
+        ),
+        ContractVersion::V2 => (
+            PayloadIdentity::v2(user_id, user_line_id),
+            PayloadIdentity::v2(user_id, user_line_id),
+        ),
+    };
+
     let response_data = UpdateMealPlanResponse {
-        user_line_id: user_line_id.clone(),
+        identity,
         days: payload.days,
-        nutrition_limit_per_day: nutrition_map,
+        nutrition_limit_per_day,
         food_menus,
         mealplan: UpdateMealPlanRequestWithoutDays {
-            user_id: user_line_id.clone(),
+            identity: mealplan_identity,
             mealplans: detailed_mealplans, // Use detailed mealplans
         },
     };
 
-    // Print the request JSON
-    let request_json = serde_json::to_string(&response_data).map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to serialize request JSON".to_string()))?;
-    println!("Request JSON to ai_update: {}", request_json);
-
-    // 8. Send a POST request to the external AI service
-    let client = Client::new();
-    let api_url = "https://ai-rec-1025044834972.asia-southeast1.run.app/ai_update";
+    let request_json = serde_json::to_value(&response_data)
+        .map_err(AppError::internal("Failed to serialize request JSON"))?;
+    debug!(request = %redact(&request_json), "Sending request to AI update");
 
-    let mut ai_response = client
-        .post(api_url)
-        .json(&response_data)
-        .send()
-        .await
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to send request".to_string()))?
-        .json::<serde_json::Value>()
+    // 5. Send the request to the AI recommender
+    let mut ai_response = recommender
+        .update(&request_json)
         .await
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to parse response".to_string()))?;
+        .map_err(|err| AppError::Upstream(format!("AI service error: {}", err)))?;
 
-    // 9. Rename `user_id` to `user_line_id` in the AI response
-    if let Some(user_id) = ai_response.get("user_id").cloned() {
-        ai_response.as_object_mut().unwrap().remove("user_id");
-        ai_response.as_object_mut().unwrap().insert("user_line_id".to_string(), user_id);
+    // 6. Identify the user by LINE id in the AI response, whatever the recommender echoed back
+    if let Some(object) = ai_response.as_object_mut() {
+        set_response_identity(object, user_line_id);
     }
 
-    // 10. Return the modified AI response
-    Ok(Json(ai_response))
+    // 7. Return the modified AI response
+    Ok(ai_response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::MAX_PLAN_DAYS;
+
+    #[test]
+    fn create_payload_defaults_to_append_without_start_date() {
+        let payload: CreateMealPlanPayload =
+            serde_json::from_value(json!({ "mealplans": [] })).unwrap();
+        assert_eq!(payload.overwrite, OverwritePolicy::Append);
+        assert!(payload.start_date.is_none());
+    }
+
+    #[test]
+    fn overwrite_policy_parses_lowercase_names() {
+        for (name, policy) in [
+            ("append", OverwritePolicy::Append),
+            ("replace", OverwritePolicy::Replace),
+            ("fail", OverwritePolicy::Fail),
+        ] {
+            assert_eq!(serde_json::from_value::<OverwritePolicy>(json!(name)).unwrap(), policy);
+        }
+        assert!(serde_json::from_value::<OverwritePolicy>(json!("Replace")).is_err());
+    }
+
+    #[test]
+    fn delete_payload_defaults_to_delete_mode() {
+        let payload: DeleteMealPlanPayload =
+            serde_json::from_value(json!({ "date": "2026-10-18" })).unwrap();
+        assert_eq!(payload.mode, DeleteMode::Delete);
+
+        let payload: DeleteMealPlanPayload = serde_json::from_value(json!({
+            "start_date": "2026-10-18",
+            "end_date": "2026-10-24",
+            "mode": "archive",
+        }))
+        .unwrap();
+        assert_eq!(payload.mode, DeleteMode::Archive);
+    }
+
+    #[test]
+    fn generate_request_only_persists_when_asked() {
+        let payload: MealPlanRequest =
+            serde_json::from_value(json!({ "data": { "days": 7 } })).unwrap();
+        assert_eq!(payload.data.u_id, LineUserId::default()); // Filled in from the ID token
+        assert_eq!(payload.data.generator, Generator::Auto);
+        assert!(!payload.data.persist);
+        assert_eq!(payload.data.overwrite, OverwritePolicy::Append);
+    }
+
+    #[test]
+    fn update_requests_are_limited_in_days() {
+        let request = |days, mealplan_days| UpdateMealPlanRequest {
+            user_line_id: LineUserId("U1".to_string()),
+            days,
+            mealplans: vec![Vec::new(); mealplan_days],
+        };
+        assert!(request(7, 7).check_days().is_ok());
+        assert!(request(0, 7).check_days().is_err());
+        assert!(request(7, MAX_PLAN_DAYS as usize + 1).check_days().is_err());
+    }
+
+    #[test]
+    fn recipes_keep_their_meal_time_or_take_it_from_their_position() {
+        let recipe: Recipe = serde_json::from_value(json!({ "recipe_id": 3 })).unwrap();
+        assert_eq!(recipe.meal_time, None);
+        assert_eq!((0..6).map(meal_time_for).collect::<Vec<_>>(), vec![1, 2, 3, 4, 4, 4]);
+    }
 }
@@ -1,4 +1,9 @@
 pub mod ingredient;
 pub mod recipe;
 pub mod mealplan;
-pub mod medicine;
\ No newline at end of file
+pub mod template;
+pub mod job;
+pub mod medicine;
+pub mod admin;
+pub mod monitoring;
+pub mod v2;
\ No newline at end of file
@@ -0,0 +1,177 @@
+use crate::db::{pending_migrations, Db};
+use crate::error::{AppError, AppQuery};
+use crate::monitoring::render;
+use crate::recommender::Recommender;
+use crate::openapi::Health;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use diesel::prelude::*;
+use metrics::gauge;
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use utoipa::{IntoParams, ToSchema};
+
+/// Longest the database check may take before the instance counts as not ready.
+const READY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReadinessQuery {
+    #[serde(default)]
+    pub recommender: bool, // Also call the AI recommender; reported but never fails the probe
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Check {
+    pub status: &'static str, // "ok", "pending", "error" or "skipped"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn ok(started: Instant) -> Self {
+        Check { status: "ok", latency_ms: Some(started.elapsed().as_millis()), error: None }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Check { status: "error", latency_ms: None, error: Some(message.into()) }
+    }
+
+    fn pending(started: Instant, message: impl Into<String>) -> Self {
+        Check { status: "pending", latency_ms: Some(started.elapsed().as_millis()), error: Some(message.into()) }
+    }
+
+    fn skipped() -> Self {
+        Check { status: "skipped", latency_ms: None, error: None }
+    }
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ReadinessChecks {
+    pub database: Check,
+    pub migrations: Check,
+    pub recommender: Check,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ReadinessResponse {
+    pub status: &'static str, // "ok" or "error"
+    pub checks: ReadinessChecks,
+    pub pending_migrations: Vec<String>, // Versions not applied yet; empty when the database could not be asked
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "monitoring",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain; version=0.0.4"),
+    ),
+)]
+#[axum::debug_handler]
+pub async fn get_metrics(
+    Extension(handle): Extension<PrometheusHandle>,
+    Extension(db): Extension<Db>,
+) -> impl IntoResponse {
+    let body = render(&handle, db.pool());
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")], body)
+}
+
+/// Liveness: the process is up and answering; touches nothing else.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "monitoring",
+    responses(
+        (status = 200, description = "The process is up", body = Health),
+    ),
+)]
+#[axum::debug_handler]
+pub async fn get_health() -> Json<Health> {
+    Json(Health {
+        status: "ok",
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+/// Readiness: 503 unless a pooled connection answers `SELECT 1`. Pending migrations
+/// are listed, and counted in `db_pending_migrations`, but do not fail the probe:
+/// the code that needs them fails on its own, and an instance should not be taken
+/// out of rotation while `kidney-admin migrate` runs.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "monitoring",
+    params(ReadinessQuery),
+    responses(
+        (status = 200, description = "Ready for traffic; `pending_migrations` lists migrations still to apply", body = ReadinessResponse),
+        (status = 503, description = "Not ready; `checks` says why", body = ReadinessResponse),
+    ),
+)]
+#[axum::debug_handler]
+pub async fn get_readiness(
+    AppQuery(query): AppQuery<ReadinessQuery>,
+    Extension(db): Extension<Db>,
+    Extension(recommender): Extension<Arc<dyn Recommender>>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    // 1. Check out a connection, run a query and compare the applied migrations
+    let started = Instant::now();
+    let database = tokio::time::timeout(
+        READY_TIMEOUT,
+        db.run(|conn| {
+            diesel::sql_query("SELECT 1")
+                .execute(conn)
+                .map_err(AppError::internal("Readiness query failed"))?;
+            Ok(pending_migrations(conn).map_err(|err| format!("Failed to read migrations: {}", err)))
+        }),
+    )
+    .await;
+
+    let mut pending_migrations = Vec::new();
+    let (database, migrations) = match database {
+        Ok(Ok(Ok(pending))) => {
+            gauge!("db_pending_migrations").set(pending.len() as f64);
+            let migrations = if pending.is_empty() {
+                Check::ok(started)
+            } else {
+                Check::pending(started, format!("not applied: {}", pending.join(", ")))
+            };
+            pending_migrations = pending;
+            (Check::ok(started), migrations)
+        }
+        Ok(Ok(Err(err))) => (Check::ok(started), Check::error(err)),
+        Ok(Err(err)) => (Check::error(err.to_string()), Check::skipped()),
+        Err(_) => (
+            Check::error(format!("no answer within {}s", READY_TIMEOUT.as_secs())),
+            Check::skipped(),
+        ),
+    };
+
+    // 2. Optionally reach the recommender; the AI routes fall back to the built-in planner without it
+    let recommender = if query.recommender {
+        let started = Instant::now();
+        match recommender.ping().await {
+            Ok(()) => Check::ok(started),
+            Err(err) => Check::error(err.to_string()),
+        }
+    } else {
+        Check::skipped()
+    };
+
+    let ready = database.status == "ok";
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (
+        status,
+        Json(ReadinessResponse {
+            status: if ready { "ok" } else { "error" },
+            checks: ReadinessChecks { database, migrations, recommender },
+            pending_migrations,
+        }),
+    )
+}
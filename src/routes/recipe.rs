@@ -1,13 +1,17 @@
-use axum::{Extension, Json, extract::Path};
-use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
+use axum::{Extension, Json};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use serde::Deserialize;
 use std::f64;
 use std::sync::Arc;
-use serde_json::json;
-use crate::schema::recipes::dsl::*;
+use crate::catalog::RecipeCatalog;
+use crate::db::Db;
+use crate::error::{AppError, AppJson, AppPath, AppResult};
+use crate::repo::RecipeRepo;
+use crate::error::ErrorBody;
+use crate::openapi::AdminErrors;
+use utoipa::ToSchema;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct UpdateRecipe {
     pub recipe_name: Option<String>,
     pub recipe_method: Option<Vec<Option<String>>>,
@@ -18,58 +22,89 @@ pub struct UpdateRecipe {
     pub dish_type: Option<Vec<Option<String>>>,
 }
 
-pub type DbPool = Pool<ConnectionManager<PgConnection>>;
-
+#[utoipa::path(
+    patch,
+    path = "/update_recipe/{r_id}",
+    tag = "v1",
+    params(("r_id" = i32, Path, description = "Recipe id")),
+    request_body = UpdateRecipe,
+    responses(
+        (status = 200, description = "Recipe updated", body = String),
+        (status = 404, description = "Recipe not found", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
 #[axum::debug_handler]
 pub async fn update_recipe(
-    Path(r_id): Path<i32>,
-    Extension(db_pool): Extension<Arc<DbPool>>,
-    Json(payload): Json<UpdateRecipe>,
-) -> Result<Json<String>, Json<serde_json::Value>> {
-    let mut conn = db_pool.get().map_err(|_| {
-        Json(json!({ "error": "Failed to connect to the database" }))
-    })?;
+    AppPath(r_id): AppPath<i32>,
+    Extension(db): Extension<Db>,
+    Extension(catalog): Extension<Arc<RecipeCatalog>>,
+    AppJson(payload): AppJson<UpdateRecipe>,
+) -> AppResult<Json<String>> {
+    let nothing_to_update = payload.recipe_name.is_none()
+        && payload.recipe_method.is_none()
+        && payload.calories.is_none()
+        && payload.calories_unit.is_none()
+        && payload.recipe_img_link.is_none()
+        && payload.food_category.is_none()
+        && payload.dish_type.is_none();
+    if nothing_to_update {
+        return Err(AppError::bad_request("No fields to update"));
+    }
 
-    let affected_rows = diesel::update(recipes.filter(recipe_id.eq(r_id)))
-        .set((
-            payload.recipe_name.map(|name| recipe_name.eq(name)),
-            payload.recipe_method.map(|method| recipe_method.eq(method)),
-            payload.calories.map(|calories_value| calories.eq(calories_value)),
-            payload.calories_unit.map(|unit| calories_unit.eq(unit)),
-            payload.recipe_img_link.map(|img_link| recipe_img_link.eq(img_link)),
-            payload.food_category.map(|category| food_category.eq(category)),
-            payload.dish_type.map(|dish| dish_type.eq(dish)),
-        ))
-        .execute(&mut conn)
-        .map_err(|_| {
-            Json(json!({ "error": "Failed to execute the update query" }))
-        })?;
+    let affected_rows = db
+        .run(move |conn| {
+            conn.update_recipe(r_id, payload)
+                .map_err(AppError::internal("Failed to execute the update query"))
+        })
+        .await?;
 
     if affected_rows == 0 {
-        return Err(Json(json!({ "error": "Recipe not found" })));
+        return Err(AppError::not_found("Recipe not found"));
     }
 
+    catalog.invalidate();
+
     Ok(Json("Recipe updated successfully".to_string()))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/delete_recipe/{r_id}",
+    tag = "v1",
+    params(("r_id" = i32, Path, description = "Recipe id")),
+    responses(
+        (status = 200, description = "Recipe deleted", body = String),
+        (status = 404, description = "Recipe not found", body = ErrorBody),
+        (status = 409, description = "A meal plan or template still uses the recipe", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
 #[axum::debug_handler]
 pub async fn delete_recipe(
-    Path(r_id): Path<i32>,
-    Extension(db_pool): Extension<Arc<DbPool>>,
-) -> Result<Json<String>, Json<serde_json::Value>> {
-    let mut conn = db_pool.get().map_err(|_| {
-        Json(json!({ "error": "Failed to connect to the database" }))
-    })?;
-
-    let affected_rows = diesel::delete(recipes.filter(recipe_id.eq(r_id)))
-        .execute(&mut conn)
-        .map_err(|_| {
-            Json(json!({ "error": "Failed to execute the delete query" }))
-        })?;
+    AppPath(r_id): AppPath<i32>,
+    Extension(db): Extension<Db>,
+    Extension(catalog): Extension<Arc<RecipeCatalog>>,
+) -> AppResult<Json<String>> {
+    let affected_rows = db
+        .run(move |conn| {
+            conn.delete_recipe(r_id).map_err(|err| match err {
+                DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => AppError::Conflict(
+                    "Recipe is used by a meal plan or template; remove it from them first".to_string(),
+                    None,
+                ),
+                err => AppError::internal("Failed to execute the delete query")(err),
+            })
+        })
+        .await?;
 
     if affected_rows == 0 {
-        return Err(Json(json!({ "error": "Recipe not found" })));
+        return Err(AppError::not_found("Recipe not found"));
     }
 
+    catalog.invalidate();
+
     Ok(Json("Recipe deleted successfully".to_string()))
 }
\ No newline at end of file
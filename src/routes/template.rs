@@ -0,0 +1,312 @@
+use crate::auth::AdminUser;
+use crate::db::Db;
+use crate::error::{parse_date, AppError, AppJson, AppPath, AppResult, ErrorBody};
+use crate::identity::LineUserId;
+use crate::line_auth::LineUser;
+use crate::routes::mealplan::{OverwritePolicy, Recipe};
+use crate::repo::{MealPlanRepo, TemplateRepo};
+use crate::service::{apply_template, check_days, create_template, insert_meal_plan_days, load_user_days, require_user};
+use crate::openapi::{AdminErrors, PublicErrors, StatusMessage, TemplateApplied, TemplateSaved, UserErrors};
+use axum::{Extension, Json};
+use chrono::NaiveDateTime;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use utoipa::ToSchema;
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CreateTemplatePayload {
+    pub name: String,
+    pub created_by: Option<String>, // Defaults to the signed-in admin's email
+    pub mealplans: Vec<Vec<Recipe>>, // Same 2D layout as create_meal_plan, one inner vec per day
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct SaveAsTemplatePayload {
+    pub name: String,
+    pub created_by: Option<String>, // Defaults to the signed-in admin's email
+    pub user_line_id: LineUserId,
+    pub start_date: String,
+    pub days: i32,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ApplyTemplatePayload {
+    pub template_id: i32,
+    pub start_date: String,
+    #[serde(default)]
+    pub overwrite: OverwritePolicy,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CloneMealPlanPayload {
+    pub from_date: String,
+    pub to_date: String,
+    pub days: Option<i32>, // Defaults to a week
+    #[serde(default)]
+    pub overwrite: OverwritePolicy,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct TemplateEntry {
+    pub template_id: i32,
+    pub name: String,
+    pub created_by: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub mealplans: Vec<Vec<Recipe>>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/create_meal_plan_template",
+    tag = "v1",
+    request_body = CreateTemplatePayload,
+    responses(
+        (status = 200, description = "Template created", body = TemplateSaved),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn create_meal_plan_template(
+    admin: AdminUser,
+    Extension(db): Extension<Db>,
+    AppJson(mut payload): AppJson<CreateTemplatePayload>,
+) -> AppResult<Json<TemplateSaved>> {
+    payload.created_by.get_or_insert(admin.email);
+
+    db.run(move |conn| {
+        let template_id = create_template(conn, &payload.name, payload.created_by.as_deref(), &payload.mealplans)?;
+
+        info!(template_id, name = %payload.name, "Created meal plan template");
+
+        Ok(TemplateSaved {
+            status: "success",
+            message: "Meal plan template created successfully",
+            template_id,
+        })
+    })
+    .await
+    .map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/save_meal_plan_as_template",
+    tag = "v1",
+    request_body = SaveAsTemplatePayload,
+    responses(
+        (status = 200, description = "Template created", body = TemplateSaved),
+        (status = 404, description = "User or meal plans not found", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn save_meal_plan_as_template(
+    admin: AdminUser,
+    Extension(db): Extension<Db>,
+    AppJson(mut payload): AppJson<SaveAsTemplatePayload>,
+) -> AppResult<Json<TemplateSaved>> {
+    payload.created_by.get_or_insert(admin.email);
+    check_days(payload.days)?;
+
+    db.run(move |conn| {
+        let user_id = require_user(conn, &payload.user_line_id)?;
+        let start_date = parse_date(&payload.start_date)?;
+        let mealplans = load_user_days(conn, user_id, start_date, payload.days as usize)?;
+
+        let template_id = create_template(conn, &payload.name, payload.created_by.as_deref(), &mealplans)?;
+
+        Ok(TemplateSaved {
+            status: "success",
+            message: "Meal plan template created successfully",
+            template_id,
+        })
+    })
+    .await
+    .map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/meal_plan_templates",
+    tag = "v1",
+    responses(
+        (status = 200, description = "All templates with their days", body = Vec<TemplateEntry>),
+        PublicErrors,
+    ),
+)]
+#[axum::debug_handler]
+pub async fn get_meal_plan_templates(
+    Extension(db): Extension<Db>,
+) -> AppResult<Json<Vec<TemplateEntry>>> {
+    db.run(|conn| {
+        conn.list_templates()
+            .map_err(AppError::internal("Error fetching meal plan templates"))
+    })
+    .await
+    .map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/apply_meal_plan_template",
+    tag = "v1",
+    request_body = ApplyTemplatePayload,
+    responses(
+        (status = 200, description = "Template copied into the user's meal plans", body = TemplateApplied),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn apply_meal_plan_template(
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<ApplyTemplatePayload>,
+) -> AppResult<Json<TemplateApplied>> {
+    db.run(move |conn| {
+        let user_id = require_user(conn, &line_id)?;
+        let start_date = parse_date(&payload.start_date)?;
+
+        let days = apply_template(conn, user_id, payload.template_id, start_date, payload.overwrite)?;
+
+        info!(template_id = payload.template_id, %user_id, %start_date, "Applied meal plan template");
+        counter!("meal_plans_created_total", "source" => "template").increment(1);
+        counter!("meal_plan_days_created_total", "source" => "template").increment(days as u64);
+
+        Ok(TemplateApplied {
+            status: "success",
+            message: "Meal plan template applied successfully",
+            start_date: start_date.format("%Y-%m-%d").to_string(),
+            days,
+        })
+    })
+    .await
+    .map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/clone_meal_plan",
+    tag = "v1",
+    request_body = CloneMealPlanPayload,
+    responses(
+        (status = 200, description = "Meal plans copied", body = StatusMessage),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn clone_meal_plan(
+    LineUser(line_id): LineUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CloneMealPlanPayload>,
+) -> AppResult<Json<StatusMessage>> {
+    let days = payload.days.unwrap_or(7);
+    check_days(days)?;
+
+    db.run(move |conn| {
+        let user_id = require_user(conn, &line_id)?;
+        let from_date = parse_date(&payload.from_date)?;
+        let to_date = parse_date(&payload.to_date)?;
+
+        let mealplans = load_user_days(conn, user_id, from_date, days as usize)?;
+        insert_meal_plan_days(conn, user_id, to_date, &mealplans, payload.overwrite)?;
+
+        info!(days, %user_id, %from_date, %to_date, "Cloned meal plans");
+        counter!("meal_plans_created_total", "source" => "clone").increment(1);
+        counter!("meal_plan_days_created_total", "source" => "clone").increment(mealplans.len() as u64);
+
+        Ok(StatusMessage::success("Meal plan cloned successfully"))
+    })
+    .await
+    .map(Json)
+}
+
+/// Staff version of `apply_meal_plan_template`, for the user in the path instead of the signed-in one.
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_line_id}/apply_meal_plan_template",
+    tag = "v1",
+    params(("user_line_id" = String, Path, description = "LINE id of the user whose meal plans are written")),
+    request_body = ApplyTemplatePayload,
+    responses(
+        (status = 200, description = "Template copied into the user's meal plans", body = TemplateApplied),
+        (status = 404, description = "User or template not found", body = ErrorBody),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn apply_meal_plan_template_for_user(
+    admin: AdminUser,
+    AppPath(user_line_id): AppPath<LineUserId>,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<ApplyTemplatePayload>,
+) -> AppResult<Json<TemplateApplied>> {
+    info!(admin = %admin.email, %user_line_id, template_id = payload.template_id, "Applying meal plan template for a user");
+    apply_meal_plan_template(LineUser(user_line_id), Extension(db), AppJson(payload)).await
+}
+
+/// Staff version of `clone_meal_plan`, for the user in the path instead of the signed-in one.
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_line_id}/clone_meal_plan",
+    tag = "v1",
+    params(("user_line_id" = String, Path, description = "LINE id of the user whose meal plans are copied")),
+    request_body = CloneMealPlanPayload,
+    responses(
+        (status = 200, description = "Meal plans copied", body = StatusMessage),
+        (status = 404, description = "User or meal plans not found", body = ErrorBody),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn clone_meal_plan_for_user(
+    admin: AdminUser,
+    AppPath(user_line_id): AppPath<LineUserId>,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CloneMealPlanPayload>,
+) -> AppResult<Json<StatusMessage>> {
+    info!(admin = %admin.email, %user_line_id, "Cloning meal plans for a user");
+    clone_meal_plan(LineUser(user_line_id), Extension(db), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/delete_meal_plan_template/{t_id}",
+    tag = "v1",
+    params(("t_id" = i32, Path, description = "Template id")),
+    responses(
+        (status = 200, description = "Template deleted", body = StatusMessage),
+        (status = 404, description = "Template not found", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn delete_meal_plan_template(
+    AppPath(t_id): AppPath<i32>,
+    Extension(db): Extension<Db>,
+) -> AppResult<Json<StatusMessage>> {
+    db.run(move |conn| {
+        let affected_rows = conn
+            .atomically(|conn| conn.delete_template(t_id))
+            .map_err(AppError::internal("Failed to delete meal plan template"))?;
+
+        if affected_rows == 0 {
+            return Err(AppError::not_found("Meal plan template not found"));
+        }
+
+        Ok(StatusMessage::success("Meal plan template deleted successfully"))
+    })
+    .await
+    .map(Json)
+}
@@ -0,0 +1,701 @@
+//! Resource-oriented routes, nested under `/api/v2`.
+//!
+//! Reads are `GET` with query parameters, creates answer `201 Created`, and paths
+//! name resources instead of actions. The handlers adapt requests to the v1
+//! handlers, so both versions behave the same; the v1 routes stay as aliases for
+//! existing clients.
+//!
+//! `{user}` is `me` or the signed-in user's own LINE id; the user still comes
+//! from the LINE ID token, and any other id is refused.
+//!
+//! | v2 | v1 |
+//! | --- | --- |
+//! | `GET /users/{user}/meal-plans?date=&from=&to=&include_archived=` | `POST /get_meal_plan` |
+//! | `POST /users/{user}/meal-plans` | `POST /create_meal_plan` |
+//! | `DELETE /users/{user}/meal-plans?date=&from=&to=&mode=` | `DELETE /delete_meal_plan` |
+//! | `PUT /users/{user}/meal-plans/{date}` | `PATCH /edit_meal_plan` |
+//! | `POST /users/{user}/meal-plans/clone` | `POST /clone_meal_plan` |
+//! | `POST /users/{user}/meal-plans/from-template` | `POST /apply_meal_plan_template` |
+//! | `POST /users/{user}/meal-plans/generate` | `POST /ai_meal_plan` (without the `data` wrapper) |
+//! | `POST /users/{user}/meal-plans/regenerate` | `POST /update_meal_plan` |
+//! | `POST /users/{user}/meal-plan-jobs/generate`, `.../regenerate` | `POST /ai_meal_plan_jobs`, `/update_meal_plan_jobs` |
+//! | `GET /users/{user}/meal-plan-jobs/{job_id}?wait=` | `GET /meal_plan_jobs/{job_id}` |
+//! | `PATCH /meal-plan-entries/{id}` | `PATCH /user_already_eat` |
+//! | `GET /ingredients`, `POST /ingredients` | `GET /ingredients`, `POST /create_ingredient` |
+//! | `PATCH /recipes/{id}`, `DELETE /recipes/{id}` | `PATCH /update_recipe/{r_id}`, `DELETE /delete_recipe/{r_id}` |
+//! | `GET /meal-plan-templates`, `POST /meal-plan-templates` | `GET /meal_plan_templates`, `POST /create_meal_plan_template` |
+//! | `POST /meal-plan-templates/from-meal-plan` | `POST /save_meal_plan_as_template` |
+//! | `DELETE /meal-plan-templates/{id}` | `DELETE /delete_meal_plan_template/{t_id}` |
+//! | `POST /admin/users/{user_line_id}/meal-plans/clone` | `POST /admin/users/{user_line_id}/clone_meal_plan` |
+//! | `POST /admin/users/{user_line_id}/meal-plan-templates/{template_id}/apply` | `POST /admin/users/{user_line_id}/apply_meal_plan_template` |
+//! | other `/admin/...` routes | the same paths |
+
+use crate::auth::{require_role, AdminUser, Role};
+use crate::catalog::RecipeCatalog;
+use crate::db::Db;
+use crate::error::{AppError, AppJson, AppPath, AppQuery, AppResult, ErrorBody};
+use crate::identity::LineUserId;
+use crate::jobs::JobQueue;
+use crate::line_auth::LineUser;
+use crate::recommender::Recommender;
+use crate::routes::admin::{
+    admin_login, create_admin, current_admin, delete_admin, export_recipe_file, get_admins, import_recipe_file,
+};
+use crate::routes::ingredient::{create_ingredient, get_ingredients, CreateIngredientPayload};
+use crate::routes::job::{get_meal_plan_job, submit_ai_meal_plan_job, submit_update_meal_plan_job, JobStatusQuery, JobStatusResponse};
+use crate::routes::mealplan::{
+    ai_meal_plan, create_meal_plan, delete_meal_plan, edit_meal_plan, get_meal_plan, update_meal_plan,
+    user_already_eat, CreateMealPlanPayload, DeleteMealPlanPayload, DeleteMode, EditMealPlanPayload,
+    GetMealPlanRequest, GetMealPlanResponse, MealPlanRequest, MealPlanRequestData, OverwritePolicy, Recipe, UpdateMealPlanRequest,
+    UserAlreadyEatPayload,
+};
+use crate::routes::recipe::{delete_recipe, update_recipe};
+use crate::routes::template::{
+    apply_meal_plan_template, apply_meal_plan_template_for_user, clone_meal_plan, clone_meal_plan_for_user, create_meal_plan_template, delete_meal_plan_template, get_meal_plan_templates, save_meal_plan_as_template, ApplyTemplatePayload, CloneMealPlanPayload,
+    CreateTemplatePayload, SaveAsTemplatePayload,
+};
+use crate::openapi::{AdminErrors, GeneratedMealPlan, JobAccepted, MealPlanSaved, MealPlansRemoved, StatusMessage, TemplateApplied, TemplateSaved, UserErrors};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, patch, post, put};
+use axum::{middleware, Extension, Json, Router};
+use serde::Deserialize;
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+type Created<T> = (StatusCode, Json<T>);
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListMealPlansQuery {
+    pub date: Option<String>,
+    pub from: Option<String>, // Inclusive range, YYYY-MM-DD
+    pub to: Option<String>,
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteMealPlansQuery {
+    pub date: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    #[serde(default)]
+    pub mode: DeleteMode,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ReplaceRecipesPayload {
+    pub recipes: Vec<Recipe>,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct MealPlanEntryPatch {
+    pub ischecked: bool,
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ApplyTemplateForUserPayload {
+    pub start_date: String,
+    #[serde(default)]
+    pub overwrite: OverwritePolicy,
+}
+
+/// The v2 routes, with the same role checks as their v1 counterparts.
+pub fn router() -> Router {
+    let catalog_routes = Router::new()
+        .route("/ingredients", post(create_ingredient_v2))
+        .route("/recipes/{id}", patch(update_recipe).delete(delete_recipe))
+        .route("/meal-plan-templates", post(create_meal_plan_template_v2))
+        .route("/meal-plan-templates/from-meal-plan", post(save_meal_plan_as_template_v2))
+        .route("/meal-plan-templates/{id}", delete(delete_meal_plan_template))
+        .route("/admin/users/{user_line_id}/meal-plans/clone", post(clone_meal_plans_for_user))
+        .route("/admin/users/{user_line_id}/meal-plan-templates/{template_id}/apply", post(apply_template_for_user))
+        .route("/admin/recipes/export", get(export_recipe_file))
+        .route("/admin/recipes/import", post(import_recipe_file))
+        .route("/admin/me", get(current_admin))
+        .route_layer(middleware::from_fn_with_state(Role::Dietitian, require_role));
+
+    let superadmin_routes = Router::new()
+        .route("/admin/admins", get(get_admins).post(create_admin))
+        .route("/admin/admins/{email}", delete(delete_admin))
+        .route_layer(middleware::from_fn_with_state(Role::Superadmin, require_role));
+
+    Router::new()
+        .route("/ingredients", get(get_ingredients))
+        .route("/meal-plan-templates", get(get_meal_plan_templates))
+        .route("/admin/login", post(admin_login))
+        .route(
+            "/users/{user}/meal-plans",
+            get(list_meal_plans).post(create_meal_plans).delete(delete_meal_plans),
+        )
+        .route("/users/{user}/meal-plans/{date}", put(replace_meal_plan_recipes))
+        .route("/users/{user}/meal-plans/clone", post(clone_meal_plans))
+        .route("/users/{user}/meal-plans/from-template", post(apply_template))
+        .route("/users/{user}/meal-plans/generate", post(generate_meal_plan))
+        .route("/users/{user}/meal-plans/regenerate", post(regenerate_meal_plan))
+        .route("/users/{user}/meal-plan-jobs/generate", post(submit_generate_job))
+        .route("/users/{user}/meal-plan-jobs/regenerate", post(submit_regenerate_job))
+        .route("/users/{user}/meal-plan-jobs/{job_id}", get(get_job))
+        .route("/meal-plan-entries/{id}", patch(update_meal_plan_entry))
+        .merge(catalog_routes)
+        .merge(superadmin_routes)
+}
+
+/// Checks the `{user}` path segment names the signed-in user.
+fn check_path_user(line_id: &LineUserId, user: &str) -> AppResult<()> {
+    if user == "me" || user == line_id.0 {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden("Only your own meal plans are accessible".to_string()))
+    }
+}
+
+fn created<T>(Json(body): Json<T>) -> Created<T> {
+    (StatusCode::CREATED, Json(body))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/users/{user}/meal-plans",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id"), ListMealPlansQuery),
+    responses(
+        (status = 200, description = "Meal plans with their recipes", body = GetMealPlanResponse),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn list_meal_plans(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    AppQuery(query): AppQuery<ListMealPlansQuery>,
+    Extension(db): Extension<Db>,
+) -> AppResult<Json<GetMealPlanResponse>> {
+    check_path_user(&line_id, &user)?;
+    let payload = GetMealPlanRequest {
+        date: query.date,
+        start_date: query.from,
+        end_date: query.to,
+        include_archived: query.include_archived,
+    };
+    get_meal_plan(LineUser(line_id), Extension(db), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/users/{user}/meal-plans",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id")),
+    request_body = CreateMealPlanPayload,
+    responses(
+        (status = 201, description = "Meal plan saved", body = MealPlanSaved),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn create_meal_plans(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CreateMealPlanPayload>,
+) -> AppResult<Created<MealPlanSaved>> {
+    check_path_user(&line_id, &user)?;
+    create_meal_plan(LineUser(line_id), Extension(db), AppJson(payload)).await.map(created)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v2/users/{user}/meal-plans",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id"), DeleteMealPlansQuery),
+    responses(
+        (status = 200, description = "Meal plan days deleted or archived", body = MealPlansRemoved),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn delete_meal_plans(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    AppQuery(query): AppQuery<DeleteMealPlansQuery>,
+    Extension(db): Extension<Db>,
+) -> AppResult<Json<MealPlansRemoved>> {
+    check_path_user(&line_id, &user)?;
+    let payload = DeleteMealPlanPayload {
+        date: query.date,
+        start_date: query.from,
+        end_date: query.to,
+        mode: query.mode,
+    };
+    delete_meal_plan(LineUser(line_id), Extension(db), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v2/users/{user}/meal-plans/{date}",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id"), ("date" = String, Path, description = "YYYY-MM-DD")),
+    request_body = ReplaceRecipesPayload,
+    responses(
+        (status = 200, description = "Recipes of the day replaced", body = StatusMessage),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn replace_meal_plan_recipes(
+    LineUser(line_id): LineUser,
+    AppPath((user, date)): AppPath<(String, String)>,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<ReplaceRecipesPayload>,
+) -> AppResult<Json<StatusMessage>> {
+    check_path_user(&line_id, &user)?;
+    let payload = EditMealPlanPayload { date, recipes: payload.recipes };
+    edit_meal_plan(LineUser(line_id), Extension(db), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/users/{user}/meal-plans/clone",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id")),
+    request_body = CloneMealPlanPayload,
+    responses(
+        (status = 201, description = "Meal plans copied", body = StatusMessage),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn clone_meal_plans(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CloneMealPlanPayload>,
+) -> AppResult<Created<StatusMessage>> {
+    check_path_user(&line_id, &user)?;
+    clone_meal_plan(LineUser(line_id), Extension(db), AppJson(payload)).await.map(created)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/users/{user}/meal-plans/from-template",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id")),
+    request_body = ApplyTemplatePayload,
+    responses(
+        (status = 201, description = "Template copied into the user's meal plans", body = TemplateApplied),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn apply_template(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<ApplyTemplatePayload>,
+) -> AppResult<Created<TemplateApplied>> {
+    check_path_user(&line_id, &user)?;
+    apply_meal_plan_template(LineUser(line_id), Extension(db), AppJson(payload)).await.map(created)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/users/{user}/meal-plans/generate",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id")),
+    request_body = MealPlanRequestData,
+    responses(
+        (status = 200, description = "Generated meal plan", body = GeneratedMealPlan),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        (status = 422, description = "`persist` was set but the plan failed validation; `details.issues` lists why", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn generate_meal_plan(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    Extension(db): Extension<Db>,
+    Extension(recommender): Extension<Arc<dyn Recommender>>,
+    Extension(catalog): Extension<Arc<RecipeCatalog>>,
+    AppJson(data): AppJson<MealPlanRequestData>,
+) -> AppResult<Json<serde_json::Value>> {
+    check_path_user(&line_id, &user)?;
+    let payload = MealPlanRequest { data };
+    ai_meal_plan(LineUser(line_id), Extension(db), Extension(recommender), Extension(catalog), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/users/{user}/meal-plans/regenerate",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id")),
+    request_body = UpdateMealPlanRequest,
+    responses(
+        (status = 200, description = "Reworked meal plan as returned by the recommender", body = Object),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn regenerate_meal_plan(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    Extension(db): Extension<Db>,
+    Extension(recommender): Extension<Arc<dyn Recommender>>,
+    Extension(catalog): Extension<Arc<RecipeCatalog>>,
+    AppJson(payload): AppJson<UpdateMealPlanRequest>,
+) -> AppResult<Json<serde_json::Value>> {
+    check_path_user(&line_id, &user)?;
+    update_meal_plan(LineUser(line_id), Extension(db), Extension(recommender), Extension(catalog), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/users/{user}/meal-plan-jobs/generate",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id")),
+    request_body = MealPlanRequestData,
+    responses(
+        (status = 202, description = "Job queued", body = JobAccepted),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        (status = 429, description = "The queue is full or the user has too many unfinished jobs", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn submit_generate_job(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    Extension(db): Extension<Db>,
+    Extension(queue): Extension<JobQueue>,
+    AppJson(data): AppJson<MealPlanRequestData>,
+) -> AppResult<(StatusCode, Json<JobAccepted>)> {
+    check_path_user(&line_id, &user)?;
+    let payload = MealPlanRequest { data };
+    submit_ai_meal_plan_job(LineUser(line_id), Extension(db), Extension(queue), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/users/{user}/meal-plan-jobs/regenerate",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id")),
+    request_body = UpdateMealPlanRequest,
+    responses(
+        (status = 202, description = "Job queued", body = JobAccepted),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        (status = 429, description = "The queue is full or the user has too many unfinished jobs", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn submit_regenerate_job(
+    LineUser(line_id): LineUser,
+    AppPath(user): AppPath<String>,
+    Extension(db): Extension<Db>,
+    Extension(queue): Extension<JobQueue>,
+    AppJson(payload): AppJson<UpdateMealPlanRequest>,
+) -> AppResult<(StatusCode, Json<JobAccepted>)> {
+    check_path_user(&line_id, &user)?;
+    submit_update_meal_plan_job(LineUser(line_id), Extension(db), Extension(queue), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/users/{user}/meal-plan-jobs/{job_id}",
+    tag = "v2",
+    params(("user" = String, Path, description = "`me` or the signed-in user's LINE id"), ("job_id" = Uuid, Path, description = "Job id"), JobStatusQuery),
+    responses(
+        (status = 200, description = "The job, finished or as it was when `wait` ran out", body = JobStatusResponse),
+        (status = 403, description = "`{user}` is not the signed-in user", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn get_job(
+    LineUser(line_id): LineUser,
+    AppPath((user, job_id)): AppPath<(String, Uuid)>,
+    AppQuery(query): AppQuery<JobStatusQuery>,
+    Extension(db): Extension<Db>,
+    Extension(queue): Extension<JobQueue>,
+) -> AppResult<Json<JobStatusResponse>> {
+    check_path_user(&line_id, &user)?;
+    get_meal_plan_job(LineUser(line_id), AppPath(job_id), AppQuery(query), Extension(db), Extension(queue)).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v2/meal-plan-entries/{id}",
+    tag = "v2",
+    params(("id" = i32, Path, description = "meal_plan_recipe_id of the entry")),
+    request_body = MealPlanEntryPatch,
+    responses(
+        (status = 200, description = "Meal plan recipe updated", body = StatusMessage),
+        (status = 403, description = "The recipe is on another user's meal plan", body = ErrorBody),
+        UserErrors,
+    ),
+    security(("line_id_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn update_meal_plan_entry(
+    line_user: LineUser,
+    AppPath(id): AppPath<i32>,
+    Extension(db): Extension<Db>,
+    AppJson(patch): AppJson<MealPlanEntryPatch>,
+) -> AppResult<Json<StatusMessage>> {
+    let payload = UserAlreadyEatPayload {
+        meal_plan_recipe_id: id,
+        ischecked: patch.ischecked,
+    };
+    user_already_eat(line_user, Extension(db), AppJson(payload)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/ingredients",
+    tag = "v2",
+    request_body = CreateIngredientPayload,
+    responses(
+        (status = 201, description = "Ingredient created", body = StatusMessage),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn create_ingredient_v2(
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CreateIngredientPayload>,
+) -> AppResult<Created<StatusMessage>> {
+    create_ingredient(Extension(db), AppJson(payload)).await.map(created)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/meal-plan-templates",
+    tag = "v2",
+    request_body = CreateTemplatePayload,
+    responses(
+        (status = 201, description = "Template created", body = TemplateSaved),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn create_meal_plan_template_v2(
+    admin: AdminUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CreateTemplatePayload>,
+) -> AppResult<Created<TemplateSaved>> {
+    create_meal_plan_template(admin, Extension(db), AppJson(payload)).await.map(created)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/meal-plan-templates/from-meal-plan",
+    tag = "v2",
+    request_body = SaveAsTemplatePayload,
+    responses(
+        (status = 201, description = "Template created", body = TemplateSaved),
+        (status = 404, description = "User or meal plans not found", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn save_meal_plan_as_template_v2(
+    admin: AdminUser,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<SaveAsTemplatePayload>,
+) -> AppResult<Created<TemplateSaved>> {
+    save_meal_plan_as_template(admin, Extension(db), AppJson(payload)).await.map(created)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/admin/users/{user_line_id}/meal-plans/clone",
+    tag = "v2",
+    params(("user_line_id" = String, Path, description = "LINE id of the user whose meal plans are copied")),
+    request_body = CloneMealPlanPayload,
+    responses(
+        (status = 201, description = "Meal plans copied", body = StatusMessage),
+        (status = 404, description = "User or meal plans not found", body = ErrorBody),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn clone_meal_plans_for_user(
+    admin: AdminUser,
+    AppPath(user_line_id): AppPath<LineUserId>,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<CloneMealPlanPayload>,
+) -> AppResult<Created<StatusMessage>> {
+    clone_meal_plan_for_user(admin, AppPath(user_line_id), Extension(db), AppJson(payload)).await.map(created)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v2/admin/users/{user_line_id}/meal-plan-templates/{template_id}/apply",
+    tag = "v2",
+    params(
+        ("user_line_id" = String, Path, description = "LINE id of the user whose meal plans are written"),
+        ("template_id" = i32, Path, description = "Template id"),
+    ),
+    request_body = ApplyTemplateForUserPayload,
+    responses(
+        (status = 201, description = "Template copied into the user's meal plans", body = TemplateApplied),
+        (status = 404, description = "User or template not found", body = ErrorBody),
+        (status = 409, description = "A meal plan already exists on one of the dates; `details.dates` lists them", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("admin_token" = [])),
+)]
+#[axum::debug_handler]
+pub async fn apply_template_for_user(
+    admin: AdminUser,
+    AppPath((user_line_id, template_id)): AppPath<(LineUserId, i32)>,
+    Extension(db): Extension<Db>,
+    AppJson(payload): AppJson<ApplyTemplateForUserPayload>,
+) -> AppResult<Created<TemplateApplied>> {
+    let payload = ApplyTemplatePayload { template_id, start_date: payload.start_date, overwrite: payload.overwrite };
+    apply_meal_plan_template_for_user(admin, AppPath(user_line_id), Extension(db), AppJson(payload)).await.map(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::JobConfig;
+    use crate::line_auth::{sign_test_token, JwksSource, LineAuth, LineAuthConfig, DEFAULT_LINE_ISSUER};
+    use crate::recommender::MockRecommender;
+    use crate::repo::memory::{MemoryDb, MemoryRepo};
+    use crate::service::MAX_PLAN_DAYS;
+    use axum::body::{to_bytes, Body};
+    use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+    use axum::http::{Method, Request};
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    const TEST_KEY: &[u8] = include_bytes!("../../dev/line-test-key.pem");
+    const TEST_JWKS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/dev/line-test-jwks.json");
+
+    fn line_config() -> LineAuthConfig {
+        LineAuthConfig {
+            channel_id: "local".to_string(),
+            issuer: DEFAULT_LINE_ISSUER.to_string(),
+            jwks: JwksSource::File(PathBuf::from(TEST_JWKS)),
+        }
+    }
+
+    /// The v1 routes the tests compare with and the v2 routes, as `main` mounts them.
+    /// Nothing may reach the database: its pool points at a closed port.
+    fn app() -> Router {
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(100))
+            .build_unchecked(ConnectionManager::new("postgres://nobody@127.0.0.1:1/unreachable"));
+        let recommender: Arc<dyn Recommender> = Arc::new(MockRecommender::new(json!({ "mealplans": [] })));
+        let catalog = Arc::new(RecipeCatalog::new(Duration::ZERO));
+        let jobs = JobConfig { workers: 1, max_attempts: 1, retention: Duration::from_secs(60), queue_capacity: 1, max_unfinished_per_user: 1 };
+        let queue = JobQueue::start(MemoryDb::new(MemoryRepo::new()), recommender.clone(), catalog.clone(), jobs);
+
+        Router::new()
+            .route("/ai_meal_plan", post(ai_meal_plan))
+            .route("/ai_meal_plan_jobs", post(submit_ai_meal_plan_job))
+            .nest("/api/v2", router())
+            .layer(Extension(Arc::new(LineAuth::new(line_config()).unwrap())))
+            .layer(Extension(Db::new(pool)))
+            .layer(Extension(recommender))
+            .layer(Extension(catalog))
+            .layer(Extension(queue))
+    }
+
+    /// Status and JSON body of `method uri` sent as LINE user `U1`.
+    async fn send(method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let token = sign_test_token(TEST_KEY, &line_config(), &LineUserId("U1".to_string()), Duration::from_secs(600)).unwrap();
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .header(CONTENT_TYPE, "application/json");
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+
+        let response = app().oneshot(request.body(body).unwrap()).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    #[test]
+    fn path_users_must_be_me_or_the_signed_in_user() {
+        let line_id = LineUserId("U1".to_string());
+
+        assert!(check_path_user(&line_id, "me").is_ok());
+        assert!(check_path_user(&line_id, "U1").is_ok());
+        for user in ["U2", "u1", "", "ME"] {
+            assert!(matches!(check_path_user(&line_id, user), Err(AppError::Forbidden(_))), "{:?}", user);
+        }
+    }
+
+    #[tokio::test]
+    async fn another_users_path_is_forbidden() {
+        let plan = json!({ "days": 3 });
+        let cases = [
+            (Method::GET, "/api/v2/users/U2/meal-plans", None),
+            (Method::DELETE, "/api/v2/users/U2/meal-plans?date=2030-01-01", None),
+            (Method::PUT, "/api/v2/users/U2/meal-plans/2030-01-01", Some(json!({ "recipes": [] }))),
+            (Method::POST, "/api/v2/users/U2/meal-plans/generate", Some(plan.clone())),
+            (Method::POST, "/api/v2/users/U2/meal-plan-jobs/generate", Some(plan)),
+            (Method::GET, "/api/v2/users/U2/meal-plan-jobs/00000000-0000-0000-0000-000000000000", None),
+        ];
+
+        for (method, uri, body) in cases {
+            let (status, body) = send(method.clone(), uri, body).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}: {}", method, uri, body);
+            assert_eq!(body["code"], "forbidden", "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn v2_routes_answer_invalid_requests_like_v1() {
+        for days in [0, MAX_PLAN_DAYS + 1] {
+            let data = json!({ "days": days });
+            let v1 = send(Method::POST, "/ai_meal_plan", Some(json!({ "data": data }))).await;
+            let v1_job = send(Method::POST, "/ai_meal_plan_jobs", Some(json!({ "data": data }))).await;
+            assert_eq!(v1.0, StatusCode::BAD_REQUEST, "{}", v1.1);
+            assert_eq!(v1_job, v1);
+
+            for user in ["me", "U1"] {
+                let generate = format!("/api/v2/users/{}/meal-plans/generate", user);
+                let job = format!("/api/v2/users/{}/meal-plan-jobs/generate", user);
+                assert_eq!(send(Method::POST, &generate, Some(data.clone())).await, v1, "{} days", days);
+                assert_eq!(send(Method::POST, &job, Some(data.clone())).await, v1, "{} days", days);
+            }
+        }
+    }
+}
@@ -3,7 +3,9 @@
 diesel::table! {
     admins (admin_email) {
         admin_email -> Text,
-        admin_password -> Uuid,
+        password_hash -> Nullable<Text>,
+        #[max_length = 20]
+        role -> Varchar,
     }
 }
 
@@ -47,6 +49,43 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    meal_plan_template_recipes (template_recipe_id) {
+        template_recipe_id -> Int4,
+        template_id -> Int4,
+        day_index -> Int4,
+        recipe_id -> Int4,
+        meal_time -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    meal_plan_templates (template_id) {
+        template_id -> Int4,
+        #[max_length = 100]
+        name -> Varchar,
+        created_by -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    meal_plan_jobs (job_id) {
+        job_id -> Uuid,
+        user_id -> Int4,
+        #[max_length = 20]
+        kind -> Varchar,
+        #[max_length = 20]
+        status -> Varchar,
+        request -> Jsonb,
+        result -> Nullable<Jsonb>,
+        error -> Nullable<Text>,
+        attempts -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     meal_plans (meal_plan_id) {
         meal_plan_id -> Int4,
@@ -54,6 +93,7 @@ diesel::table! {
         #[max_length = 100]
         name -> Varchar,
         date -> Date,
+        archived_at -> Nullable<Timestamp>,
     }
 }
 
@@ -204,6 +244,9 @@ diesel::table! {
 }
 
 diesel::joinable!(meal_plan_recipes -> meal_plans (meal_plan_id));
+diesel::joinable!(meal_plan_template_recipes -> meal_plan_templates (template_id));
+diesel::joinable!(meal_plan_template_recipes -> recipes (recipe_id));
+diesel::joinable!(meal_plan_jobs -> users (user_id));
 diesel::joinable!(meal_plans -> users (user_id));
 diesel::joinable!(recipes_ingredient_allergies -> ingredient_allergies (ingredient_allergy_id));
 diesel::joinable!(recipes_ingredient_allergies -> recipes (recipe_id));
@@ -230,7 +273,10 @@ diesel::allow_tables_to_appear_in_same_query!(
     food_condition_types,
     ingredient_allergies,
     ingredients,
+    meal_plan_jobs,
     meal_plan_recipes,
+    meal_plan_template_recipes,
+    meal_plan_templates,
     meal_plans,
     nutrients,
     recipes,
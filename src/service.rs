@@ -0,0 +1,564 @@
+//! Meal plan rules shared by the HTTP handlers, templates and background jobs.
+//!
+//! Everything here is written against the traits in [`crate::repo`], so it runs
+//! the same on a `PgConnection` inside [`crate::db::Db::run`] and on a
+//! [`crate::repo::memory::MemoryRepo`].
+
+use crate::catalog::{food_menus_for, CatalogRecipe, RecipeCatalog};
+use crate::error::{parse_date, AppError, AppResult};
+use crate::identity::{LineUserId, UserId};
+use crate::repo::{MealPlanFilter, MealPlanRepo, NutrientRepo, RecipeRepo, TemplateRepo, UserRepo, ACTIVE_MEAL_PLAN_INDEX};
+use crate::routes::mealplan::{FoodMenu, Nutrition, OverwritePolicy, Recipe};
+use chrono::NaiveDate;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::QueryResult;
+use metrics::counter;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// Most days one request may generate, copy or save as a template.
+pub const MAX_PLAN_DAYS: i32 = 31;
+
+/// Meal slots of a day: 1 breakfast, 2 lunch, 3 dinner, 4 snack.
+pub const MEAL_TIMES: std::ops::RangeInclusive<i32> = 1..=4;
+
+/// 400 unless `days` is between 1 and [`MAX_PLAN_DAYS`].
+pub fn check_days(days: i32) -> AppResult<()> {
+    if (1..=MAX_PLAN_DAYS).contains(&days) {
+        Ok(())
+    } else {
+        Err(AppError::bad_request(format!("days must be between 1 and {}", MAX_PLAN_DAYS)))
+    }
+}
+
+/// 400 if `mealplans` has more than [`MAX_PLAN_DAYS`] days.
+pub fn check_mealplan_days(mealplans: &[Vec<Recipe>]) -> AppResult<()> {
+    if mealplans.len() > MAX_PLAN_DAYS as usize {
+        return Err(AppError::bad_request(format!("mealplans may have at most {} days", MAX_PLAN_DAYS)));
+    }
+    Ok(())
+}
+
+/// Last date of `days` consecutive days from `start_date`; 400 if they run past the end of the calendar.
+pub fn last_day(start_date: NaiveDate, days: usize) -> AppResult<NaiveDate> {
+    start_date
+        .checked_add_signed(chrono::Duration::days(days.saturating_sub(1) as i64))
+        .ok_or_else(|| AppError::bad_request("The dates run past the end of the calendar"))
+}
+
+/// 400 if a recipe names a meal_time outside [`MEAL_TIMES`].
+pub fn check_meal_times(mealplans: &[Vec<Recipe>]) -> AppResult<()> {
+    let invalid = mealplans
+        .iter()
+        .flatten()
+        .filter_map(|recipe| recipe.meal_time)
+        .any(|meal_time| !MEAL_TIMES.contains(&meal_time));
+    if invalid {
+        return Err(AppError::bad_request("meal_time must be between 1 and 4"));
+    }
+    Ok(())
+}
+
+/// Internal id of the user, or 404 if the LINE id is unknown.
+pub fn require_user<R: UserRepo>(repo: &mut R, line_id: &LineUserId) -> AppResult<UserId> {
+    repo.find_user(line_id)
+        .map_err(AppError::internal("Failed to fetch user"))?
+        .ok_or_else(|| AppError::not_found("User not found"))
+}
+
+/// The day after the user's latest active meal plan, or today if they have none in the future.
+pub fn next_start_date<R: MealPlanRepo>(repo: &mut R, user_id: UserId) -> QueryResult<NaiveDate> {
+    let latest_date = repo.latest_active_date(user_id)?;
+
+    let today = chrono::Local::now().date_naive();
+    Ok(match latest_date {
+        Some(date) if date >= today => date + chrono::Duration::days(1), // Start from the next day if the latest date is in the future or today
+        _ => today, // Start from today if no meal plans exist or the latest date is in the past
+    })
+}
+
+/// The explicit start date from a request, otherwise the day after the latest meal plan.
+pub fn resolve_start_date<R: MealPlanRepo>(repo: &mut R, user_id: UserId, requested: Option<&str>) -> AppResult<NaiveDate> {
+    match requested {
+        Some(date_str) => parse_date(date_str),
+        None => next_start_date(repo, user_id).map_err(AppError::internal("Failed to fetch latest meal plan date")),
+    }
+}
+
+/// Returns the dates in `[start_date, start_date + days)` on which the user already has a meal plan.
+pub fn find_overlapping_dates<R: MealPlanRepo>(
+    repo: &mut R,
+    user_id: UserId,
+    start_date: NaiveDate,
+    days: usize,
+) -> QueryResult<Vec<NaiveDate>> {
+    if days == 0 {
+        return Ok(Vec::new());
+    }
+
+    let end_date = start_date
+        .checked_add_signed(chrono::Duration::days(days as i64 - 1))
+        .unwrap_or(NaiveDate::MAX);
+    repo.active_dates(user_id, start_date, end_date)
+}
+
+/// 409 naming the dates on which the user already has a meal plan.
+fn overlap_conflict(overlapping: &[NaiveDate]) -> AppError {
+    let dates: Vec<String> = overlapping.iter().map(|d| d.format("%Y-%m-%d").to_string()).collect();
+    AppError::Conflict(
+        "Meal plan already exists for the given dates".to_string(),
+        Some(json!({ "dates": dates })),
+    )
+}
+
+/// Maps a failed meal plan write to an `AppError`. Losing the race for a day to
+/// a concurrent request trips the unique index on active days, which is a 409.
+pub fn meal_plan_write_error(err: DieselError) -> AppError {
+    match &err {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info)
+            if info.constraint_name() == Some(ACTIVE_MEAL_PLAN_INDEX) =>
+        {
+            AppError::Conflict("Meal plan already exists for the given dates".to_string(), None)
+        }
+        _ => AppError::internal("Failed to create meal plan")(err),
+    }
+}
+
+/// Slot of the `index`-th recipe of a day: 1 to 4, with every later recipe in 4.
+pub fn meal_time_for(index: usize) -> i32 {
+    if index < 4 {
+        (index + 1) as i32 // 1, 2, 3, 4 for the first four recipes
+    } else {
+        4 // 4 for all subsequent recipes
+    }
+}
+
+/// Writes one meal plan per day starting at `start_date`, atomically.
+/// Days that already have a meal plan keep their row; `overwrite` decides whether
+/// the new recipes are appended to it or replace its recipes, or with
+/// [`OverwritePolicy::Fail`] whether the whole write is refused with a 409.
+/// The check runs in the same transaction as the writes.
+pub fn insert_meal_plan_days<R: MealPlanRepo>(
+    repo: &mut R,
+    user_id: UserId,
+    start_date: NaiveDate,
+    mealplans: &[Vec<Recipe>],
+    overwrite: OverwritePolicy,
+) -> AppResult<()> {
+    last_day(start_date, mealplans.len())?;
+
+    let overlapping = repo.atomically(|repo| {
+        if overwrite == OverwritePolicy::Fail {
+            let overlapping = find_overlapping_dates(repo, user_id, start_date, mealplans.len())?;
+            if !overlapping.is_empty() {
+                return Ok(overlapping); // Nothing has been written yet
+            }
+        }
+
+        for (day_index, day_mealplans) in mealplans.iter().enumerate() {
+            let meal_plan_date = start_date + chrono::Duration::days(day_index as i64);
+            debug!(date = %meal_plan_date, "Creating meal plan day");
+
+            let existing_meal_plan_id = repo
+                .active_meal_plan_ids(user_id, meal_plan_date, meal_plan_date)?
+                .into_iter()
+                .next();
+
+            let (meal_plan_id, existing_recipes) = match existing_meal_plan_id {
+                Some(meal_plan_id) if overwrite == OverwritePolicy::Replace => {
+                    repo.clear_recipes(meal_plan_id)?;
+                    debug!(meal_plan_id, "Replaced recipes of meal plan");
+                    (meal_plan_id, 0)
+                }
+                Some(meal_plan_id) => {
+                    let existing_recipes = repo.recipe_count(meal_plan_id)?;
+                    debug!(meal_plan_id, "Appending to meal plan");
+                    (meal_plan_id, existing_recipes)
+                }
+                None => {
+                    let meal_plan_name = format!("Meal Plan {}", meal_plan_date.format("%d/%m/%Y"));
+                    let meal_plan_id = repo.create_meal_plan(user_id, &meal_plan_name, meal_plan_date)?;
+                    debug!(meal_plan_id, "Created meal plan");
+                    (meal_plan_id, 0)
+                }
+            };
+
+            // Recipes without a meal_time continue the slots after any recipes already on the day
+            let recipes = day_mealplans.iter().filter_map(|recipe| Some((recipe.recipe_id?, recipe.meal_time)));
+            for (recipe_index, (recipe_id, meal_time)) in recipes.enumerate() {
+                let meal_time = meal_time.unwrap_or_else(|| meal_time_for(existing_recipes + recipe_index));
+                repo.add_recipe(meal_plan_id, recipe_id, meal_time)?;
+            }
+        }
+        Ok(Vec::new())
+    })
+    .map_err(meal_plan_write_error)?;
+
+    if !overlapping.is_empty() {
+        return Err(overlap_conflict(&overlapping));
+    }
+    Ok(())
+}
+
+/// Resolves the start date, enforces `overwrite` and writes the days; returns the start date used.
+/// `source` labels the meal plan counters: `create`, `ai` or `ai_job`.
+pub fn save_meal_plan<R: MealPlanRepo>(
+    repo: &mut R,
+    user_id: UserId,
+    requested_start: Option<&str>,
+    mealplans: &[Vec<Recipe>],
+    overwrite: OverwritePolicy,
+    source: &'static str,
+) -> AppResult<NaiveDate> {
+    check_mealplan_days(mealplans)?;
+    check_meal_times(mealplans)?;
+    let start_date = resolve_start_date(repo, user_id, requested_start)?;
+
+    info!(%user_id, %start_date, ?overwrite, days = mealplans.len(), "Saving meal plan");
+
+    insert_meal_plan_days(repo, user_id, start_date, mealplans, overwrite)?;
+    counter!("meal_plans_created_total", "source" => source).increment(1);
+    counter!("meal_plan_days_created_total", "source" => source).increment(mealplans.len() as u64);
+    Ok(start_date)
+}
+
+/// The user's meal plans for `days` days from `start_date` in the 2D recipe layout, with their meal times.
+/// Days without a meal plan stay empty so day offsets are preserved; 404 if every day is empty.
+pub fn load_user_days<R: MealPlanRepo>(
+    repo: &mut R,
+    user_id: UserId,
+    start_date: NaiveDate,
+    days: usize,
+) -> AppResult<Vec<Vec<Recipe>>> {
+    let end_date = last_day(start_date, days)?;
+    let filter = MealPlanFilter {
+        start_date: Some(start_date),
+        end_date: Some(end_date),
+        ..Default::default()
+    };
+    let entries = repo
+        .list_meal_plans(user_id, filter)
+        .map_err(AppError::internal("Error fetching meal plans"))?;
+
+    let mut mealplans = vec![Vec::new(); days];
+    for mut entry in entries {
+        // Same order as the day is shown in: by slot, recipes without one last
+        entry
+            .recipes
+            .sort_by_key(|recipe| (recipe.meal_time.is_none(), recipe.meal_time, recipe.meal_plan_recipe_id));
+        let day_index = (entry.date - start_date).num_days() as usize;
+        mealplans[day_index] = entry
+            .recipes
+            .into_iter()
+            .map(|recipe| Recipe { recipe_id: Some(recipe.recipe_id), meal_time: recipe.meal_time })
+            .collect();
+    }
+
+    if mealplans.iter().all(|day| day.is_empty()) {
+        return Err(AppError::not_found("Meal plan not found for the given dates"));
+    }
+    Ok(mealplans)
+}
+
+/// Stores a template and returns its id. Recipes without a meal_time are slotted by their position in the day.
+pub fn create_template<R: MealPlanRepo + TemplateRepo>(
+    repo: &mut R,
+    name: &str,
+    created_by: Option<&str>,
+    mealplans: &[Vec<Recipe>],
+) -> AppResult<i32> {
+    check_mealplan_days(mealplans)?;
+    check_meal_times(mealplans)?;
+
+    repo.atomically(|repo| {
+        let template_id = repo.create_template(name, created_by)?;
+        for (day_index, day_mealplans) in mealplans.iter().enumerate() {
+            let recipes = day_mealplans.iter().filter_map(|recipe| Some((recipe.recipe_id?, recipe.meal_time)));
+            for (recipe_index, (recipe_id, meal_time)) in recipes.enumerate() {
+                let meal_time = meal_time.unwrap_or_else(|| meal_time_for(recipe_index));
+                repo.add_template_recipe(template_id, day_index, recipe_id, meal_time)?;
+            }
+        }
+        Ok(template_id)
+    })
+    .map_err(AppError::internal("Failed to create meal plan template"))
+}
+
+/// Copies a template into the user's meal plans from `start_date` and returns how many days it has.
+pub fn apply_template<R: MealPlanRepo + TemplateRepo>(
+    repo: &mut R,
+    user_id: UserId,
+    template_id: i32,
+    start_date: NaiveDate,
+    overwrite: OverwritePolicy,
+) -> AppResult<usize> {
+    let mealplans = repo
+        .template_days(template_id)
+        .map_err(AppError::internal("Error fetching meal plan template"))?
+        .ok_or_else(|| AppError::not_found("Meal plan template not found"))?;
+
+    insert_meal_plan_days(repo, user_id, start_date, &mealplans, overwrite)?;
+    Ok(mealplans.len())
+}
+
+/// What the recommender needs to know about a user, for both generating and reworking a plan.
+pub struct RecommendationContext {
+    pub user_id: UserId,
+    pub catalog_recipes: Arc<Vec<CatalogRecipe>>,
+    /// The catalog without the recipes the user is allergic to.
+    pub food_menus: Vec<FoodMenu>,
+    pub nutrition_limit_per_day: Nutrition,
+}
+
+pub fn recommendation_context<R>(repo: &mut R, catalog: &RecipeCatalog, line_id: &LineUserId) -> AppResult<RecommendationContext>
+where
+    R: UserRepo + RecipeRepo + NutrientRepo,
+{
+    let user_id = require_user(repo, line_id)?;
+
+    let catalog_recipes = catalog
+        .recipes(repo)
+        .map_err(AppError::internal("Error fetching filtered recipes"))?;
+    let allergy_ids = repo
+        .allergy_ids(user_id)
+        .map_err(AppError::internal("Error fetching filtered recipes"))?;
+    let food_menus = food_menus_for(&catalog_recipes, &allergy_ids);
+
+    let nutrition_limit_per_day = repo
+        .daily_limits(user_id)
+        .map_err(AppError::internal("Error fetching nutrition limits"))?;
+
+    Ok(RecommendationContext {
+        user_id,
+        catalog_recipes,
+        food_menus,
+        nutrition_limit_per_day,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::memory::{MemoryRepo, MemoryUser};
+
+    const USER: UserId = UserId(1);
+
+    fn repo() -> MemoryRepo {
+        let mut repo = MemoryRepo::new();
+        repo.users.push(MemoryUser {
+            user_id: USER,
+            line_id: LineUserId("U1".to_string()),
+            allergy_ids: Default::default(),
+            limits: Nutrition::default(),
+        });
+        repo
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2030, 1, day).unwrap()
+    }
+
+    fn recipe(recipe_id: i32, meal_time: Option<i32>) -> Recipe {
+        Recipe { recipe_id: Some(recipe_id), meal_time }
+    }
+
+    fn catalog_recipe(recipe_id: i32) -> CatalogRecipe {
+        CatalogRecipe {
+            menu: FoodMenu {
+                name: format!("Recipe {}", recipe_id),
+                nutrition: Nutrition::default(),
+                recipe_id,
+                recipe_img_link: Vec::new(),
+            },
+            allergy_ids: Vec::new(),
+        }
+    }
+
+    /// (recipe_id, meal_time) of each recipe on the user's active day, in insertion order.
+    fn slots(repo: &MemoryRepo, day: NaiveDate) -> Vec<(i32, i32)> {
+        let plan = repo
+            .meal_plans
+            .iter()
+            .find(|plan| plan.date == day && plan.archived_at.is_none())
+            .expect("meal plan for the day");
+        repo.meal_plan_recipes
+            .iter()
+            .filter(|row| row.meal_plan_id == plan.meal_plan_id)
+            .map(|row| (row.recipe_id, row.meal_time))
+            .collect()
+    }
+
+    #[test]
+    fn days_must_be_within_the_maximum() {
+        assert!(check_days(1).is_ok());
+        assert!(check_days(MAX_PLAN_DAYS).is_ok());
+        for days in [0, -1, MAX_PLAN_DAYS + 1, i32::MAX] {
+            assert!(matches!(check_days(days), Err(AppError::BadRequest(_))), "{} days", days);
+        }
+    }
+
+    #[test]
+    fn saved_recipes_keep_an_explicit_meal_time() {
+        let mut repo = repo();
+        let mealplans = vec![vec![recipe(10, Some(2)), recipe(11, Some(3))]];
+
+        save_meal_plan(&mut repo, USER, Some("2030-01-01"), &mealplans, OverwritePolicy::Append, "create").unwrap();
+
+        assert_eq!(slots(&repo, date(1)), vec![(10, 2), (11, 3)]);
+    }
+
+    #[test]
+    fn recipes_without_a_meal_time_are_slotted_by_position() {
+        let mut repo = repo();
+        let mealplans = vec![vec![recipe(10, None), recipe(11, None), recipe(12, None), recipe(13, None), recipe(14, None)]];
+
+        save_meal_plan(&mut repo, USER, Some("2030-01-01"), &mealplans, OverwritePolicy::Append, "create").unwrap();
+
+        assert_eq!(slots(&repo, date(1)), vec![(10, 1), (11, 2), (12, 3), (13, 4), (14, 4)]);
+    }
+
+    #[test]
+    fn meal_times_outside_the_day_are_rejected() {
+        let mut repo = repo();
+        let mealplans = vec![vec![recipe(10, Some(5))]];
+
+        let result = save_meal_plan(&mut repo, USER, Some("2030-01-01"), &mealplans, OverwritePolicy::Append, "create");
+
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert!(repo.meal_plans.is_empty());
+    }
+
+    #[test]
+    fn plans_longer_than_the_maximum_are_rejected() {
+        let mut repo = repo();
+        let mealplans = vec![vec![recipe(10, None)]; MAX_PLAN_DAYS as usize + 1];
+
+        let result = save_meal_plan(&mut repo, USER, Some("2030-01-01"), &mealplans, OverwritePolicy::Append, "create");
+
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert!(repo.meal_plans.is_empty());
+    }
+
+    #[test]
+    fn append_adds_to_an_existing_day() {
+        let mut repo = repo();
+        save_meal_plan(&mut repo, USER, Some("2030-01-01"), &[vec![recipe(10, None)]], OverwritePolicy::Append, "create").unwrap();
+
+        let mealplans = vec![vec![recipe(11, None)], vec![recipe(12, None)]];
+        save_meal_plan(&mut repo, USER, Some("2030-01-01"), &mealplans, OverwritePolicy::Append, "create").unwrap();
+
+        assert_eq!(slots(&repo, date(1)), vec![(10, 1), (11, 2)]);
+        assert_eq!(slots(&repo, date(2)), vec![(12, 1)]);
+        assert_eq!(repo.meal_plans.len(), 2);
+    }
+
+    #[test]
+    fn replace_swaps_the_recipes_of_an_existing_day() {
+        let mut repo = repo();
+        let first = vec![vec![recipe(10, None), recipe(11, None)]];
+        save_meal_plan(&mut repo, USER, Some("2030-01-01"), &first, OverwritePolicy::Append, "create").unwrap();
+
+        save_meal_plan(&mut repo, USER, Some("2030-01-01"), &[vec![recipe(12, None)]], OverwritePolicy::Replace, "create").unwrap();
+
+        assert_eq!(slots(&repo, date(1)), vec![(12, 1)]);
+        assert_eq!(repo.meal_plans.len(), 1);
+    }
+
+    #[test]
+    fn fail_refuses_overlapping_days_without_writing() {
+        let mut repo = repo();
+        save_meal_plan(&mut repo, USER, Some("2030-01-02"), &[vec![recipe(10, None)]], OverwritePolicy::Append, "create").unwrap();
+
+        let mealplans = vec![vec![recipe(11, None)], vec![recipe(12, None)], vec![recipe(13, None)]];
+        let result = save_meal_plan(&mut repo, USER, Some("2030-01-01"), &mealplans, OverwritePolicy::Fail, "create");
+
+        let Err(AppError::Conflict(_, Some(details))) = result else {
+            panic!("expected a conflict, got {:?}", result);
+        };
+        assert_eq!(details, json!({ "dates": ["2030-01-02"] }));
+        assert_eq!(repo.meal_plans.len(), 1);
+        assert_eq!(slots(&repo, date(2)), vec![(10, 1)]);
+    }
+
+    #[test]
+    fn fail_writes_free_days() {
+        let mut repo = repo();
+        save_meal_plan(&mut repo, USER, Some("2030-01-01"), &[vec![recipe(10, None)]], OverwritePolicy::Append, "create").unwrap();
+
+        save_meal_plan(&mut repo, USER, Some("2030-01-02"), &[vec![recipe(11, None)]], OverwritePolicy::Fail, "create").unwrap();
+
+        assert_eq!(slots(&repo, date(2)), vec![(11, 1)]);
+    }
+
+    #[test]
+    fn a_second_active_plan_for_a_day_is_a_conflict() {
+        let mut repo = repo();
+        repo.create_meal_plan(USER, "Meal Plan", date(1)).unwrap();
+
+        // What a request that lost the race for the day sees from the unique index
+        let err = repo.create_meal_plan(USER, "Meal Plan", date(1)).unwrap_err();
+
+        assert!(matches!(meal_plan_write_error(err), AppError::Conflict(..)));
+        assert!(matches!(meal_plan_write_error(DieselError::NotFound), AppError::Internal(_)));
+    }
+
+    #[test]
+    fn templates_keep_meal_times_and_slot_the_rest_by_position() {
+        let mut repo = repo();
+        let mealplans = vec![vec![recipe(10, Some(3)), recipe(11, None)], vec![], vec![recipe(12, None)]];
+
+        let template_id = create_template(&mut repo, "Week", Some("dietitian@x.io"), &mealplans).unwrap();
+
+        let days = repo.template_days(template_id).unwrap().unwrap();
+        assert_eq!(days, vec![vec![recipe(11, Some(2)), recipe(10, Some(3))], vec![], vec![recipe(12, Some(1))]]);
+        assert_eq!(repo.list_templates().unwrap()[0].mealplans, days);
+    }
+
+    #[test]
+    fn templates_longer_than_the_maximum_are_rejected() {
+        let mut repo = repo();
+        let mealplans = vec![vec![recipe(10, None)]; MAX_PLAN_DAYS as usize + 1];
+
+        let result = create_template(&mut repo, "Too long", None, &mealplans);
+
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert!(repo.templates.is_empty());
+    }
+
+    #[test]
+    fn applying_a_template_copies_its_days() {
+        let mut repo = repo();
+        let template_id =
+            create_template(&mut repo, "Two days", None, &[vec![recipe(10, Some(2))], vec![recipe(11, Some(4))]]).unwrap();
+
+        let days = apply_template(&mut repo, USER, template_id, date(5), OverwritePolicy::Fail).unwrap();
+
+        assert_eq!(days, 2);
+        assert_eq!(slots(&repo, date(5)), vec![(10, 2)]);
+        assert_eq!(slots(&repo, date(6)), vec![(11, 4)]);
+    }
+
+    #[test]
+    fn applying_a_missing_template_is_not_found() {
+        let mut repo = repo();
+
+        let result = apply_template(&mut repo, USER, 99, date(5), OverwritePolicy::Append);
+
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn user_days_keep_offsets_and_order_by_meal_time() {
+        let mut repo = repo();
+        repo.recipes = [10, 11, 12].into_iter().map(catalog_recipe).collect();
+        save_meal_plan(&mut repo, USER, Some("2030-01-01"), &[vec![recipe(10, Some(3)), recipe(11, Some(1))]], OverwritePolicy::Append, "create")
+            .unwrap();
+        save_meal_plan(&mut repo, USER, Some("2030-01-03"), &[vec![recipe(12, None)]], OverwritePolicy::Append, "create").unwrap();
+
+        let days = load_user_days(&mut repo, USER, date(1), 4).unwrap();
+
+        assert_eq!(days, vec![vec![recipe(11, Some(1)), recipe(10, Some(3))], vec![], vec![recipe(12, Some(1))], vec![]]);
+        assert!(matches!(load_user_days(&mut repo, USER, date(10), 3), Err(AppError::NotFound(_))));
+    }
+}